@@ -0,0 +1,141 @@
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use colour::{green, red, yellow};
+
+use crate::cli::OnCorrupted;
+
+/// Apply the configured action to corrupted archives at the end of a run
+pub(crate) fn handle_corrupted(
+    files: &[PathBuf],
+    action: OnCorrupted,
+    quarantine_dir: Option<&Path>,
+) {
+    if files.is_empty() {
+        return;
+    }
+
+    match action {
+        OnCorrupted::Ask => {
+            // Only offer deletion interactively, so scripts and CI never block here
+            if io::stdin().is_terminal() && confirm_deletion() {
+                files.iter().for_each(|file| delete(file));
+            }
+        }
+        OnCorrupted::Keep => {}
+        OnCorrupted::Delete => files.iter().for_each(|file| delete(file)),
+        OnCorrupted::Quarantine => {
+            let dir = quarantine_dir.expect("clap requires --quarantine-dir");
+
+            files.iter().for_each(|file| quarantine(file, dir));
+        }
+    }
+}
+
+/// Apply the configured action to one corrupted archive, as soon as it is found.
+///
+/// There is nobody to ask while watching, so `ask` keeps the file.
+pub(crate) fn handle_corrupted_now(
+    file: &Path,
+    action: OnCorrupted,
+    quarantine_dir: Option<&Path>,
+) {
+    match action {
+        OnCorrupted::Ask | OnCorrupted::Keep => {}
+        OnCorrupted::Delete => delete(file),
+        OnCorrupted::Quarantine => quarantine(
+            file,
+            quarantine_dir.expect("clap requires --quarantine-dir"),
+        ),
+    }
+}
+
+fn confirm_deletion() -> bool {
+    println!();
+
+    yellow!("Do you want to delete all corrupted zip files? (y/N): ");
+
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+
+    input.trim().eq_ignore_ascii_case("y")
+}
+
+fn delete(file: &Path) {
+    match fs::remove_file(file) {
+        Ok(_) => {
+            green!("🗑️ Deleted corrupted file: {}\n", file.display());
+        }
+        Err(e) => {
+            red!("❌ Failed to delete file {}: {}\n", file.display(), e);
+        }
+    }
+}
+
+fn quarantine(file: &Path, dir: &Path) {
+    match move_into(file, dir) {
+        Ok(target) => {
+            green!(
+                "📦 Moved corrupted file: {} -> {}\n",
+                file.display(),
+                target.display()
+            );
+        }
+        Err(e) => {
+            red!("❌ Failed to move file {}: {}\n", file.display(), e);
+        }
+    }
+}
+
+/// Move `file` into `dir` without overwriting anything already there
+fn move_into(file: &Path, dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let name = file
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+
+    let mut target = dir.join(name);
+    let mut n = 1;
+
+    while target.exists() {
+        let mut numbered = name.to_os_string();
+        numbered.push(format!(".{}", n));
+        target = dir.join(numbered);
+        n += 1;
+    }
+
+    // Renaming fails across filesystems, so fall back to copy and delete
+    if fs::rename(file, &target).is_err() {
+        fs::copy(file, &target)?;
+        fs::remove_file(file)?;
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_move_into_keeps_existing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let quarantine_dir = temp_dir.path().join("quarantine");
+
+        let file = temp_dir.path().join("broken.zip");
+        fs::write(&file, b"first").unwrap();
+        let first = move_into(&file, &quarantine_dir).unwrap();
+
+        fs::write(&file, b"second").unwrap();
+        let second = move_into(&file, &quarantine_dir).unwrap();
+
+        assert!(!file.exists());
+        assert_eq!(first, quarantine_dir.join("broken.zip"));
+        assert_eq!(second, quarantine_dir.join("broken.zip.1"));
+        assert_eq!(fs::read(second).unwrap(), b"second");
+    }
+}
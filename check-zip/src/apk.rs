@@ -0,0 +1,633 @@
+//! Checks of Android packages beyond the ZIP structure: the APK Signing Block
+//! between the entries and the central directory, the content digests its
+//! v2, v3 and v3.1 signatures hold over the rest of the file, and the binary
+//! `AndroidManifest.xml`.
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use sha2::{Digest, Sha256, Sha512};
+use zip::ZipArchive;
+
+use crate::error::{CheckError, ErrorKind};
+use crate::format::{END_OF_CENTRAL_DIRECTORY_SIGNATURE, EndOfCentralDirectory};
+
+/// Extensions of Android packages, checked as such even without a manifest
+pub(crate) const APK_EXTENSIONS: [&str; 1] = ["apk"];
+
+pub(crate) const ANDROID_MANIFEST: &str = "AndroidManifest.xml";
+
+/// Root entries only an APK has. Android libraries (`.aar`) carry a manifest
+/// too, but in plain text and with neither of these.
+const APK_ONLY_ENTRIES: [&str; 2] = ["classes.dex", "resources.arsc"];
+
+/// Type of the `ResXMLTree` chunk a binary XML file starts with
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_HEADER_SIZE: u16 = 8;
+
+const SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+/// Size field and magic at the end of the signing block
+const SIGNING_BLOCK_FOOTER_SIZE: u64 = 24;
+
+const SIGNATURE_SCHEME_V2_BLOCK_ID: u32 = 0x7109_871A;
+const SIGNATURE_SCHEME_V3_BLOCK_ID: u32 = 0xF053_68C0;
+const SIGNATURE_SCHEME_V31_BLOCK_ID: u32 = 0x1B93_AD61;
+
+/// Signature schemes stored in the signing block, by block ID
+const SIGNATURE_SCHEMES: [(u32, &str); 3] = [
+    (SIGNATURE_SCHEME_V2_BLOCK_ID, "v2"),
+    (SIGNATURE_SCHEME_V3_BLOCK_ID, "v3"),
+    (SIGNATURE_SCHEME_V31_BLOCK_ID, "v3.1"),
+];
+
+/// Size of the chunks content digests are computed over
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// End of central directory record without its comment
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+/// Offset of the central directory offset in the end of central directory record
+const CENTRAL_DIRECTORY_OFFSET_FIELD: usize = 16;
+
+/// Digest over the chunks of the protected regions of an APK
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContentDigest {
+    ChunkedSha256,
+    ChunkedSha512,
+}
+
+impl ContentDigest {
+    /// Content digest of a signature algorithm ID; verity digests aren't computed
+    fn of_algorithm(id: u32) -> Option<Self> {
+        match id {
+            // RSASSA-PSS, RSASSA-PKCS1-v1_5, ECDSA and DSA with SHA2-256
+            0x0101 | 0x0103 | 0x0201 | 0x0301 => Some(ContentDigest::ChunkedSha256),
+            // RSASSA-PSS, RSASSA-PKCS1-v1_5 and ECDSA with SHA2-512
+            0x0102 | 0x0104 | 0x0202 => Some(ContentDigest::ChunkedSha512),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ContentDigest::ChunkedSha256 => "SHA-256",
+            ContentDigest::ChunkedSha512 => "SHA-512",
+        }
+    }
+
+    fn hash(&self, parts: &[&[u8]]) -> Vec<u8> {
+        match self {
+            ContentDigest::ChunkedSha256 => hash_parts(Sha256::new(), parts),
+            ContentDigest::ChunkedSha512 => hash_parts(Sha512::new(), parts),
+        }
+    }
+}
+
+fn hash_parts<D: Digest>(mut hasher: D, parts: &[&[u8]]) -> Vec<u8> {
+    for part in parts {
+        hasher.update(part);
+    }
+
+    hasher.finalize().to_vec()
+}
+
+/// Signature schemes of an APK that passed the checks
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ApkSignatures {
+    pub schemes: Vec<&'static str>,
+}
+
+impl ApkSignatures {
+    /// Summary such as `APK signature schemes: v1, v2, v3`
+    pub(crate) fn describe(&self) -> String {
+        if self.schemes.is_empty() {
+            String::from("unsigned APK")
+        } else {
+            format!("APK signature schemes: {}", self.schemes.join(", "))
+        }
+    }
+}
+
+/// Whether an archive is an Android package, by its extension or its
+/// compiled code and resources
+pub(crate) fn is_apk<R: Read + Seek>(path: &Path, archive: &ZipArchive<R>) -> bool {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    APK_EXTENSIONS.contains(&extension.as_str())
+        || APK_ONLY_ENTRIES
+            .iter()
+            .any(|name| archive.index_for_name(name).is_some())
+}
+
+/// Check that the APK has an `AndroidManifest.xml` in binary XML
+pub(crate) fn check_android_manifest<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<(), CheckError> {
+    let mut data = vec![];
+
+    archive
+        .by_name(ANDROID_MANIFEST)
+        .map_err(|_| bad_manifest(format!("APK has no {}", ANDROID_MANIFEST)))?
+        .read_to_end(&mut data)
+        .map_err(|e| {
+            CheckError::new(
+                ErrorKind::of_io(&e),
+                format!("Cannot read {}: {}", ANDROID_MANIFEST, e),
+            )
+        })?;
+
+    if let Some(problem) = binary_xml_problem(&data) {
+        return Err(bad_manifest(format!("{} {}", ANDROID_MANIFEST, problem)));
+    }
+
+    Ok(())
+}
+
+fn bad_manifest(message: String) -> CheckError {
+    CheckError::new(ErrorKind::BadAndroidManifest, message)
+}
+
+/// Problem with the chunk header of a binary XML file, if it has one
+fn binary_xml_problem(data: &[u8]) -> Option<String> {
+    let [a, b, c, d, e, f, g, h, ..] = *data else {
+        return Some(format!("is {} bytes, too short for binary XML", data.len()));
+    };
+
+    let chunk_type = u16::from_le_bytes([a, b]);
+    let header_size = u16::from_le_bytes([c, d]);
+    let size = u32::from_le_bytes([e, f, g, h]) as usize;
+
+    if data.trim_ascii_start().starts_with(b"<") {
+        Some(String::from("is plain text XML instead of binary XML"))
+    } else if chunk_type != RES_XML_TYPE || header_size != RES_XML_HEADER_SIZE {
+        Some(format!(
+            "starts with chunk type 0x{:04x} and header size {} instead of binary XML",
+            chunk_type, header_size
+        ))
+    } else if size != data.len() {
+        Some(format!("says it is {} bytes but is {}", size, data.len()))
+    } else {
+        None
+    }
+}
+
+fn bad_block(message: String) -> CheckError {
+    CheckError::new(ErrorKind::BadSigningBlock, message)
+}
+
+fn read_error(e: io::Error) -> CheckError {
+    CheckError::new(
+        ErrorKind::of_io(&e),
+        format!("Cannot read APK Signing Block: {}", e),
+    )
+}
+
+/// Take a little-endian `u32` off the front of `data`
+fn take_u32(data: &mut &[u8]) -> Result<u32, String> {
+    let Some((value, rest)) = data.split_first_chunk::<4>() else {
+        return Err(String::from("ends in the middle of a field"));
+    };
+
+    *data = rest;
+    Ok(u32::from_le_bytes(*value))
+}
+
+/// Take a `u32` length-prefixed field off the front of `data`
+fn take_prefixed<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], String> {
+    let length = take_u32(data)? as usize;
+
+    if length > data.len() {
+        return Err(format!(
+            "has a field of {} bytes with only {} left",
+            length,
+            data.len()
+        ));
+    }
+
+    let (value, rest) = data.split_at(length);
+    *data = rest;
+    Ok(value)
+}
+
+/// `(signature algorithm ID, digest)` of every signer of a v2, v3 or v3.1
+/// signature block, whose signed data all start with the digests
+fn signer_digests(mut block: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, String> {
+    let mut signers = take_prefixed(&mut block)?;
+    let mut digests = vec![];
+
+    if signers.is_empty() {
+        return Err(String::from("has no signers"));
+    }
+
+    while !signers.is_empty() {
+        let mut signer = take_prefixed(&mut signers)?;
+        let mut signed_data = take_prefixed(&mut signer)?;
+        let mut signer_digests = take_prefixed(&mut signed_data)?;
+
+        if signer_digests.is_empty() {
+            return Err(String::from("has a signer without digests"));
+        }
+
+        while !signer_digests.is_empty() {
+            let mut entry = take_prefixed(&mut signer_digests)?;
+            let algorithm = take_u32(&mut entry)?;
+            let digest = take_prefixed(&mut entry)?;
+
+            digests.push((algorithm, digest.to_vec()));
+        }
+    }
+
+    Ok(digests)
+}
+
+/// Offset of the end of central directory record and the record itself,
+/// with its comment
+fn find_end_of_central_directory<R: Read + Seek>(
+    reader: &mut R,
+) -> io::Result<Option<(u64, Vec<u8>)>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_start = len.saturating_sub((END_OF_CENTRAL_DIRECTORY_SIZE + 0xFFFF) as u64);
+
+    reader.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![];
+    reader.read_to_end(&mut tail)?;
+
+    let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+
+    Ok(tail
+        .windows(4)
+        .rposition(|w| w == signature)
+        .map(|position| (tail_start + position as u64, tail[position..].to_vec())))
+}
+
+/// Check the APK Signing Block and the content digests of its signatures,
+/// and return the schemes it holds
+pub(crate) fn check_signing_block<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<Vec<&'static str>, CheckError> {
+    let Some((end_start, end_record)) =
+        find_end_of_central_directory(reader).map_err(read_error)?
+    else {
+        return Ok(vec![]);
+    };
+
+    let end = EndOfCentralDirectory::read(&mut &end_record[4..]).map_err(read_error)?;
+    let central_directory_start = end.central_directory_offset;
+
+    // Zip64 APKs are not installable, and have no signing block to look for
+    if central_directory_start == u32::MAX as u64
+        || central_directory_start < SIGNING_BLOCK_FOOTER_SIZE + 8
+    {
+        return Ok(vec![]);
+    }
+
+    reader
+        .seek(SeekFrom::Start(
+            central_directory_start - SIGNING_BLOCK_FOOTER_SIZE,
+        ))
+        .map_err(read_error)?;
+
+    let mut footer = [0; SIGNING_BLOCK_FOOTER_SIZE as usize];
+    reader.read_exact(&mut footer).map_err(read_error)?;
+
+    if &footer[8..] != SIGNING_BLOCK_MAGIC {
+        return Ok(vec![]);
+    }
+
+    let size = u64::from_le_bytes(footer[..8].try_into().unwrap());
+
+    // The size counts everything but the leading size field itself
+    if size < SIGNING_BLOCK_FOOTER_SIZE || size > central_directory_start - 8 {
+        return Err(bad_block(format!(
+            "APK Signing Block size {} doesn't fit before the central directory at {}",
+            size, central_directory_start
+        )));
+    }
+
+    let block_start = central_directory_start - size - 8;
+    reader
+        .seek(SeekFrom::Start(block_start))
+        .map_err(read_error)?;
+
+    let mut block = vec![];
+    reader
+        .take(size + 8)
+        .read_to_end(&mut block)
+        .map_err(read_error)?;
+
+    let leading_size = u64::from_le_bytes(block[..8].try_into().unwrap());
+
+    if leading_size != size {
+        return Err(bad_block(format!(
+            "APK Signing Block sizes differ: {} at the start, {} at the end",
+            leading_size, size
+        )));
+    }
+
+    let mut pairs = &block[8..block.len() - SIGNING_BLOCK_FOOTER_SIZE as usize];
+    let mut schemes = vec![];
+    let mut expected = vec![];
+
+    while !pairs.is_empty() {
+        let Some((length, rest)) = pairs.split_first_chunk::<8>() else {
+            return Err(bad_block(String::from(
+                "APK Signing Block ends in the middle of a pair length",
+            )));
+        };
+        let length = u64::from_le_bytes(*length);
+
+        if length < 4 || length > rest.len() as u64 {
+            return Err(bad_block(format!(
+                "APK Signing Block has a pair of {} bytes with {} left",
+                length,
+                rest.len()
+            )));
+        }
+
+        let (pair, rest) = rest.split_at(length as usize);
+        let id = u32::from_le_bytes(pair[..4].try_into().unwrap());
+        pairs = rest;
+
+        let Some((_, scheme)) = SIGNATURE_SCHEMES
+            .iter()
+            .find(|(scheme_id, _)| *scheme_id == id)
+        else {
+            continue;
+        };
+
+        let digests = signer_digests(&pair[4..])
+            .map_err(|e| bad_block(format!("APK Signature Scheme {} block {}", scheme, e)))?;
+
+        schemes.push(*scheme);
+        expected.extend(digests.into_iter().filter_map(|(algorithm, digest)| {
+            ContentDigest::of_algorithm(algorithm).map(|kind| (*scheme, kind, digest))
+        }));
+    }
+
+    // The end record as signed points at the signing block instead of the
+    // central directory
+    let mut signed_end = end_record;
+    signed_end[CENTRAL_DIRECTORY_OFFSET_FIELD..CENTRAL_DIRECTORY_OFFSET_FIELD + 4]
+        .copy_from_slice(&(block_start as u32).to_le_bytes());
+
+    let mut kinds: Vec<ContentDigest> = expected.iter().map(|(_, kind, _)| *kind).collect();
+    kinds.sort_by_key(|kind| kind.name());
+    kinds.dedup();
+
+    for kind in kinds {
+        let actual = content_digest(
+            reader,
+            kind,
+            &[(0, block_start), (central_directory_start, end_start)],
+            &signed_end,
+        )
+        .map_err(read_error)?;
+
+        for (scheme, _, digest) in expected.iter().filter(|(_, k, _)| *k == kind) {
+            if *digest != actual {
+                return Err(CheckError::new(
+                    ErrorKind::DigestMismatch,
+                    format!(
+                        "APK Signature Scheme {} {} content digest doesn't match",
+                        scheme,
+                        kind.name()
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(schemes)
+}
+
+/// Chunked digest of the file `ranges` followed by `tail`, each split into
+/// chunks of their own
+fn content_digest<R: Read + Seek>(
+    reader: &mut R,
+    kind: ContentDigest,
+    ranges: &[(u64, u64)],
+    tail: &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut chunk_digests = vec![];
+    let mut count: u32 = 0;
+    let mut chunk = vec![];
+
+    let mut add_chunk = |chunk: &[u8]| {
+        chunk_digests.extend(kind.hash(&[&[0xA5], &(chunk.len() as u32).to_le_bytes(), chunk]));
+        count += 1;
+    };
+
+    for &(start, end) in ranges {
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut remaining = end - start;
+
+        while remaining > 0 {
+            chunk.clear();
+            reader
+                .by_ref()
+                .take(remaining.min(CHUNK_SIZE))
+                .read_to_end(&mut chunk)?;
+
+            if chunk.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            remaining -= chunk.len() as u64;
+            add_chunk(&chunk);
+        }
+    }
+
+    for part in tail.chunks(CHUNK_SIZE as usize) {
+        add_chunk(part);
+    }
+
+    Ok(kind.hash(&[&[0x5A], &count.to_le_bytes(), &chunk_digests]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    /// Binary XML holding nothing but its chunk header
+    const BINARY_XML: &[u8] = b"\x03\x00\x08\x00\x08\x00\x00\x00";
+
+    fn archive(names: &[&str]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for name in names {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        }
+
+        ZipArchive::new(Cursor::new(zip.finish().unwrap().into_inner())).unwrap()
+    }
+
+    #[test]
+    fn test_is_apk() {
+        let library = archive(&["AndroidManifest.xml", "classes.jar", "R.txt"]);
+        let app = archive(&["AndroidManifest.xml", "classes.dex"]);
+
+        assert!(!is_apk(Path::new("lib.aar"), &library));
+        assert!(!is_apk(Path::new("lib.zip"), &library));
+        assert!(is_apk(Path::new("app.APK"), &library));
+        assert!(is_apk(Path::new("app.zip"), &app));
+        assert!(is_apk(Path::new("app.zip"), &archive(&["resources.arsc"])));
+    }
+
+    fn prefixed(data: &[u8]) -> Vec<u8> {
+        let mut field = (data.len() as u32).to_le_bytes().to_vec();
+        field.extend_from_slice(data);
+        field
+    }
+
+    /// A v2 signature block with one signer and one SHA-256 digest
+    fn v2_block(digest: &[u8]) -> Vec<u8> {
+        let mut entry = 0x0103u32.to_le_bytes().to_vec();
+        entry.extend(prefixed(digest));
+
+        let digests = prefixed(&prefixed(&entry));
+        let mut signed_data = digests;
+        signed_data.extend(prefixed(&[]));
+        signed_data.extend(prefixed(&[]));
+
+        let mut signer = prefixed(&signed_data);
+        signer.extend(prefixed(&[]));
+        signer.extend(prefixed(&[]));
+
+        prefixed(&prefixed(&signer))
+    }
+
+    /// A signing block holding a v2 block and its ID
+    fn signing_block(v2: &[u8]) -> Vec<u8> {
+        let mut pair = SIGNATURE_SCHEME_V2_BLOCK_ID.to_le_bytes().to_vec();
+        pair.extend_from_slice(v2);
+
+        let size = (8 + pair.len() + 24) as u64;
+        let mut block = size.to_le_bytes().to_vec();
+        block.extend((pair.len() as u64).to_le_bytes());
+        block.extend(pair);
+        block.extend(size.to_le_bytes());
+        block.extend(SIGNING_BLOCK_MAGIC);
+        block
+    }
+
+    /// An APK with a v2 signature over its contents, given the digest to store
+    fn apk(digest: impl Fn(&[u8], u64, &[u8]) -> Vec<u8>) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file(ANDROID_MANIFEST, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(BINARY_XML).unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        let central_directory_start = u32::from_le_bytes(
+            archive[archive.len() - 6..archive.len() - 2]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let (entries, rest) = archive.split_at(central_directory_start);
+
+        // The block size doesn't depend on the digest, so it is known up front
+        let block_len = signing_block(&v2_block(&[0; 32])).len();
+        let block_start = entries.len() as u64;
+        let block = signing_block(&v2_block(&digest(entries, block_start, rest)));
+        assert_eq!(block.len(), block_len);
+
+        let mut apk = entries.to_vec();
+        apk.extend(&block);
+        apk.extend(rest);
+
+        let end = apk.len() - 22;
+        let moved = (central_directory_start + block.len()) as u32;
+        apk[end + 16..end + 20].copy_from_slice(&moved.to_le_bytes());
+        apk
+    }
+
+    /// The v2 content digest, computed the long way round
+    fn v2_digest(entries: &[u8], block_start: u64, rest: &[u8]) -> Vec<u8> {
+        let chunk = |data: &[u8]| {
+            let mut hasher = Sha256::new();
+            hasher.update([0xA5]);
+            hasher.update((data.len() as u32).to_le_bytes());
+            hasher.update(data);
+            hasher.finalize().to_vec()
+        };
+
+        let end_start = rest.len() - 22;
+        let mut end = rest[end_start..].to_vec();
+        end[16..20].copy_from_slice(&(block_start as u32).to_le_bytes());
+
+        let mut hasher = Sha256::new();
+        hasher.update([0x5A]);
+        hasher.update(3u32.to_le_bytes());
+        hasher.update(chunk(entries));
+        hasher.update(chunk(&rest[..end_start]));
+        hasher.update(chunk(&end));
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    fn test_binary_xml_problem() {
+        assert_eq!(binary_xml_problem(BINARY_XML), None);
+        assert!(binary_xml_problem(b"<?xml version=\"1.0\"?><manifest/>").is_some());
+        assert!(binary_xml_problem(b"\x03\x00\x08\x00\x09\x00\x00\x00").is_some());
+        assert!(binary_xml_problem(b"\x03\x00").is_some());
+    }
+
+    #[test]
+    fn test_check_signing_block() {
+        let signed = apk(v2_digest);
+        assert_eq!(
+            check_signing_block(&mut Cursor::new(&signed)).unwrap(),
+            vec!["v2"]
+        );
+
+        let wrong = apk(|_, _, _| vec![0; 32]);
+        assert_eq!(
+            check_signing_block(&mut Cursor::new(&wrong))
+                .unwrap_err()
+                .kind,
+            ErrorKind::DigestMismatch
+        );
+
+        // The size at the start of the block disagrees with the one at its end
+        let mut sizes = signed.clone();
+        let block_start = sizes
+            .windows(16)
+            .position(|w| w == SIGNING_BLOCK_MAGIC)
+            .unwrap()
+            + 16
+            - signing_block(&v2_block(&[0; 32])).len();
+        sizes[block_start] ^= 1;
+        assert_eq!(
+            check_signing_block(&mut Cursor::new(&sizes))
+                .unwrap_err()
+                .kind,
+            ErrorKind::BadSigningBlock
+        );
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("a.txt", SimpleFileOptions::default())
+            .unwrap();
+        let unsigned = zip.finish().unwrap().into_inner();
+        assert!(
+            check_signing_block(&mut Cursor::new(&unsigned))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_signer_digests() {
+        assert_eq!(
+            signer_digests(&v2_block(&[7; 32])).unwrap(),
+            vec![(0x0103, vec![7; 32])]
+        );
+        assert!(signer_digests(&prefixed(&[])).is_err());
+        assert!(signer_digests(&[9, 0, 0, 0, 1]).is_err());
+    }
+}
@@ -1,18 +1,227 @@
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::info::InfoArgs;
+use crate::lint::LintArgs;
+use crate::log_file::parse_log_template;
+use crate::names::NameEncoding;
+use crate::repack::RepackArgs;
+use crate::search::{FindArgs, GrepArgs};
+use crate::throttle::{parse_bandwidth, parse_size, parse_timeout};
+
+/// Findings that can make a run fail
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum FailOn {
+    /// Damaged archives
+    Corrupted,
+    /// Password protected archives
+    Encrypted,
+    /// Archives with entries encrypted with weak ZipCrypto
+    WeakCrypto,
+    /// Archives that could not be fully verified
+    Suspicious,
+    /// Archives still being downloaded or written
+    Incomplete,
+    /// Readable Java archives with bad manifests, signatures or class files
+    PolicyViolation,
+}
+
+/// Text format of the metrics file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum MetricsFormat {
+    /// Prometheus text format, for node_exporter's textfile collector
+    Prometheus,
+    /// OpenMetrics text format
+    #[value(name = "openmetrics")]
+    OpenMetrics,
+}
+
+/// What to do with corrupted archives
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OnCorrupted {
+    /// Offer to delete them at the end of an interactive run
+    Ask,
+    /// Leave them in place
+    Keep,
+    /// Delete them
+    Delete,
+    /// Move them into --quarantine-dir
+    Quarantine,
+}
+
+/// What to do with duplicate archives found by --find-duplicates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OnDuplicate {
+    /// Only report them
+    Keep,
+    /// Replace identical copies with hardlinks to the archive kept
+    Hardlink,
+    /// Delete identical copies of the archive kept
+    Delete,
+    /// Delete identical copies and archives that only hold the same entries,
+    /// though their timestamps or compression differ
+    DeleteSameContents,
+}
+
+/// Tools besides the integrity check
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// List archive entries whose path matches a glob or regular expression
+    Find(FindArgs),
+    /// Search the contents of archive entries for a regular expression
+    Grep(GrepArgs),
+    /// Show who made archives and when: comment, timestamps, creator, permissions, extra fields
+    Info(InfoArgs),
+    /// Flag invalid, out-of-range or inconsistent timestamps, sizes that contradict Zip64 and undecodable names
+    Lint(LintArgs),
+    /// Rewrite valid archives with another compression, sorted, decrypted or without
+    /// extra fields and timestamps, verifying every entry before replacing them
+    Repack(RepackArgs),
+}
 
 #[derive(Parser)]
 #[command(
     name = "check-zip",
     about = "A tool for checking integrity of zip archives"
 )]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 pub(crate) struct Cli {
-    /// Folder to operate on
-    pub path: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Files and folders to operate on, or `-` to read a ZIP stream from stdin
+    /// [default: current directory]
+    pub paths: Vec<PathBuf>,
+
+    /// Validate a ZIP archive streamed on stdin
+    #[arg(long, conflicts_with_all = ["paths", "files_from"])]
+    pub stdin: bool,
+
+    /// Read more files and folders from FILE, one per line, or `-` for stdin
+    #[arg(long, value_name = "FILE")]
+    pub files_from: Option<PathBuf>,
+
+    /// Separate the entries read by --files-from with NUL instead of newlines
+    #[arg(short = '0', long = "null", requires = "files_from")]
+    pub null: bool,
 
-    // Log file to write results to
-    #[arg(short, long, value_name = "LOG_FILE")]
+    /// Log file or folder to stream results to; strftime specifiers such as %Y-%m-%d in the file name are expanded
+    #[arg(short, long, value_name = "LOG_FILE", value_parser = parse_log_template)]
     pub log: Option<PathBuf>,
+
+    /// Append to the log file instead of replacing it
+    #[arg(long, requires = "log")]
+    pub log_append: bool,
+
+    /// Rotate the log file before it grows past this size, e.g. 10M
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "log")]
+    pub log_max_size: Option<u64>,
+
+    /// Number of rotated log files to keep
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "log_max_size")]
+    pub log_max_files: usize,
+
+    /// Comma-separated findings that cause a non-zero exit code
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "corrupted,policy-violation",
+        value_name = "FINDINGS"
+    )]
+    pub fail_on: Vec<FailOn>,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N", conflicts_with = "per_device")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Maximum number of workers reading from disk at the same time
+    #[arg(long, value_name = "N")]
+    pub io_jobs: Option<NonZeroUsize>,
+
+    /// Limit the total read bandwidth, e.g. 500K, 20M or 1.5GiB per second
+    #[arg(long, value_name = "RATE", value_parser = parse_bandwidth)]
+    pub max_bandwidth: Option<u64>,
+
+    /// Give up on an archive after this long, e.g. 90, 30s or 5m
+    #[arg(long, value_name = "DURATION", value_parser = parse_timeout)]
+    pub timeout_per_file: Option<Duration>,
+
+    /// Check every entry of a corrupted archive and list all failing ones,
+    /// instead of stopping at the first
+    #[arg(short = 'v', long = "verbose", visible_alias = "all-errors")]
+    pub all_errors: bool,
+
+    /// Encoding of entry names without the UTF-8 flag, also for subcommands
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENCODING",
+        default_value = "auto",
+        global = true
+    )]
+    pub name_encoding: NameEncoding,
+
+    /// Run one worker per disk, so parallel mounts don't thrash a single disk
+    #[arg(long)]
+    pub per_device: bool,
+
+    /// What to do with corrupted archives
+    #[arg(long, value_enum, value_name = "ACTION", default_value = "ask")]
+    pub on_corrupted: OnCorrupted,
+
+    /// Folder corrupted archives are moved to by `--on-corrupted quarantine`
+    #[arg(long, value_name = "DIR", required_if_eq("on_corrupted", "quarantine"))]
+    pub quarantine_dir: Option<PathBuf>,
+
+    /// Report archives that are identical, or hold the same entries, and the space they waste
+    #[arg(long, conflicts_with_all = ["stdin", "watch"])]
+    pub find_duplicates: bool,
+
+    /// What to do with the duplicates found by --find-duplicates
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value = "keep",
+        requires = "find_duplicates"
+    )]
+    pub on_duplicate: OnDuplicate,
+
+    /// Keep watching the folders and check archives as they are written
+    #[arg(long, conflicts_with_all = ["stdin", "files_from"])]
+    pub watch: bool,
+
+    /// Seconds a file's size must stay unchanged before it is checked in watch mode
+    #[arg(long, value_name = "SECS", default_value_t = 2, requires = "watch")]
+    pub settle: u64,
+
+    /// Write a self-contained HTML report of the run to this file
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    pub html: Option<PathBuf>,
+
+    /// Write a JSON report of the run to this file, usable as a later --baseline
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    pub json: Option<PathBuf>,
+
+    /// Compare the run with a previous JSON report and fail on newly corrupted archives
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    pub baseline: Option<PathBuf>,
+
+    /// Atomically write run metrics to this file, e.g. for node_exporter's textfile collector
+    #[arg(long, value_name = "PATH")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Text format of the metrics file
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "prometheus",
+        requires = "metrics_file"
+    )]
+    pub metrics_format: MetricsFormat,
 }
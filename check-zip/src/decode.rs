@@ -0,0 +1,219 @@
+//! Decoders for the compression methods of ZIP entries, for entries
+//! `ZipArchive` can't read and for archives read as a stream.
+
+use std::io::{self, BufRead, Read, Write};
+
+use bzip2::bufread::BzDecoder;
+use crc32fast::Hasher;
+use deflate64::Deflate64Decoder;
+use flate2::bufread::DeflateDecoder;
+use lzma_rs::decompress::{Options, UnpackedSize};
+use ppmd_rust::Ppmd8Decoder;
+use xz2::bufread::XzDecoder;
+
+use crate::format::{
+    FLAG_LZMA_END_MARKER, METHOD_BZIP2, METHOD_DEFLATE64, METHOD_DEFLATED, METHOD_LZMA,
+    METHOD_PPMD, METHOD_STORED, METHOD_XZ, METHOD_ZSTD, METHOD_ZSTD_DEPRECATED,
+};
+
+/// Writer computing the CRC-32 and length of everything written to it
+#[derive(Default)]
+pub(crate) struct Checksum {
+    pub hasher: Hasher,
+    pub len: u64,
+}
+
+impl Write for Checksum {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A compression method that can be decoded here
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Decoder {
+    Stored,
+    Deflated,
+    Deflate64,
+    Bzip2,
+    Lzma,
+    Zstd,
+    Xz,
+    Ppmd,
+}
+
+impl Decoder {
+    /// Pick the decoder of an entry, or `None` if its method is not supported.
+    ///
+    /// Without the uncompressed size, only methods whose data marks its own
+    /// end can be decoded.
+    pub(crate) fn for_method(
+        method: u16,
+        flags: u16,
+        uncompressed_size: Option<u64>,
+    ) -> Option<Self> {
+        let size_known = uncompressed_size.is_some();
+
+        match method {
+            METHOD_STORED if size_known => Some(Decoder::Stored),
+            METHOD_DEFLATED => Some(Decoder::Deflated),
+            METHOD_DEFLATE64 => Some(Decoder::Deflate64),
+            METHOD_BZIP2 => Some(Decoder::Bzip2),
+            METHOD_LZMA if size_known || flags & FLAG_LZMA_END_MARKER != 0 => Some(Decoder::Lzma),
+            METHOD_ZSTD | METHOD_ZSTD_DEPRECATED => Some(Decoder::Zstd),
+            METHOD_XZ => Some(Decoder::Xz),
+            METHOD_PPMD if size_known => Some(Decoder::Ppmd),
+            _ => None,
+        }
+    }
+
+    /// Decompress the data of one entry into `output`
+    pub(crate) fn decompress<R: BufRead, W: Write>(
+        &self,
+        mut input: R,
+        output: &mut W,
+        uncompressed_size: Option<u64>,
+    ) -> io::Result<()> {
+        match self {
+            Decoder::Stored => io::copy(&mut input, output).map(drop),
+            Decoder::Deflated => io::copy(&mut DeflateDecoder::new(input), output).map(drop),
+            Decoder::Deflate64 => {
+                io::copy(&mut Deflate64Decoder::with_buffer(input), output).map(drop)
+            }
+            Decoder::Bzip2 => io::copy(&mut BzDecoder::new(input), output).map(drop),
+            Decoder::Lzma => decompress_lzma(input, output, uncompressed_size),
+            // A single frame, so the decoder stops where the entry ends
+            Decoder::Zstd => io::copy(
+                &mut zstd::stream::read::Decoder::with_buffer(input)?.single_frame(),
+                output,
+            )
+            .map(drop),
+            Decoder::Xz => io::copy(&mut XzDecoder::new(input), output).map(drop),
+            Decoder::Ppmd => decompress_ppmd(input, output, uncompressed_size.unwrap_or(0)),
+        }
+    }
+}
+
+/// LZMA data in a ZIP entry starts with a version and the size of the
+/// properties that follow, instead of the uncompressed size of `.lzma` files
+fn decompress_lzma<R: BufRead, W: Write>(
+    mut input: R,
+    output: &mut W,
+    uncompressed_size: Option<u64>,
+) -> io::Result<()> {
+    let mut header = [0u8; 4];
+    input.read_exact(&mut header)?;
+
+    let options = Options {
+        unpacked_size: UnpackedSize::UseProvided(uncompressed_size),
+        ..Default::default()
+    };
+
+    lzma_rs::lzma_decompress_with_options(&mut input, output, &options).map_err(|e| match e {
+        lzma_rs::error::Error::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    })
+}
+
+/// PPMd data starts with the model order, memory size and restore method
+/// packed in two bytes, and has no reliable end marker
+fn decompress_ppmd<R: BufRead, W: Write>(
+    mut input: R,
+    output: &mut W,
+    uncompressed_size: u64,
+) -> io::Result<()> {
+    let mut header = [0u8; 2];
+    input.read_exact(&mut header)?;
+
+    let parameters = u16::from_le_bytes(header);
+    let order = u32::from(parameters & 0x0F) + 1;
+    let memory_size = (u32::from((parameters >> 4) & 0xFF) + 1) << 20;
+    let restore_method = parameters >> 12;
+
+    let decoder = Ppmd8Decoder::new(input, order, memory_size, restore_method.into()).map_err(
+        |e| match e {
+            ppmd_rust::Error::IoError(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        },
+    )?;
+
+    io::copy(&mut decoder.take(uncompressed_size), output).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn checksum(decoder: Decoder, data: &[u8], uncompressed_size: Option<u64>) -> (u32, u64) {
+        let mut checksum = Checksum::default();
+
+        decoder
+            .decompress(Cursor::new(data), &mut checksum, uncompressed_size)
+            .unwrap();
+
+        (checksum.hasher.finalize(), checksum.len)
+    }
+
+    #[test]
+    fn test_for_method() {
+        assert_eq!(Decoder::for_method(METHOD_STORED, 0, None), None);
+        assert_eq!(
+            Decoder::for_method(METHOD_DEFLATED, 0, None),
+            Some(Decoder::Deflated)
+        );
+        assert_eq!(Decoder::for_method(METHOD_LZMA, 0, None), None);
+        assert_eq!(
+            Decoder::for_method(METHOD_LZMA, FLAG_LZMA_END_MARKER, None),
+            Some(Decoder::Lzma)
+        );
+        assert_eq!(
+            Decoder::for_method(METHOD_PPMD, 0, Some(1)),
+            Some(Decoder::Ppmd)
+        );
+        assert_eq!(Decoder::for_method(97, 0, Some(1)), None);
+    }
+
+    #[test]
+    fn test_decompress_zstd_and_xz() {
+        let content = b"Hello, World! ".repeat(50);
+        let expected = (crc32fast::hash(&content), content.len() as u64);
+
+        let zstd = zstd::encode_all(&content[..], 3).unwrap();
+        let mut xz = vec![];
+        xz2::read::XzEncoder::new(&content[..], 6)
+            .read_to_end(&mut xz)
+            .unwrap();
+
+        assert_eq!(checksum(Decoder::Zstd, &zstd, None), expected);
+        assert_eq!(checksum(Decoder::Xz, &xz, None), expected);
+    }
+
+    #[test]
+    fn test_decompress_ppmd() {
+        let content = b"Hello, World! ".repeat(50);
+        // Order 6 and 16 MiB of memory, as 7-Zip writes them
+        let parameters: u16 = 5 | (15 << 4);
+
+        let mut encoder = ppmd_rust::Ppmd8Encoder::new(
+            parameters.to_le_bytes().to_vec(),
+            6,
+            16 << 20,
+            ppmd_rust::RestoreMethod::Restart,
+        )
+        .unwrap();
+        encoder.write_all(&content).unwrap();
+        let data = encoder.finish(false).unwrap();
+
+        assert_eq!(
+            checksum(Decoder::Ppmd, &data, Some(content.len() as u64)),
+            (crc32fast::hash(&content), content.len() as u64)
+        );
+    }
+}
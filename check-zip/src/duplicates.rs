@@ -0,0 +1,468 @@
+//! Duplicate archive detection.
+//!
+//! Identical copies are found like fdupes does: archives are grouped by size,
+//! then by a hash of their first bytes, then by a hash of the whole file.
+//! Archives that differ as files are then compared by their contents, so
+//! copies that were re-zipped with other timestamps are found too, unless
+//! their entries are encrypted or compressed in a method that can't be read.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use colour::{green, red, yellow};
+use indicatif::HumanBytes;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::cli::OnDuplicate;
+
+/// Bytes hashed to tell apart archives of the same size before hashing them fully
+const PARTIAL_HASH_SIZE: u64 = 64 * 1024;
+
+pub(crate) type Hash = [u8; 32];
+
+/// Archives that are duplicates of each other; the first one is kept
+pub(crate) struct DuplicateGroup {
+    pub archives: Vec<(PathBuf, u64)>,
+    /// Whether the archives are byte-for-byte identical, rather than only
+    /// holding the same entries
+    pub identical: bool,
+}
+
+impl DuplicateGroup {
+    /// Space freed by removing every archive but the first
+    pub(crate) fn reclaimable(&self) -> u64 {
+        self.archives.iter().skip(1).map(|(_, size)| size).sum()
+    }
+}
+
+/// Device and inode of a file, shared by all hardlinks to it
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Hash everything `reader` yields
+pub(crate) fn hash_reader<R: Read>(mut reader: R) -> io::Result<Hash> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = reader.read(&mut buf)?;
+
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+fn partial_hash(path: &Path) -> io::Result<Hash> {
+    hash_reader(File::open(path)?.take(PARTIAL_HASH_SIZE))
+}
+
+fn full_hash(path: &Path) -> io::Result<Hash> {
+    hash_reader(File::open(path)?)
+}
+
+/// Cheap identity of an archive's contents, from its central directory: the
+/// names, CRCs and sizes of its entries, in name order
+fn listing_hash(path: &Path) -> zip::result::ZipResult<Hash> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut listing = vec![];
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        listing.push((entry.name().to_string(), entry.crc32(), entry.size()));
+    }
+
+    listing.sort();
+
+    let mut hasher = Sha256::new();
+
+    for (name, crc32, size) in listing {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(crc32.to_le_bytes());
+        hasher.update(size.to_le_bytes());
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Identity of an archive's contents: the hash of every entry's name and
+/// data, in name order. Timestamps, comments and compression don't count.
+fn content_hash(path: &Path) -> zip::result::ZipResult<Hash> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut entries = vec![];
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+
+        entries.push((name, hash_reader(entry)?));
+    }
+
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+
+    for (name, hash) in entries {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(hash);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Split every group by `key`, dropping archives whose key cannot be computed
+/// and groups left with a single archive
+fn refine<K, E>(
+    groups: Vec<Vec<(PathBuf, u64)>>,
+    key: impl Fn(&Path) -> Result<K, E>,
+) -> Vec<Vec<(PathBuf, u64)>>
+where
+    K: std::hash::Hash + Eq,
+    E: std::fmt::Display,
+{
+    let mut refined = vec![];
+
+    for group in groups {
+        let mut by_key = HashMap::<K, Vec<(PathBuf, u64)>>::new();
+
+        for (path, size) in group {
+            match key(&path) {
+                Ok(key) => by_key.entry(key).or_default().push((path, size)),
+                Err(e) => red!("❌ Cannot hash {}: {}\n", path.display(), e),
+            }
+        }
+
+        refined.extend(by_key.into_values().filter(|group| group.len() > 1));
+    }
+
+    refined
+}
+
+/// Find duplicates among `archives`, given as paths and sizes. Archives in
+/// `opaque` can't have their entries read, so they are only compared as files.
+///
+/// Groups and the archives within them are sorted by path, so the archive
+/// kept is always the same one.
+pub(crate) fn find_duplicates(
+    archives: &[(PathBuf, u64)],
+    opaque: &HashSet<PathBuf>,
+) -> Vec<DuplicateGroup> {
+    // Hardlinks of the same file take no extra space, so only one of them counts
+    let mut sorted = archives.to_vec();
+    sorted.sort();
+
+    let mut seen = HashSet::new();
+    let archives: Vec<(PathBuf, u64)> = sorted
+        .into_iter()
+        .filter(|(path, _)| file_id(path).is_none_or(|id| seen.insert(id)))
+        .collect();
+
+    let mut by_size = BTreeMap::<u64, Vec<(PathBuf, u64)>>::new();
+
+    for (path, size) in &archives {
+        by_size
+            .entry(*size)
+            .or_default()
+            .push((path.clone(), *size));
+    }
+
+    let candidates = by_size
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    let candidates = refine(candidates, partial_hash);
+    let identical = refine(candidates, full_hash);
+
+    let mut groups: Vec<DuplicateGroup> = identical
+        .into_iter()
+        .map(|archives| DuplicateGroup {
+            archives,
+            identical: true,
+        })
+        .collect();
+
+    // Compare contents with one representative of every set of identical
+    // copies, and the archives that have none
+    let mut representatives: Vec<(PathBuf, u64)> = archives
+        .iter()
+        .filter(|(path, _)| {
+            !groups
+                .iter()
+                .any(|group| group.archives.iter().any(|(p, _)| p == path))
+        })
+        .cloned()
+        .collect();
+
+    for group in &mut groups {
+        group.archives.sort();
+        representatives.push(group.archives[0].clone());
+    }
+
+    representatives.retain(|(path, _)| !opaque.contains(path));
+
+    let candidates = refine(vec![representatives], listing_hash);
+    let same_contents = refine(candidates, content_hash);
+
+    groups.extend(same_contents.into_iter().map(|archives| DuplicateGroup {
+        archives,
+        identical: false,
+    }));
+
+    for group in &mut groups {
+        group.archives.sort();
+    }
+
+    groups.sort_by(|a, b| a.archives[0].cmp(&b.archives[0]));
+
+    groups
+}
+
+/// Print the duplicate groups and the space they take up, and how many
+/// archives were only compared as files
+pub(crate) fn print_duplicates(groups: &[DuplicateGroup], opaque: usize) {
+    println!("========================================================");
+
+    if opaque > 0 {
+        yellow!(
+            "🔒 {} encrypted or unsupported archives were only compared as files, not by contents\n",
+            opaque
+        );
+    }
+
+    if groups.is_empty() {
+        green!("🧬 No duplicate archives found\n");
+        return;
+    }
+
+    yellow!("🧬 Duplicate archives:\n");
+
+    for group in groups {
+        if group.identical {
+            yellow!(
+                "📦 {} identical copies of {}:\n",
+                group.archives.len(),
+                HumanBytes(group.archives[0].1)
+            );
+        } else {
+            yellow!(
+                "🧩 {} archives with the same contents, but different timestamps or compression:\n",
+                group.archives.len()
+            );
+        }
+
+        for (i, (path, size)) in group.archives.iter().enumerate() {
+            let role = if i == 0 { "keep" } else { "dup " };
+
+            println!("   {} {} ({})", role, path.display(), HumanBytes(*size));
+        }
+    }
+
+    let identical: u64 = groups
+        .iter()
+        .filter(|group| group.identical)
+        .map(DuplicateGroup::reclaimable)
+        .sum();
+    let same_contents: u64 = groups
+        .iter()
+        .filter(|group| !group.identical)
+        .map(DuplicateGroup::reclaimable)
+        .sum();
+
+    green!(
+        "♻️ Reclaimable space: {} from identical copies, {} from archives with the same contents\n",
+        HumanBytes(identical),
+        HumanBytes(same_contents)
+    );
+}
+
+/// Apply the configured action to every duplicate but the first of each group.
+///
+/// Only identical copies are hardlinked or deleted; archives that merely hold
+/// the same entries are left alone unless deleting those was asked for.
+pub(crate) fn handle_duplicates(groups: &[DuplicateGroup], action: OnDuplicate) {
+    for group in groups {
+        let (keep, _) = &group.archives[0];
+
+        for (duplicate, _) in &group.archives[1..] {
+            match action {
+                OnDuplicate::Keep => {}
+                OnDuplicate::Hardlink if group.identical => match hardlink(keep, duplicate) {
+                    Ok(_) => green!(
+                        "🔗 Hardlinked {} to {}\n",
+                        duplicate.display(),
+                        keep.display()
+                    ),
+                    Err(e) => red!("❌ Failed to hardlink {}: {}\n", duplicate.display(), e),
+                },
+                OnDuplicate::Hardlink => {}
+                OnDuplicate::Delete if !group.identical => {}
+                OnDuplicate::Delete | OnDuplicate::DeleteSameContents => {
+                    match fs::remove_file(duplicate) {
+                        Ok(_) => green!("🗑️ Deleted duplicate: {}\n", duplicate.display()),
+                        Err(e) => red!("❌ Failed to delete file {}: {}\n", duplicate.display(), e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replace `duplicate` with a hardlink to `keep`, atomically: the link is
+/// created next to it and renamed over it
+fn hardlink(keep: &Path, duplicate: &Path) -> io::Result<()> {
+    let mut tmp_name = duplicate.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.link", std::process::id()));
+
+    let tmp = duplicate.with_file_name(tmp_name);
+
+    fs::hard_link(keep, &tmp)?;
+    fs::rename(&tmp, duplicate).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::unstable::write::FileOptionsExt;
+    use zip::write::SimpleFileOptions;
+
+    fn create_zip(path: &Path, content: &[u8], year: u16) -> (PathBuf, u64) {
+        let file = File::create(path).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        let options = SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(year, 1, 1, 0, 0, 0).unwrap());
+
+        zip.start_file("data.txt", options).unwrap();
+        zip.write_all(content).unwrap();
+        zip.finish().unwrap();
+
+        (path.to_path_buf(), fs::metadata(path).unwrap().len())
+    }
+
+    #[test]
+    fn test_find_identical_and_same_contents() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        let a = create_zip(&dir.join("a.zip"), b"hello", 2020);
+        fs::copy(dir.join("a.zip"), dir.join("b.zip")).unwrap();
+        let b = (dir.join("b.zip"), a.1);
+        let c = create_zip(&dir.join("c.zip"), b"hello", 2024);
+        let d = create_zip(&dir.join("d.zip"), b"other", 2020);
+
+        let groups = find_duplicates(&[a.clone(), b.clone(), c.clone(), d], &HashSet::new());
+
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].identical);
+        assert_eq!(groups[0].archives, vec![a.clone(), b]);
+        assert_eq!(groups[0].reclaimable(), a.1);
+        assert!(!groups[1].identical);
+        assert_eq!(groups[1].archives, vec![a, c]);
+    }
+
+    #[test]
+    fn test_opaque_archives_are_only_compared_as_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        let mut sizes = vec![];
+
+        for (name, year) in [("a.zip", 2020), ("c.zip", 2024)] {
+            let mut zip = zip::ZipWriter::new(File::create(dir.join(name)).unwrap());
+            let options = SimpleFileOptions::default()
+                .last_modified_time(zip::DateTime::from_date_and_time(year, 1, 1, 0, 0, 0).unwrap())
+                .with_deprecated_encryption(b"pw");
+
+            zip.start_file("data.txt", options).unwrap();
+            zip.write_all(b"hello").unwrap();
+            zip.finish().unwrap();
+
+            sizes.push(fs::metadata(dir.join(name)).unwrap().len());
+        }
+
+        fs::copy(dir.join("a.zip"), dir.join("b.zip")).unwrap();
+
+        let a = (dir.join("a.zip"), sizes[0]);
+        let b = (dir.join("b.zip"), sizes[0]);
+        let c = (dir.join("c.zip"), sizes[1]);
+        let opaque = HashSet::from([a.0.clone(), b.0.clone(), c.0.clone()]);
+
+        let groups = find_duplicates(&[a.clone(), b.clone(), c], &opaque);
+
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].identical);
+        assert_eq!(groups[0].archives, vec![a, b]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks_are_not_duplicates() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let a = create_zip(&temp_dir.path().join("a.zip"), b"hello", 2020);
+        let b = temp_dir.path().join("b.zip");
+
+        fs::hard_link(&a.0, &b).unwrap();
+
+        assert!(find_duplicates(&[a.clone(), (b, a.1)], &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn test_delete_spares_same_contents_unless_asked() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        let a = create_zip(&dir.join("a.zip"), b"hello", 2020);
+        let b = create_zip(&dir.join("b.zip"), b"hello", 2024);
+        let groups = find_duplicates(&[a, b.clone()], &HashSet::new());
+
+        handle_duplicates(&groups, OnDuplicate::Delete);
+        assert!(b.0.exists());
+
+        handle_duplicates(&groups, OnDuplicate::DeleteSameContents);
+        assert!(!b.0.exists());
+    }
+
+    #[test]
+    fn test_hardlink_replaces_duplicate() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let keep = temp_dir.path().join("keep.zip");
+        let duplicate = temp_dir.path().join("dup.zip");
+
+        fs::write(&keep, b"same").unwrap();
+        fs::write(&duplicate, b"same").unwrap();
+
+        hardlink(&keep, &duplicate).unwrap();
+
+        assert_eq!(fs::read(&duplicate).unwrap(), b"same");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(
+                fs::metadata(&keep).unwrap().ino(),
+                fs::metadata(&duplicate).unwrap().ino()
+            );
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! What went wrong with a corrupted archive, as kinds with stable codes that
+//! filters, exit codes and reports can rely on instead of error messages.
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use serde::{Deserialize, Serialize};
+use zip::result::ZipError;
+
+use crate::format::{
+    END_OF_CENTRAL_DIRECTORY_SIGNATURE, EndOfCentralDirectory, LOCAL_FILE_HEADER_SIGNATURE,
+    read_u32,
+};
+
+/// Largest end of central directory record: 22 bytes and a 64 KiB comment
+const MAX_END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22 + 0xFFFF;
+
+/// Kind of failure of an archive check. The serialized names are the codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorKind {
+    /// Reading the file failed
+    Io,
+    /// The file can't be read with the current permissions
+    PermissionDenied,
+    /// The file disappeared before it could be read
+    NotFound,
+    /// Neither an end of central directory record nor a local header
+    NotAZip,
+    /// The archive or the data of an entry ends early
+    Truncated,
+    /// The end of central directory record doesn't match the central directory
+    BadEocd,
+    /// A central directory header is missing, invalid or disagrees with its entry
+    BadCentralHeader,
+    /// A local file header is missing or invalid
+    BadLocalHeader,
+    /// The data of an entry doesn't match its CRC-32
+    CrcMismatch,
+    /// An entry is larger or smaller than its headers say
+    SizeMismatch,
+    /// The compressed data of an entry can't be decoded
+    BadData,
+    /// The archive uses a feature that can't be read
+    UnsupportedFeature,
+    /// A stream holds something else where a header should start
+    UnexpectedSignature,
+    /// The manifest of a Java archive breaks the JAR manifest syntax
+    BadManifest,
+    /// A signature file of a Java archive is invalid or doesn't match the manifest
+    BadSignature,
+    /// Data doesn't match the digest a jar manifest or an APK signature gives for it
+    DigestMismatch,
+    /// A `.class` entry isn't a class file of a known version
+    BadClass,
+    /// A signed Java archive holds an entry no signature covers
+    UnsignedEntry,
+    /// The APK Signing Block has inconsistent sizes or malformed signatures
+    BadSigningBlock,
+    /// An APK has no `AndroidManifest.xml`, or not in binary XML
+    BadAndroidManifest,
+    /// Reading stopped once `--timeout-per-file` was spent
+    TimedOut,
+}
+
+impl ErrorKind {
+    /// Every kind, in the order they are listed in reports
+    pub(crate) const ALL: [ErrorKind; 21] = [
+        ErrorKind::Io,
+        ErrorKind::PermissionDenied,
+        ErrorKind::NotFound,
+        ErrorKind::NotAZip,
+        ErrorKind::Truncated,
+        ErrorKind::BadEocd,
+        ErrorKind::BadCentralHeader,
+        ErrorKind::BadLocalHeader,
+        ErrorKind::CrcMismatch,
+        ErrorKind::SizeMismatch,
+        ErrorKind::BadData,
+        ErrorKind::UnsupportedFeature,
+        ErrorKind::UnexpectedSignature,
+        ErrorKind::BadManifest,
+        ErrorKind::BadSignature,
+        ErrorKind::DigestMismatch,
+        ErrorKind::BadClass,
+        ErrorKind::UnsignedEntry,
+        ErrorKind::BadSigningBlock,
+        ErrorKind::BadAndroidManifest,
+        ErrorKind::TimedOut,
+    ];
+
+    /// Stable, machine-readable code of the kind
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::PermissionDenied => "permission_denied",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::NotAZip => "not_a_zip",
+            ErrorKind::Truncated => "truncated",
+            ErrorKind::BadEocd => "bad_eocd",
+            ErrorKind::BadCentralHeader => "bad_central_header",
+            ErrorKind::BadLocalHeader => "bad_local_header",
+            ErrorKind::CrcMismatch => "crc_mismatch",
+            ErrorKind::SizeMismatch => "size_mismatch",
+            ErrorKind::BadData => "bad_data",
+            ErrorKind::UnsupportedFeature => "unsupported_feature",
+            ErrorKind::UnexpectedSignature => "unexpected_signature",
+            ErrorKind::BadManifest => "bad_manifest",
+            ErrorKind::BadSignature => "bad_signature",
+            ErrorKind::DigestMismatch => "digest_mismatch",
+            ErrorKind::BadClass => "bad_class",
+            ErrorKind::UnsignedEntry => "unsigned_entry",
+            ErrorKind::BadSigningBlock => "bad_signing_block",
+            ErrorKind::BadAndroidManifest => "bad_android_manifest",
+            ErrorKind::TimedOut => "timed_out",
+        }
+    }
+
+    /// Whether the file could not be read at all, so nothing is known about the archive
+    pub(crate) fn is_unreadable(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Io | ErrorKind::PermissionDenied | ErrorKind::NotFound
+        )
+    }
+
+    /// Kind of a failed read of the archive or of an entry's data
+    pub(crate) fn of_io(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::UnexpectedEof => ErrorKind::Truncated,
+            // Only `DeadlineReader` fails this way
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            // `ZipArchive` reports a CRC mismatch only through this message
+            io::ErrorKind::InvalidData if e.to_string() == "Invalid checksum" => {
+                ErrorKind::CrcMismatch
+            }
+            io::ErrorKind::InvalidData => ErrorKind::BadData,
+            _ => ErrorKind::Io,
+        }
+    }
+
+    /// Kind of an error of `ZipArchive` opening an entry
+    pub(crate) fn of_entry(e: &ZipError) -> Self {
+        match e {
+            ZipError::Io(e) => ErrorKind::of_io(e),
+            ZipError::UnsupportedArchive(_) => ErrorKind::UnsupportedFeature,
+            _ => ErrorKind::BadLocalHeader,
+        }
+    }
+
+    /// Kind of an error of `ZipArchive` reading the central directory, told
+    /// apart by looking at the end and the start of the file
+    pub(crate) fn of_archive<R: Read + Seek>(reader: &mut R, e: &ZipError) -> Self {
+        match e {
+            ZipError::Io(e) => ErrorKind::of_io(e),
+            ZipError::UnsupportedArchive(_) => ErrorKind::UnsupportedFeature,
+            _ => probe_archive(reader).unwrap_or(ErrorKind::Io),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Find out why the central directory of an archive can't be read
+fn probe_archive<R: Read + Seek>(reader: &mut R) -> io::Result<ErrorKind> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_start = len.saturating_sub(MAX_END_OF_CENTRAL_DIRECTORY_SIZE);
+
+    reader.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = vec![];
+    reader.read_to_end(&mut tail)?;
+
+    let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+
+    let Some(position) = tail.windows(4).rposition(|w| w == signature) else {
+        // Without an end record, a file starting like an archive was cut short
+        reader.seek(SeekFrom::Start(0))?;
+
+        return Ok(match read_u32(reader) {
+            Ok(LOCAL_FILE_HEADER_SIGNATURE) => ErrorKind::Truncated,
+            _ => ErrorKind::NotAZip,
+        });
+    };
+
+    let end_start = tail_start + position as u64;
+    let end = EndOfCentralDirectory::read(&mut &tail[position + 4..])?;
+
+    // Unless the end record points past itself, the central directory is to blame
+    if end.central_directory_offset + end.central_directory_size > end_start {
+        Ok(ErrorKind::BadEocd)
+    } else {
+        Ok(ErrorKind::BadCentralHeader)
+    }
+}
+
+/// A failed check: its kind, and a message for people
+#[derive(Clone, Debug)]
+pub(crate) struct CheckError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CheckError {
+    pub(crate) fn new(kind: ErrorKind, message: String) -> Self {
+        Self { kind, message }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::CENTRAL_DIRECTORY_HEADER_SIGNATURE;
+    use std::io::{Cursor, Write};
+
+    fn probe(data: &[u8]) -> ErrorKind {
+        ErrorKind::of_archive(
+            &mut Cursor::new(data),
+            &ZipError::InvalidArchive("test".into()),
+        )
+    }
+
+    #[test]
+    fn test_codes_match_serialized_names() {
+        for kind in ErrorKind::ALL {
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind.code())
+            );
+        }
+    }
+
+    #[test]
+    fn test_of_io() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let checksum = io::Error::new(io::ErrorKind::InvalidData, "Invalid checksum");
+        let inflate = io::Error::new(io::ErrorKind::InvalidData, "corrupt deflate stream");
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "per-file timeout exceeded");
+
+        assert_eq!(ErrorKind::of_io(&denied), ErrorKind::PermissionDenied);
+        assert_eq!(ErrorKind::of_io(&checksum), ErrorKind::CrcMismatch);
+        assert_eq!(ErrorKind::of_io(&inflate), ErrorKind::BadData);
+        assert_eq!(ErrorKind::of_io(&timeout), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_probe_archive() {
+        let mut archive = vec![];
+        {
+            let mut zip = zip::ZipWriter::new(Cursor::new(&mut archive));
+            zip.start_file("a.txt", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(b"Hello").unwrap();
+            zip.finish().unwrap();
+        }
+        let central = archive
+            .windows(4)
+            .position(|w| w == CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes())
+            .unwrap();
+
+        assert_eq!(probe(b"plain text"), ErrorKind::NotAZip);
+        assert_eq!(probe(&archive[..central]), ErrorKind::Truncated);
+
+        // The end record claims a central directory running past it
+        let mut moved = archive.clone();
+        let end = moved.len() - 22;
+        moved[end + 16] = 0xFF;
+        assert_eq!(probe(&moved), ErrorKind::BadEocd);
+
+        let mut broken = archive.clone();
+        broken[central] = b'X';
+        assert_eq!(probe(&broken), ErrorKind::BadCentralHeader);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::CheckResult;
+use crate::cli::FailOn;
+
+/// All archives are valid, or no finding selected by `--fail-on` occurred
+pub(crate) const SUCCESS: u8 = 0;
+/// At least one archive is corrupted
+pub(crate) const CORRUPTED: u8 = 1;
+/// Invalid command-line usage (clap uses the same code)
+pub(crate) const USAGE: u8 = 2;
+/// At least one archive was skipped because it is password protected
+pub(crate) const SKIPPED: u8 = 3;
+/// At least one directory could not be read while walking
+pub(crate) const WALK_ERROR: u8 = 4;
+/// At least one archive could not be fully verified
+pub(crate) const SUSPICIOUS: u8 = 5;
+/// At least one archive is corrupted that was not in the `--baseline` report
+pub(crate) const REGRESSION: u8 = 6;
+/// At least one archive has entries encrypted with weak ZipCrypto
+pub(crate) const WEAK_CRYPTO: u8 = 7;
+/// At least one file could not be read, so it was never checked
+pub(crate) const UNREADABLE: u8 = 8;
+/// At least one archive is still being downloaded or written
+pub(crate) const INCOMPLETE: u8 = 9;
+/// At least one Java archive has a bad manifest, signature or class file
+pub(crate) const POLICY_VIOLATION: u8 = 10;
+/// `find` and `grep` matched nothing, like grep
+pub(crate) const NO_MATCH: u8 = 1;
+/// `lint` flagged at least one entry
+pub(crate) const LINT_FINDINGS: u8 = 1;
+
+/// Pick the exit code for a finished run.
+///
+/// Findings are only considered when selected by `--fail-on`; the most severe
+/// one wins. Regressions against a baseline, unreadable files and walker
+/// errors always fail the run, the latter two since some archives were never
+/// checked.
+pub(crate) fn exit_code(result: &CheckResult, fail_on: &[FailOn]) -> u8 {
+    if result.regressions > 0 {
+        REGRESSION
+    } else if fail_on.contains(&FailOn::Corrupted) && result.corrupted > 0 {
+        CORRUPTED
+    } else if fail_on.contains(&FailOn::PolicyViolation) && result.policy_violations > 0 {
+        POLICY_VIOLATION
+    } else if fail_on.contains(&FailOn::WeakCrypto) && result.weak_crypto > 0 {
+        WEAK_CRYPTO
+    } else if fail_on.contains(&FailOn::Encrypted) && result.encrypted > 0 {
+        SKIPPED
+    } else if fail_on.contains(&FailOn::Suspicious) && result.suspicious > 0 {
+        SUSPICIOUS
+    } else if fail_on.contains(&FailOn::Incomplete) && result.incomplete > 0 {
+        INCOMPLETE
+    } else if result.unreadable > 0 {
+        UNREADABLE
+    } else if result.walk_errors > 0 {
+        WALK_ERROR
+    } else {
+        SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_all_valid() {
+        let result = CheckResult {
+            total: 3,
+            valid: 3,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), SUCCESS);
+    }
+
+    #[test]
+    fn test_exit_code_respects_fail_on() {
+        let result = CheckResult {
+            total: 2,
+            skipped: 1,
+            encrypted: 1,
+            corrupted: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), CORRUPTED);
+        assert_eq!(exit_code(&result, &[FailOn::Encrypted]), SKIPPED);
+        assert_eq!(exit_code(&result, &[FailOn::Suspicious]), SUCCESS);
+        assert_eq!(
+            exit_code(&result, &[FailOn::Encrypted, FailOn::Corrupted]),
+            CORRUPTED
+        );
+    }
+
+    #[test]
+    fn test_exit_code_weak_crypto() {
+        let result = CheckResult {
+            skipped: 2,
+            encrypted: 2,
+            weak_crypto: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::WeakCrypto]), WEAK_CRYPTO);
+        assert_eq!(
+            exit_code(&result, &[FailOn::Encrypted, FailOn::WeakCrypto]),
+            WEAK_CRYPTO
+        );
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), SUCCESS);
+    }
+
+    #[test]
+    fn test_exit_code_incomplete() {
+        let result = CheckResult {
+            skipped: 1,
+            incomplete: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), SUCCESS);
+        assert_eq!(
+            exit_code(&result, &[FailOn::Corrupted, FailOn::Incomplete]),
+            INCOMPLETE
+        );
+    }
+
+    #[test]
+    fn test_exit_code_policy_violations() {
+        let result = CheckResult {
+            total: 2,
+            valid: 1,
+            policy_violations: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), SUCCESS);
+        assert_eq!(
+            exit_code(&result, &[FailOn::Corrupted, FailOn::PolicyViolation]),
+            POLICY_VIOLATION
+        );
+    }
+
+    #[test]
+    fn test_exit_code_walk_errors_always_fail() {
+        let result = CheckResult {
+            walk_errors: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[]), WALK_ERROR);
+    }
+
+    #[test]
+    fn test_exit_code_unreadable_files_always_fail() {
+        let result = CheckResult {
+            skipped: 1,
+            unreadable: 1,
+            walk_errors: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), UNREADABLE);
+        assert_eq!(exit_code(&result, &[]), UNREADABLE);
+    }
+
+    #[test]
+    fn test_exit_code_regressions_win() {
+        let result = CheckResult {
+            corrupted: 2,
+            regressions: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(exit_code(&result, &[FailOn::Corrupted]), REGRESSION);
+        assert_eq!(exit_code(&result, &[]), REGRESSION);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Read a list of paths from `source`, or from stdin when it is `-`.
+///
+/// Entries are separated by newlines, or by NUL bytes when `nul` is set, so
+/// the output of `find -print0` survives filenames with newlines in them.
+pub(crate) fn read_file_list(source: &Path, nul: bool) -> io::Result<Vec<PathBuf>> {
+    if source == Path::new("-") {
+        parse_file_list(io::stdin().lock(), nul)
+    } else {
+        parse_file_list(BufReader::new(File::open(source)?), nul)
+    }
+}
+
+fn parse_file_list<R: BufRead>(mut reader: R, nul: bool) -> io::Result<Vec<PathBuf>> {
+    let separator = if nul { b'\0' } else { b'\n' };
+
+    let mut paths = Vec::new();
+    let mut entry = Vec::new();
+
+    while reader.read_until(separator, &mut entry)? > 0 {
+        if entry.last() == Some(&separator) {
+            entry.pop();
+        }
+
+        if !nul && entry.last() == Some(&b'\r') {
+            entry.pop();
+        }
+
+        if !entry.is_empty() {
+            paths.push(path_from_bytes(std::mem::take(&mut entry)));
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_newline_separated() {
+        let input = b"a.zip\r\ndir/b.zip\n\nwith space.zip";
+
+        let paths = parse_file_list(&input[..], false).unwrap();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("a.zip"),
+                PathBuf::from("dir/b.zip"),
+                PathBuf::from("with space.zip"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_nul_separated() {
+        let input = b"a.zip\0line\nbreak.zip\0\0";
+
+        let paths = parse_file_list(&input[..], true).unwrap();
+
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("a.zip"), PathBuf::from("line\nbreak.zip")]
+        );
+    }
+}
@@ -0,0 +1,616 @@
+//! Raw ZIP record layouts, for checks that need more than `ZipArchive` exposes.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+pub(crate) const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+pub(crate) const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+pub(crate) const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+pub(crate) const DIGITAL_SIGNATURE_SIGNATURE: u32 = 0x0505_4b50;
+pub(crate) const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+pub(crate) const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+pub(crate) const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+/// General purpose flag: the entry is encrypted
+pub(crate) const FLAG_ENCRYPTED: u16 = 1;
+/// General purpose flag of LZMA entries: the data ends with an end-of-stream marker
+pub(crate) const FLAG_LZMA_END_MARKER: u16 = 1 << 1;
+/// General purpose flag: CRC and sizes follow the data in a data descriptor
+pub(crate) const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+/// General purpose flag: the entry uses PKWARE strong encryption
+pub(crate) const FLAG_STRONG_ENCRYPTION: u16 = 1 << 6;
+/// General purpose flag (language encoding): the name and comment are UTF-8
+pub(crate) const FLAG_UTF8: u16 = 1 << 11;
+
+pub(crate) const METHOD_STORED: u16 = 0;
+pub(crate) const METHOD_DEFLATED: u16 = 8;
+pub(crate) const METHOD_DEFLATE64: u16 = 9;
+pub(crate) const METHOD_BZIP2: u16 = 12;
+pub(crate) const METHOD_LZMA: u16 = 14;
+/// Method ID that Zstandard used before 93 was assigned
+pub(crate) const METHOD_ZSTD_DEPRECATED: u16 = 20;
+pub(crate) const METHOD_ZSTD: u16 = 93;
+pub(crate) const METHOD_XZ: u16 = 95;
+pub(crate) const METHOD_PPMD: u16 = 98;
+/// Placeholder method of WinZip AES entries; the real one is in the AES extra field
+pub(crate) const METHOD_AES: u16 = 99;
+
+/// Name of a compression method, as listed in the ZIP specification
+pub(crate) fn method_name(method: u16) -> &'static str {
+    match method {
+        METHOD_STORED => "Stored",
+        1 => "Shrunk",
+        2..=5 => "Reduced",
+        6 => "Imploded",
+        7 => "Tokenized",
+        METHOD_DEFLATED => "Deflated",
+        METHOD_DEFLATE64 => "Deflate64",
+        10 => "PKWARE DCL Imploded",
+        METHOD_BZIP2 => "BZIP2",
+        METHOD_LZMA => "LZMA",
+        16 => "IBM z/OS CMPSC",
+        18 => "IBM TERSE",
+        19 => "IBM LZ77 z",
+        METHOD_ZSTD_DEPRECATED | METHOD_ZSTD => "Zstandard",
+        94 => "MP3",
+        METHOD_XZ => "XZ",
+        96 => "JPEG",
+        97 => "WavPack",
+        METHOD_PPMD => "PPMd",
+        METHOD_AES => "AES",
+        _ => "unknown",
+    }
+}
+
+/// Extra field holding the 64-bit values of fields set to their maximum
+pub(crate) const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+/// Extra field of PKWARE strong encryption
+pub(crate) const STRONG_ENCRYPTION_EXTRA_FIELD: u16 = 0x0017;
+/// Extra field of WinZip AES encryption, holding the key strength
+pub(crate) const AES_EXTRA_FIELD: u16 = 0x9901;
+/// Extra field of NTFS timestamps, in 100 ns ticks since 1601
+pub(crate) const NTFS_EXTRA_FIELD: u16 = 0x000A;
+/// Info-ZIP extended timestamp extra field, in seconds since 1970 (UTC)
+pub(crate) const EXTENDED_TIMESTAMP_EXTRA_FIELD: u16 = 0x5455;
+
+/// Seconds between 1601-01-01, where NTFS time starts, and 1970-01-01
+const NTFS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Name of a known extra field ID
+pub(crate) fn extra_field_name(id: u16) -> &'static str {
+    match id {
+        ZIP64_EXTRA_FIELD => "Zip64",
+        0x0007 => "AV info",
+        0x0009 => "OS/2",
+        NTFS_EXTRA_FIELD => "NTFS",
+        0x000D => "Unix",
+        0x0014 => "PKCS#7 store",
+        0x0015 => "X.509 certificate",
+        0x0016 => "X.509 central directory certificate",
+        STRONG_ENCRYPTION_EXTRA_FIELD => "strong encryption",
+        0x0021 => "strong encryption recipients",
+        0x6375 => "Info-ZIP Unicode comment",
+        0x7075 => "Info-ZIP Unicode path",
+        EXTENDED_TIMESTAMP_EXTRA_FIELD => "extended timestamp",
+        0x5855 => "Info-ZIP Unix (old)",
+        0x7855 => "Info-ZIP Unix",
+        0x7875 => "Info-ZIP Unix UID/GID",
+        AES_EXTRA_FIELD => "WinZip AES",
+        0xA220 => "Microsoft padding",
+        0xCAFE => "JAR marker",
+        0xD935 => "Android alignment",
+        _ => "unknown",
+    }
+}
+
+/// Name of the host system in the upper byte of "version made by"
+pub(crate) fn host_system_name(host: u8) -> &'static str {
+    match host {
+        0 => "MS-DOS",
+        1 => "Amiga",
+        2 => "OpenVMS",
+        3 => "Unix",
+        4 => "VM/CMS",
+        5 => "Atari ST",
+        6 => "OS/2",
+        7 => "Macintosh",
+        8 => "Z-System",
+        9 => "CP/M",
+        10 => "Windows NTFS",
+        11 => "MVS",
+        12 => "VSE",
+        13 => "Acorn RISC",
+        14 => "VFAT",
+        15 => "alternate MVS",
+        16 => "BeOS",
+        17 => "Tandem",
+        18 => "OS/400",
+        19 => "macOS",
+        _ => "unknown",
+    }
+}
+
+/// Local date and time of an MS-DOS timestamp, or `None` if it is not a valid date
+pub(crate) fn dos_datetime(date: u16, time: u16) -> Option<NaiveDateTime> {
+    let day = NaiveDate::from_ymd_opt(
+        1980 + i32::from(date >> 9),
+        u32::from((date >> 5) & 0x0F),
+        u32::from(date & 0x1F),
+    )?;
+
+    day.and_hms_opt(
+        u32::from(time >> 11),
+        u32::from((time >> 5) & 0x3F),
+        u32::from(time & 0x1F) * 2,
+    )
+}
+
+/// Modification time in the extended timestamp or NTFS extra field, in
+/// seconds since 1970 (UTC)
+pub(crate) fn extra_modified_time(extra: &[u8]) -> Option<i64> {
+    extended_modified_time(extra).or_else(|| ntfs_modified_time(extra))
+}
+
+/// Modification time in the Info-ZIP extended timestamp extra field
+pub(crate) fn extended_modified_time(extra: &[u8]) -> Option<i64> {
+    let (_, data) = extra_fields(extra).find(|(id, _)| *id == EXTENDED_TIMESTAMP_EXTRA_FIELD)?;
+
+    // Flags, then the modification time if bit 0 is set
+    if data.first()? & 1 == 0 {
+        return None;
+    }
+
+    data.get(1..5)
+        .map(|time| i64::from(i32::from_le_bytes(time.try_into().unwrap())))
+}
+
+/// Modification time in the NTFS extra field
+pub(crate) fn ntfs_modified_time(extra: &[u8]) -> Option<i64> {
+    let (_, mut data) = extra_fields(extra).find(|(id, _)| *id == NTFS_EXTRA_FIELD)?;
+
+    // Reserved, then tagged attributes; tag 1 holds mtime, atime and ctime
+    let _reserved = read_u32(&mut data).ok()?;
+
+    while let (Ok(tag), Ok(size)) = (read_u16(&mut data), read_u16(&mut data)) {
+        if tag == 1 && size >= 8 {
+            let ticks = read_u64(&mut data).ok()? as i64;
+            return Some(ticks / 10_000_000 - NTFS_EPOCH_OFFSET);
+        }
+
+        data = data.get(size as usize..)?;
+    }
+
+    None
+}
+
+/// How an entry is encrypted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encryption {
+    /// Traditional PKWARE encryption, which is easily broken
+    ZipCrypto,
+    Aes128,
+    Aes192,
+    Aes256,
+    /// PKWARE strong encryption, announced by a strong encryption header
+    Strong,
+}
+
+impl Encryption {
+    /// Tell the scheme of an entry from its header, or `None` if it is not encrypted
+    pub(crate) fn detect(flags: u16, method: u16, extra: &[u8]) -> Option<Self> {
+        if flags & FLAG_ENCRYPTED == 0 {
+            return None;
+        }
+
+        if flags & FLAG_STRONG_ENCRYPTION != 0
+            || extra_fields(extra).any(|(id, _)| id == STRONG_ENCRYPTION_EXTRA_FIELD)
+        {
+            return Some(Encryption::Strong);
+        }
+
+        if method == METHOD_AES {
+            // Vendor version (2), vendor ID (2), then the key strength
+            let strength = extra_fields(extra)
+                .find(|(id, _)| *id == AES_EXTRA_FIELD)
+                .and_then(|(_, data)| data.get(4).copied());
+
+            match strength {
+                Some(1) => return Some(Encryption::Aes128),
+                Some(2) => return Some(Encryption::Aes192),
+                Some(3) => return Some(Encryption::Aes256),
+                _ => {}
+            }
+        }
+
+        Some(Encryption::ZipCrypto)
+    }
+
+    /// Whether the scheme is considered broken
+    pub(crate) fn is_weak(&self) -> bool {
+        *self == Encryption::ZipCrypto
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Encryption::ZipCrypto => "ZipCrypto",
+            Encryption::Aes128 => "AES-128",
+            Encryption::Aes192 => "AES-192",
+            Encryption::Aes256 => "AES-256",
+            Encryption::Strong => "strong encryption",
+        }
+    }
+}
+
+pub(crate) fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Iterate over the `(id, data)` pairs of an extra field block.
+///
+/// Iteration stops at the first field whose length runs past the block.
+pub(crate) fn extra_fields(extra: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut rest = extra;
+
+    std::iter::from_fn(move || {
+        if rest.len() < 4 {
+            return None;
+        }
+
+        let id = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + len)?;
+
+        rest = &rest[4 + len..];
+
+        Some((id, data))
+    })
+}
+
+/// Replace 32-bit fields set to `0xFFFFFFFF` with the values of the Zip64 extra field.
+///
+/// The extra field only holds the values that overflowed, in the order of `fields`.
+fn apply_zip64(extra: &[u8], fields: &mut [&mut u64]) {
+    let Some((_, mut data)) = extra_fields(extra).find(|(id, _)| *id == ZIP64_EXTRA_FIELD) else {
+        return;
+    };
+
+    for field in fields.iter_mut() {
+        if **field == u32::MAX as u64 {
+            match read_u64(&mut data) {
+                Ok(value) => **field = value,
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+pub(crate) struct LocalFileHeader {
+    pub flags: u16,
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub name: Vec<u8>,
+    pub extra: Vec<u8>,
+}
+
+impl LocalFileHeader {
+    /// Read a local file header whose signature has already been consumed
+    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let _version_needed = read_u16(reader)?;
+        let flags = read_u16(reader)?;
+        let method = read_u16(reader)?;
+        let _modified_time = read_u16(reader)?;
+        let _modified_date = read_u16(reader)?;
+        let crc32 = read_u32(reader)?;
+        let mut compressed_size = read_u32(reader)? as u64;
+        let mut uncompressed_size = read_u32(reader)? as u64;
+        let name_len = read_u16(reader)? as usize;
+        let extra_len = read_u16(reader)? as usize;
+        let name = read_bytes(reader, name_len)?;
+        let extra = read_bytes(reader, extra_len)?;
+
+        apply_zip64(&extra, &mut [&mut uncompressed_size, &mut compressed_size]);
+
+        Ok(Self {
+            flags,
+            method,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            name,
+            extra,
+        })
+    }
+
+    /// Whether the entry carries a Zip64 extra field, which widens its data descriptor
+    pub(crate) fn is_zip64(&self) -> bool {
+        extra_fields(&self.extra).any(|(id, _)| id == ZIP64_EXTRA_FIELD)
+    }
+}
+
+pub(crate) struct CentralDirectoryHeader {
+    /// Host system in the upper byte, specification version in the lower
+    pub version_made_by: u16,
+    pub flags: u16,
+    pub method: u16,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// Sizes as written in the header, before the Zip64 extra field is applied
+    pub stored_compressed_size: u32,
+    pub stored_uncompressed_size: u32,
+    pub local_header_offset: u64,
+    pub modified_time: u16,
+    pub modified_date: u16,
+    pub external_attributes: u32,
+    pub name: Vec<u8>,
+    pub extra: Vec<u8>,
+}
+
+impl CentralDirectoryHeader {
+    /// Read a central directory header whose signature has already been consumed
+    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let version_made_by = read_u16(reader)?;
+        let _version_needed = read_u16(reader)?;
+        let flags = read_u16(reader)?;
+        let method = read_u16(reader)?;
+        let modified_time = read_u16(reader)?;
+        let modified_date = read_u16(reader)?;
+        let crc32 = read_u32(reader)?;
+        let stored_compressed_size = read_u32(reader)?;
+        let stored_uncompressed_size = read_u32(reader)?;
+        let mut compressed_size = stored_compressed_size as u64;
+        let mut uncompressed_size = stored_uncompressed_size as u64;
+        let name_len = read_u16(reader)? as usize;
+        let extra_len = read_u16(reader)? as usize;
+        let comment_len = read_u16(reader)? as usize;
+        let _disk_start = read_u16(reader)?;
+        let _internal_attributes = read_u16(reader)?;
+        let external_attributes = read_u32(reader)?;
+        let mut local_header_offset = read_u32(reader)? as u64;
+        let name = read_bytes(reader, name_len)?;
+        let extra = read_bytes(reader, extra_len)?;
+        let _comment = read_bytes(reader, comment_len)?;
+
+        apply_zip64(
+            &extra,
+            &mut [
+                &mut uncompressed_size,
+                &mut compressed_size,
+                &mut local_header_offset,
+            ],
+        );
+
+        Ok(Self {
+            version_made_by,
+            flags,
+            method,
+            crc32,
+            compressed_size,
+            uncompressed_size,
+            stored_compressed_size,
+            stored_uncompressed_size,
+            local_header_offset,
+            modified_time,
+            modified_date,
+            external_attributes,
+            name,
+            extra,
+        })
+    }
+}
+
+/// Read the central directory header at `offset`
+pub(crate) fn central_header_at<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+) -> io::Result<CentralDirectoryHeader> {
+    reader.seek(SeekFrom::Start(offset))?;
+
+    if read_u32(reader)? != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid central directory header signature",
+        ));
+    }
+
+    CentralDirectoryHeader::read(reader)
+}
+
+/// Read the headers of `entries` entries from the central directory at `start`
+pub(crate) fn read_central_directory<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    entries: usize,
+) -> io::Result<Vec<CentralDirectoryHeader>> {
+    reader.seek(SeekFrom::Start(start))?;
+
+    (0..entries)
+        .map(|_| {
+            if read_u32(reader)? != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid central directory header signature",
+                ));
+            }
+
+            CentralDirectoryHeader::read(reader)
+        })
+        .collect()
+}
+
+pub(crate) struct EndOfCentralDirectory {
+    pub entries: u64,
+    pub central_directory_size: u64,
+    pub central_directory_offset: u64,
+}
+
+impl EndOfCentralDirectory {
+    /// Read an end of central directory record whose signature has already been consumed
+    pub(crate) fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let _disk = read_u16(reader)?;
+        let _central_directory_disk = read_u16(reader)?;
+        let _entries_on_disk = read_u16(reader)?;
+        let entries = read_u16(reader)? as u64;
+        let central_directory_size = read_u32(reader)? as u64;
+        let central_directory_offset = read_u32(reader)? as u64;
+        let comment_len = read_u16(reader)? as usize;
+        let _comment = read_bytes(reader, comment_len)?;
+
+        Ok(Self {
+            entries,
+            central_directory_size,
+            central_directory_offset,
+        })
+    }
+
+    /// Read a Zip64 end of central directory record whose signature has already been consumed
+    pub(crate) fn read_zip64<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let record_size = read_u64(reader)?;
+        let _version_made_by = read_u16(reader)?;
+        let _version_needed = read_u16(reader)?;
+        let _disk = read_u32(reader)?;
+        let _central_directory_disk = read_u32(reader)?;
+        let _entries_on_disk = read_u64(reader)?;
+        let entries = read_u64(reader)?;
+        let central_directory_size = read_u64(reader)?;
+        let central_directory_offset = read_u64(reader)?;
+
+        // Skip the extensible data sector after the fixed 44 bytes
+        io::copy(
+            &mut reader.take(record_size.saturating_sub(44)),
+            &mut io::sink(),
+        )?;
+
+        Ok(Self {
+            entries,
+            central_directory_size,
+            central_directory_offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extra_fields() {
+        let extra = [
+            0x01, 0x00, 0x02, 0x00, 0xAA, 0xBB, 0x55, 0x54, 0x01, 0x00, 0xCC,
+        ];
+
+        let fields: Vec<(u16, &[u8])> = extra_fields(&extra).collect();
+
+        assert_eq!(
+            fields,
+            vec![(0x0001, &[0xAA, 0xBB][..]), (0x5455, &[0xCC][..])]
+        );
+    }
+
+    #[test]
+    fn test_extra_fields_truncated() {
+        let extra = [0x01, 0x00, 0x08, 0x00, 0xAA];
+
+        assert_eq!(extra_fields(&extra).count(), 0);
+    }
+
+    #[test]
+    fn test_apply_zip64_only_replaces_overflowed_fields() {
+        let mut extra = vec![0x01, 0x00, 0x08, 0x00];
+        extra.extend_from_slice(&(5u64 << 32).to_le_bytes());
+
+        let mut uncompressed = 10u64;
+        let mut compressed = u32::MAX as u64;
+        apply_zip64(&extra, &mut [&mut uncompressed, &mut compressed]);
+
+        assert_eq!(uncompressed, 10);
+        assert_eq!(compressed, 5u64 << 32);
+    }
+
+    #[test]
+    fn test_dos_datetime() {
+        // 2024-03-15 13:45:30
+        let date = (44 << 9) | (3 << 5) | 15;
+        let time = (13 << 11) | (45 << 5) | 15;
+
+        assert_eq!(
+            dos_datetime(date, time).unwrap().to_string(),
+            "2024-03-15 13:45:30"
+        );
+        // Month 0, as written by tools that leave the date empty
+        assert_eq!(dos_datetime(0, 0), None);
+    }
+
+    #[test]
+    fn test_extra_modified_time() {
+        let mut extended = vec![0x55, 0x54, 0x05, 0x00, 0x01];
+        extended.extend_from_slice(&1_700_000_000i32.to_le_bytes());
+
+        let mut ntfs = vec![0x0A, 0x00, 0x20, 0x00, 0, 0, 0, 0, 0x01, 0x00, 0x18, 0x00];
+        let ticks = (1_700_000_000 + NTFS_EPOCH_OFFSET) as u64 * 10_000_000;
+        ntfs.extend_from_slice(&ticks.to_le_bytes());
+        ntfs.extend_from_slice(&[0; 16]);
+
+        assert_eq!(extra_modified_time(&extended), Some(1_700_000_000));
+        assert_eq!(extra_modified_time(&ntfs), Some(1_700_000_000));
+        assert_eq!(extra_modified_time(&[]), None);
+
+        let both = [ntfs, extended].concat();
+        assert_eq!(extended_modified_time(&both), Some(1_700_000_000));
+        assert_eq!(ntfs_modified_time(&both), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn test_detect_encryption() {
+        let aes = |strength: u8| {
+            [
+                0x01, 0x99, 0x07, 0x00, 0x02, 0x00, b'A', b'E', strength, 0x08, 0x00,
+            ]
+        };
+
+        assert_eq!(Encryption::detect(0, METHOD_DEFLATED, &[]), None);
+        assert_eq!(
+            Encryption::detect(FLAG_ENCRYPTED, METHOD_DEFLATED, &[]),
+            Some(Encryption::ZipCrypto)
+        );
+        assert_eq!(
+            Encryption::detect(FLAG_ENCRYPTED, METHOD_AES, &aes(1)),
+            Some(Encryption::Aes128)
+        );
+        assert_eq!(
+            Encryption::detect(FLAG_ENCRYPTED, METHOD_AES, &aes(3)),
+            Some(Encryption::Aes256)
+        );
+        assert_eq!(
+            Encryption::detect(
+                FLAG_ENCRYPTED | FLAG_STRONG_ENCRYPTION,
+                METHOD_DEFLATED,
+                &[]
+            ),
+            Some(Encryption::Strong)
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Self-contained HTML report: styles, script and charts are inlined, so the
+//! page can be shared as a single file and never loads anything from the network.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::ErrorKind;
+use crate::{CheckResult, FileRecord, ZipFileStatus};
+
+/// Colour of each status, in the order of `ZipFileStatus::CODES`
+const STATUS_COLOURS: [&str; ZipFileStatus::CODES.len()] = [
+    "#2e9e44", "#d99a00", "#d0342c", "#7d7d7d", "#7a4fb5", "#1f6fb2", "#16a0a0", "#e0641a",
+];
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0; }
+.meta { color: #666; margin-top: 0.3em; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin: 1.5em 0; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 0.8em 1.2em; min-width: 8em; }
+.card .value { font-size: 1.8em; font-weight: bold; }
+.chart { display: flex; align-items: center; gap: 2em; }
+.legend span { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.4em; }
+table { border-collapse: collapse; width: 100%; margin-top: 1em; }
+th, td { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #eee; }
+th { cursor: pointer; user-select: none; background: #f6f6f6; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+.status { font-weight: bold; }
+.bar { display: flex; height: 0.9em; min-width: 10em; }
+.filters { margin-top: 1em; display: flex; gap: 1em; }
+ul.entries { margin: 0.3em 0 0; padding-left: 1.2em; font-size: 0.9em; }
+"#;
+
+const SCRIPT: &str = r#"
+function sortTable(th) {
+  const table = th.closest('table');
+  const column = Array.from(th.parentNode.children).indexOf(th);
+  const ascending = th.dataset.order !== 'asc';
+  th.dataset.order = ascending ? 'asc' : 'desc';
+  const rows = Array.from(table.tBodies[0].rows);
+  rows.sort((a, b) => {
+    const x = a.cells[column].dataset.sort ?? a.cells[column].textContent;
+    const y = b.cells[column].dataset.sort ?? b.cells[column].textContent;
+    const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+    return ascending ? order : -order;
+  });
+  rows.forEach(row => table.tBodies[0].appendChild(row));
+}
+function filterArchives() {
+  const text = document.getElementById('search').value.toLowerCase();
+  const status = document.getElementById('status').value;
+  const error = document.getElementById('error').value;
+  for (const row of document.getElementById('archives').tBodies[0].rows) {
+    const matches = row.textContent.toLowerCase().includes(text)
+      && (status === '' || row.dataset.status === status)
+      && (error === '' || row.dataset.error === error);
+    row.style.display = matches ? '' : 'none';
+  }
+}
+document.querySelectorAll('th').forEach(th => th.addEventListener('click', () => sortTable(th)));
+"#;
+
+/// Render the report of a finished run and write it to `path`
+pub(crate) fn write_html_report(
+    path: &Path,
+    result: &CheckResult,
+    records: &[FileRecord],
+) -> io::Result<()> {
+    let generated = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    fs::write(path, render_report(result, records, &generated))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn colour(code: &str) -> &'static str {
+    ZipFileStatus::CODES
+        .iter()
+        .position(|c| *c == code)
+        .map_or("#000", |i| STATUS_COLOURS[i])
+}
+
+/// Archives per status, in the order of `ZipFileStatus::CODES`
+fn count_statuses<'a>(
+    records: impl Iterator<Item = &'a FileRecord>,
+) -> [usize; ZipFileStatus::CODES.len()] {
+    let mut counts = [0; ZipFileStatus::CODES.len()];
+
+    for record in records {
+        let code = record.status.code();
+
+        if let Some(i) = ZipFileStatus::CODES.iter().position(|c| *c == code) {
+            counts[i] += 1;
+        }
+    }
+
+    counts
+}
+
+/// Donut chart of the status distribution, drawn as stroked circle segments
+fn donut_chart(counts: &[usize; ZipFileStatus::CODES.len()]) -> String {
+    let total: usize = counts.iter().sum();
+    let circumference = 2.0 * std::f64::consts::PI * 15.915;
+    let mut svg = String::from(
+        r#"<svg viewBox="0 0 42 42" width="180" height="180" role="img" aria-label="Status distribution">"#,
+    );
+    let mut offset = 0.0;
+
+    svg.push_str(
+        r##"<circle cx="21" cy="21" r="15.915" fill="none" stroke="#eee" stroke-width="6"/>"##,
+    );
+
+    for (i, count) in counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+
+        let length = circumference * *count as f64 / total as f64;
+
+        write!(
+            svg,
+            r#"<circle cx="21" cy="21" r="15.915" fill="none" stroke="{}" stroke-width="6" stroke-dasharray="{:.3} {:.3}" stroke-dashoffset="{:.3}" transform="rotate(-90 21 21)"/>"#,
+            STATUS_COLOURS[i],
+            length,
+            circumference - length,
+            -offset
+        )
+        .unwrap();
+
+        offset += length;
+    }
+
+    write!(
+        svg,
+        r#"<text x="21" y="23.5" text-anchor="middle" font-size="7">{}</text></svg>"#,
+        total
+    )
+    .unwrap();
+
+    svg
+}
+
+/// Horizontal bar split by status, for one row of the per-directory table
+fn stacked_bar(counts: &[usize; ZipFileStatus::CODES.len()]) -> String {
+    let total: usize = counts.iter().sum::<usize>().max(1);
+    let mut bar = String::from(r#"<div class="bar">"#);
+
+    for (i, count) in counts.iter().enumerate() {
+        if *count > 0 {
+            write!(
+                bar,
+                r#"<div style="background:{};width:{:.2}%" title="{}: {}"></div>"#,
+                STATUS_COLOURS[i],
+                *count as f64 * 100.0 / total as f64,
+                ZipFileStatus::CODES[i],
+                count
+            )
+            .unwrap();
+        }
+    }
+
+    bar.push_str("</div>");
+    bar
+}
+
+/// Message of a status, followed by the failing entries collected with `--all-errors`
+fn message_cell(status: &ZipFileStatus) -> String {
+    let mut cell = escape(&status.message().unwrap_or_default());
+
+    if let ZipFileStatus::Corrupted(corruption) | ZipFileStatus::PolicyViolation(corruption) =
+        status
+        && !corruption.entries.is_empty()
+    {
+        cell.push_str("<ul class=\"entries\">");
+
+        for entry in &corruption.entries {
+            write!(cell, "<li>{}</li>", escape(&entry.describe())).unwrap();
+        }
+
+        cell.push_str("</ul>");
+    }
+
+    cell
+}
+
+fn render_report(result: &CheckResult, records: &[FileRecord], generated: &str) -> String {
+    let mut html = String::new();
+
+    write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>check-zip report</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>check-zip report</h1>\n<p class=\"meta\">Generated {}</p>\n",
+        STYLE,
+        escape(generated)
+    )
+    .unwrap();
+
+    // Summary totals
+    html.push_str("<div class=\"cards\">\n");
+
+    for (label, value) in [
+        ("Total", result.total),
+        ("Valid", result.valid),
+        ("Corrupted", result.corrupted),
+        ("Skipped", result.skipped),
+        ("Password protected", result.encrypted),
+        ("Not fully verified", result.suspicious),
+        ("Timed out", result.timed_out),
+        ("Unreadable files", result.unreadable),
+        ("Incomplete", result.incomplete),
+        ("Policy violations", result.policy_violations),
+        ("Unreadable directories", result.walk_errors),
+    ] {
+        writeln!(
+            html,
+            "<div class=\"card\"><div class=\"value\">{}</div>{}</div>",
+            value, label
+        )
+        .unwrap();
+    }
+
+    html.push_str("</div>\n");
+
+    // Status distribution
+    let counts = count_statuses(records.iter());
+
+    writeln!(
+        html,
+        "<h2>Status distribution</h2>\n<div class=\"chart\">{}<div class=\"legend\">",
+        donut_chart(&counts)
+    )
+    .unwrap();
+
+    for (i, code) in ZipFileStatus::CODES.iter().enumerate() {
+        writeln!(
+            html,
+            "<div><span style=\"background:{}\"></span>{}: {}</div>",
+            STATUS_COLOURS[i], code, counts[i]
+        )
+        .unwrap();
+    }
+
+    html.push_str("</div></div>\n");
+
+    // Per-directory breakdown
+    let mut directories = BTreeMap::<PathBuf, Vec<&FileRecord>>::new();
+
+    for record in records {
+        let directory = record.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        directories.entry(directory).or_default().push(record);
+    }
+
+    html.push_str("<h2>Directories</h2>\n<table>\n<thead><tr><th>Directory</th>");
+
+    for code in ZipFileStatus::CODES {
+        write!(html, "<th>{}</th>", code).unwrap();
+    }
+
+    html.push_str("<th>Distribution</th></tr></thead>\n<tbody>\n");
+
+    for (directory, entries) in &directories {
+        let counts = count_statuses(entries.iter().copied());
+        let name = directory.display().to_string();
+
+        write!(
+            html,
+            "<tr><td>{}</td>",
+            escape(if name.is_empty() { "." } else { &name })
+        )
+        .unwrap();
+
+        for count in counts {
+            write!(html, "<td class=\"num\">{}</td>", count).unwrap();
+        }
+
+        writeln!(html, "<td>{}</td></tr>", stacked_bar(&counts)).unwrap();
+    }
+
+    html.push_str("</tbody>\n</table>\n");
+
+    // Every archive
+    html.push_str(
+        "<h2>Archives</h2>\n<div class=\"filters\">\
+         <input id=\"search\" type=\"search\" placeholder=\"Filter archives\" oninput=\"filterArchives()\">\
+         <select id=\"status\" onchange=\"filterArchives()\"><option value=\"\">All statuses</option>",
+    );
+
+    for code in ZipFileStatus::CODES {
+        write!(html, "<option>{}</option>", code).unwrap();
+    }
+
+    html.push_str(
+        "</select><select id=\"error\" onchange=\"filterArchives()\">\
+         <option value=\"\">All errors</option>",
+    );
+
+    // Only the kinds of error that occurred
+    for kind in ErrorKind::ALL {
+        if records
+            .iter()
+            .any(|record| record.status.error_kind() == Some(kind))
+        {
+            write!(html, "<option>{}</option>", kind).unwrap();
+        }
+    }
+
+    html.push_str(
+        "</select></div>\n<table id=\"archives\">\n<thead><tr><th>Archive</th><th>Status</th>\
+         <th>Size</th><th>Message</th></tr></thead>\n<tbody>\n",
+    );
+
+    for record in records {
+        let code = record.status.code();
+
+        writeln!(
+            html,
+            "<tr data-status=\"{}\" data-error=\"{}\"><td>{}</td><td class=\"status\" style=\"color:{}\">{}</td>\
+             <td class=\"num\" data-sort=\"{}\">{}</td><td>{}</td></tr>",
+            code,
+            record
+                .status
+                .error_kind()
+                .map(|kind| kind.code())
+                .unwrap_or_default(),
+            escape(&record.path.display().to_string()),
+            colour(code),
+            code,
+            record.size,
+            indicatif::HumanBytes(record.size),
+            message_cell(&record.status)
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        html,
+        "</tbody>\n</table>\n<script>{}</script>\n</body>\n</html>",
+        SCRIPT
+    )
+    .unwrap();
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, status: ZipFileStatus) -> FileRecord {
+        FileRecord {
+            path: PathBuf::from(path),
+            root: PathBuf::from("."),
+            size: 2048,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_render_report() {
+        let result = CheckResult {
+            total: 2,
+            valid: 1,
+            corrupted: 1,
+            ..Default::default()
+        };
+        let records = vec![
+            record("a/ok.zip", ZipFileStatus::Valid(None)),
+            record(
+                "b/<bad>.zip",
+                ZipFileStatus::corrupted(ErrorKind::NotAZip, String::from("Invalid & broken")),
+            ),
+        ];
+
+        let html = render_report(&result, &records, "2026-01-07 09:30:00");
+
+        assert!(html.contains("<td>b/&lt;bad&gt;.zip</td>"));
+        assert!(html.contains("<td>Invalid &amp; broken</td>"));
+        assert!(html.contains("<tr data-status=\"corrupted\" data-error=\"not_a_zip\">"));
+        assert!(html.contains("<option>not_a_zip</option>"));
+        assert!(!html.contains("<option>crc_mismatch</option>"));
+        assert!(html.contains("<tr><td>a</td>"));
+        assert!(html.contains("2.00 KiB"));
+    }
+
+    #[test]
+    fn test_render_report_is_self_contained() {
+        let html = render_report(&CheckResult::default(), &[], "now");
+
+        assert!(!html.contains("http://"));
+        assert!(!html.contains("https://"));
+        assert!(!html.contains(" src="));
+    }
+}
@@ -0,0 +1,228 @@
+//! Telling archives that are still being downloaded or written apart from
+//! corrupted ones, so they are left alone until they are complete.
+
+use std::fs::{File, Metadata};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::format::{FLAG_DATA_DESCRIPTOR, LOCAL_FILE_HEADER_SIGNATURE, LocalFileHeader, read_u32};
+
+/// Extensions of unfinished downloads, or of markers next to them: Chrome and
+/// Edge, Firefox, Safari, and aria2's control file
+const PARTIAL_EXTENSIONS: [&str; 4] = ["crdownload", "part", "download", "aria2"];
+
+/// Why an archive looks like it is still being transferred
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum IncompleteReason {
+    /// The file is empty
+    Empty,
+    /// The file itself is named like an unfinished download
+    PartialName,
+    /// A download manager keeps a marker of an unfinished download next to it
+    Sidecar(PathBuf),
+    /// The file changed while it was checked
+    Changed { before: u64, after: u64 },
+    /// Every entry is there, but the file ends where the central directory should start
+    MissingCentralDirectory,
+}
+
+impl IncompleteReason {
+    /// Explanation shown next to the status
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            IncompleteReason::Empty => String::from("File is empty"),
+            IncompleteReason::PartialName => {
+                String::from("File is named like an unfinished download")
+            }
+            IncompleteReason::Sidecar(marker) => {
+                format!("Unfinished download marker {} next to it", marker.display())
+            }
+            IncompleteReason::Changed { before, after } if before == after => {
+                String::from("File was modified during the check")
+            }
+            IncompleteReason::Changed { before, after } => format!(
+                "File changed from {} to {} bytes during the check",
+                before, after
+            ),
+            IncompleteReason::MissingCentralDirectory => {
+                String::from("File ends right before the central directory")
+            }
+        }
+    }
+}
+
+/// Size and modification time of a file, compared before and after a check
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Snapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl From<&Metadata> for Snapshot {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+impl Snapshot {
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        path.metadata()
+            .ok()
+            .map(|metadata| Snapshot::from(&metadata))
+    }
+}
+
+/// Look for signs of an unfinished transfer before the archive is read
+pub(crate) fn detect_before(path: &Path, snapshot: Option<Snapshot>) -> Option<IncompleteReason> {
+    if snapshot.is_some_and(|snapshot| snapshot.len == 0) {
+        return Some(IncompleteReason::Empty);
+    }
+
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    if PARTIAL_EXTENSIONS.contains(&extension) {
+        return Some(IncompleteReason::PartialName);
+    }
+
+    sidecar(path).map(IncompleteReason::Sidecar)
+}
+
+/// Compare the file with how it was before the check
+pub(crate) fn detect_after(path: &Path, before: Option<Snapshot>) -> Option<IncompleteReason> {
+    let (before, after) = (before?, Snapshot::of(path)?);
+
+    (before != after).then_some(IncompleteReason::Changed {
+        before: before.len,
+        after: after.len,
+    })
+}
+
+/// Find a download manager's marker next to the file, such as `a.zip.part`
+fn sidecar(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+
+    PARTIAL_EXTENSIONS
+        .iter()
+        .map(|extension| path.with_file_name(format!("{}.{}", name, extension)))
+        .find(|marker| marker.exists())
+}
+
+/// Whether a file without central directory holds complete entries up to its
+/// very end, as when the writer has not got to the central directory yet
+pub(crate) fn ends_before_central_directory(path: &Path) -> bool {
+    File::open(path)
+        .and_then(|file| walk_local_entries(&mut BufReader::new(file)))
+        .unwrap_or(false)
+}
+
+fn walk_local_entries<R: Read + Seek>(reader: &mut R) -> io::Result<bool> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let mut position = reader.seek(SeekFrom::Start(0))?;
+
+    while position < len {
+        if read_u32(reader)? != LOCAL_FILE_HEADER_SIGNATURE {
+            return Ok(false);
+        }
+
+        let header = LocalFileHeader::read(reader)?;
+
+        // The size of the data is only known after it, so its end can't be found
+        if header.flags & FLAG_DATA_DESCRIPTOR != 0 {
+            return Ok(false);
+        }
+
+        position = reader.seek(SeekFrom::Current(header.compressed_size as i64))?;
+    }
+
+    Ok(position == len && len > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn archive() -> Vec<u8> {
+        let mut data = vec![];
+        let mut zip = zip::ZipWriter::new(Cursor::new(&mut data));
+
+        for name in ["a.txt", "b.txt"] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"Hello, World!").unwrap();
+        }
+
+        zip.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_detect_before() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let empty = temp_dir.path().join("empty.zip");
+        let downloading = temp_dir.path().join("a.zip");
+        let marker = temp_dir.path().join("a.zip.part");
+        let partial = temp_dir.path().join("b.zip.crdownload");
+
+        fs::write(&empty, b"").unwrap();
+        fs::write(&downloading, b"PK").unwrap();
+        fs::write(&marker, b"").unwrap();
+        fs::write(&partial, b"PK").unwrap();
+
+        assert_eq!(
+            detect_before(&empty, Snapshot::of(&empty)),
+            Some(IncompleteReason::Empty)
+        );
+        assert_eq!(
+            detect_before(&downloading, Snapshot::of(&downloading)),
+            Some(IncompleteReason::Sidecar(marker))
+        );
+        assert_eq!(
+            detect_before(&partial, Snapshot::of(&partial)),
+            Some(IncompleteReason::PartialName)
+        );
+
+        fs::remove_file(temp_dir.path().join("a.zip.part")).unwrap();
+        assert_eq!(
+            detect_before(&downloading, Snapshot::of(&downloading)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_after() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.zip");
+
+        fs::write(&path, b"PK").unwrap();
+        let before = Snapshot::of(&path);
+
+        assert_eq!(detect_after(&path, before), None);
+
+        fs::write(&path, b"PK\x03\x04").unwrap();
+
+        assert_eq!(
+            detect_after(&path, before),
+            Some(IncompleteReason::Changed {
+                before: 2,
+                after: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_ends_before_central_directory() {
+        let data = archive();
+        let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+
+        assert!(walk_local_entries(&mut Cursor::new(&data[..central])).unwrap());
+        // Cut in the middle of the second entry
+        assert!(!walk_local_entries(&mut Cursor::new(&data[..central - 5])).unwrap_or(false));
+        assert!(!walk_local_entries(&mut Cursor::new(&data[..])).unwrap());
+    }
+}
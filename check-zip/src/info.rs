@@ -0,0 +1,443 @@
+//! `info` subcommand: who made an archive and when, from its central
+//! directory, as text or JSON.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, NaiveDateTime};
+use clap::{Args, ValueEnum};
+use colour::e_red;
+use serde::Serialize;
+use zip::ZipArchive;
+
+use crate::exit;
+use crate::format::{
+    FLAG_DATA_DESCRIPTOR, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE, ZIP64_EXTRA_FIELD,
+    dos_datetime, extra_field_name, extra_fields, extra_modified_time, host_system_name,
+    read_central_directory, read_u32,
+};
+use crate::names::{NameEncoding, NameSource, decode_name};
+use crate::search::{default_jobs, search_roots};
+use crate::walk::{run_workers, walk};
+
+/// Host systems whose external attributes hold Unix permissions in the upper 16 bits
+const UNIX_HOSTS: [u8; 2] = [3, 19];
+
+#[derive(Args)]
+pub(crate) struct InfoArgs {
+    /// Archives and folders to describe [default: current directory]
+    pub paths: Vec<PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "text")]
+    pub format: InfoFormat,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum InfoFormat {
+    /// One block of aligned fields per archive
+    Text,
+    /// A JSON array with one object per archive
+    Json,
+}
+
+/// Metadata of one archive
+#[derive(Default, Serialize)]
+struct ArchiveInfo {
+    path: String,
+    /// Why the archive could not be read; nothing else is filled in then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    entries: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    /// Range of the MS-DOS timestamps, in the creator's local time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dos_modified: Option<TimeRange>,
+    /// Range of the extended timestamp and NTFS extra fields, in UTC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra_modified: Option<TimeRange>,
+    created_by: Vec<CreatorCount>,
+    unix_permissions: Vec<PermissionCount>,
+    /// Whether the archive has a Zip64 end record or entries with Zip64 fields
+    zip64: bool,
+    extra_fields: Vec<ExtraFieldCount>,
+    name_encodings: Vec<NameEncodingCount>,
+    /// Entries whose CRC and sizes follow their data
+    data_descriptors: usize,
+}
+
+#[derive(Serialize)]
+struct TimeRange {
+    oldest: String,
+    newest: String,
+}
+
+#[derive(Serialize)]
+struct CreatorCount {
+    /// Host system of "version made by"
+    host: String,
+    /// ZIP specification version the creator implements
+    version: String,
+    entries: usize,
+}
+
+#[derive(Serialize)]
+struct PermissionCount {
+    /// Octal permission bits, such as `0644`
+    mode: String,
+    entries: usize,
+}
+
+#[derive(Serialize)]
+struct ExtraFieldCount {
+    /// Hexadecimal ID, such as `0x5455`
+    id: String,
+    name: String,
+    entries: usize,
+}
+
+#[derive(Serialize)]
+struct NameEncodingCount {
+    /// How the names were decoded, such as `UTF-8 flag` or `GBK`
+    encoding: String,
+    entries: usize,
+    /// Names whose bytes are invalid in that encoding
+    invalid: usize,
+}
+
+/// Oldest and newest of the values seen
+#[derive(Default)]
+struct Range<T>(Option<(T, T)>);
+
+impl<T: Copy + Ord> Range<T> {
+    fn add(&mut self, value: T) {
+        self.0 = Some(match self.0 {
+            Some((oldest, newest)) => (oldest.min(value), newest.max(value)),
+            None => (value, value),
+        });
+    }
+
+    fn format(&self, format: impl Fn(T) -> String) -> Option<TimeRange> {
+        self.0.map(|(oldest, newest)| TimeRange {
+            oldest: format(oldest),
+            newest: format(newest),
+        })
+    }
+}
+
+fn archive_info(path: &Path, encoding: NameEncoding) -> io::Result<ArchiveInfo> {
+    let archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+
+    let mut info = ArchiveInfo {
+        path: path.display().to_string(),
+        entries: archive.len(),
+        comment: (!archive.comment().is_empty())
+            .then(|| String::from_utf8_lossy(archive.comment()).into_owned()),
+        ..Default::default()
+    };
+
+    let central_directory_start = archive.central_directory_start();
+    let mut reader = archive.into_inner();
+    let headers = read_central_directory(&mut reader, central_directory_start, info.entries)?;
+
+    let mut dos_modified: Range<NaiveDateTime> = Range::default();
+    let mut extra_modified: Range<i64> = Range::default();
+    let mut creators: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut permissions: BTreeMap<u32, usize> = BTreeMap::new();
+    let mut extra_ids: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut name_sources: BTreeMap<NameSource, (usize, usize)> = BTreeMap::new();
+
+    for header in headers {
+        if let Some(modified) = dos_datetime(header.modified_date, header.modified_time) {
+            dos_modified.add(modified);
+        }
+
+        if let Some(modified) = extra_modified_time(&header.extra) {
+            extra_modified.add(modified);
+        }
+
+        *creators.entry(header.version_made_by).or_default() += 1;
+
+        let host = (header.version_made_by >> 8) as u8;
+        let mode = header.external_attributes >> 16;
+
+        if UNIX_HOSTS.contains(&host) && mode != 0 {
+            *permissions.entry(mode & 0o7777).or_default() += 1;
+        }
+
+        for (id, _) in extra_fields(&header.extra) {
+            *extra_ids.entry(id).or_default() += 1;
+        }
+
+        if header.flags & FLAG_DATA_DESCRIPTOR != 0 {
+            info.data_descriptors += 1;
+        }
+
+        let name = decode_name(&header.name, header.flags, &header.extra, encoding);
+        let (entries, invalid) = name_sources.entry(name.source).or_default();
+        *entries += 1;
+        *invalid += usize::from(!name.valid);
+    }
+
+    // The Zip64 end record, if any, directly follows the central directory
+    info.zip64 = extra_ids.contains_key(&ZIP64_EXTRA_FIELD)
+        || read_u32(&mut reader)
+            .is_ok_and(|signature| signature == ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+
+    info.dos_modified = dos_modified.format(|time| time.format("%Y-%m-%dT%H:%M:%S").to_string());
+    info.extra_modified = extra_modified.format(|seconds| {
+        DateTime::from_timestamp(seconds, 0)
+            .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_else(|| seconds.to_string())
+    });
+
+    info.created_by = creators
+        .into_iter()
+        .map(|(version_made_by, entries)| {
+            let version = version_made_by & 0xFF;
+
+            CreatorCount {
+                host: host_system_name((version_made_by >> 8) as u8).to_string(),
+                version: format!("{}.{}", version / 10, version % 10),
+                entries,
+            }
+        })
+        .collect();
+
+    info.unix_permissions = permissions
+        .into_iter()
+        .map(|(mode, entries)| PermissionCount {
+            mode: format!("{:04o}", mode),
+            entries,
+        })
+        .collect();
+
+    info.extra_fields = extra_ids
+        .into_iter()
+        .map(|(id, entries)| ExtraFieldCount {
+            id: format!("0x{:04x}", id),
+            name: extra_field_name(id).to_string(),
+            entries,
+        })
+        .collect();
+
+    info.name_encodings = name_sources
+        .into_iter()
+        .map(|(source, (entries, invalid))| NameEncodingCount {
+            encoding: source.label().to_string(),
+            entries,
+            invalid,
+        })
+        .collect();
+
+    Ok(info)
+}
+
+/// Text block of one archive
+fn render_text(info: &ArchiveInfo) -> String {
+    let mut fields: Vec<(&str, String)> = vec![("Entries", info.entries.to_string())];
+
+    if let Some(comment) = &info.comment {
+        fields.push(("Comment", comment.clone()));
+    }
+
+    for (label, range) in [
+        ("Modified (DOS)", &info.dos_modified),
+        ("Modified (extra)", &info.extra_modified),
+    ] {
+        if let Some(range) = range {
+            fields.push((label, format!("{} to {}", range.oldest, range.newest)));
+        }
+    }
+
+    let join = |values: Vec<String>| {
+        if values.is_empty() {
+            String::from("none")
+        } else {
+            values.join(", ")
+        }
+    };
+
+    fields.push((
+        "Created by",
+        join(
+            info.created_by
+                .iter()
+                .map(|c| format!("{} {} ({})", c.host, c.version, c.entries))
+                .collect(),
+        ),
+    ));
+    fields.push((
+        "Unix permissions",
+        join(
+            info.unix_permissions
+                .iter()
+                .map(|p| format!("{} ({})", p.mode, p.entries))
+                .collect(),
+        ),
+    ));
+    fields.push(("Zip64", String::from(if info.zip64 { "yes" } else { "no" })));
+    fields.push((
+        "Extra fields",
+        join(
+            info.extra_fields
+                .iter()
+                .map(|f| format!("{} {} ({})", f.id, f.name, f.entries))
+                .collect(),
+        ),
+    ));
+    fields.push((
+        "Name encodings",
+        join(
+            info.name_encodings
+                .iter()
+                .map(|n| match n.invalid {
+                    0 => format!("{} ({})", n.encoding, n.entries),
+                    invalid => format!("{} ({}, {} invalid)", n.encoding, n.entries, invalid),
+                })
+                .collect(),
+        ),
+    ));
+    fields.push((
+        "Data descriptors",
+        format!("{} of {} entries", info.data_descriptors, info.entries),
+    ));
+
+    let mut text = format!("📦 {}\n", info.path);
+
+    for (label, value) in fields {
+        text.push_str(&format!("   {:<18}{}\n", format!("{}:", label), value));
+    }
+
+    text
+}
+
+/// Run the `info` subcommand and return its exit code
+pub(crate) fn info(args: InfoArgs, encoding: NameEncoding) -> u8 {
+    let paths = match search_roots(args.paths) {
+        Ok(paths) => paths,
+        Err(path) => {
+            e_red!("❌ Path does not exist: {}\n", path.display());
+            return exit::USAGE;
+        }
+    };
+
+    let infos = Arc::new(Mutex::new(vec![]));
+    let sink = infos.clone();
+
+    run_workers(
+        walk(paths).paths,
+        false,
+        default_jobs(args.jobs),
+        move |path| {
+            let info = archive_info(&path, encoding).unwrap_or_else(|e| ArchiveInfo {
+                path: path.display().to_string(),
+                error: Some(e.to_string()),
+                ..Default::default()
+            });
+
+            sink.lock().unwrap().push(info);
+        },
+    );
+
+    let mut infos = std::mem::take(&mut *infos.lock().unwrap());
+    infos.sort_by(|a, b| a.path.cmp(&b.path));
+
+    for info in &infos {
+        if let Some(error) = &info.error {
+            e_red!("❌ Cannot read {}: {}\n", info.path, error);
+        }
+    }
+
+    match args.format {
+        InfoFormat::Text => {
+            let blocks: Vec<String> = infos
+                .iter()
+                .filter(|info| info.error.is_none())
+                .map(render_text)
+                .collect();
+
+            print!("{}", blocks.join("\n"));
+        }
+        InfoFormat::Json => match serde_json::to_string_pretty(&infos) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                e_red!("❌ Cannot write JSON: {}\n", e);
+                return exit::USAGE;
+            }
+        },
+    }
+
+    if infos.iter().any(|info| info.error.is_some()) {
+        exit::CORRUPTED
+    } else {
+        exit::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::DateTime as ZipDateTime;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_archive_info() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.zip");
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default()
+            .unix_permissions(0o644)
+            .last_modified_time(ZipDateTime::from_date_and_time(2021, 6, 1, 12, 0, 0).unwrap());
+
+        zip.set_comment("Built by CI");
+        zip.start_file("a.txt", options).unwrap();
+        zip.write_all(b"Hello").unwrap();
+        zip.start_file(
+            "run.sh",
+            options
+                .unix_permissions(0o755)
+                .last_modified_time(ZipDateTime::from_date_and_time(2023, 1, 2, 3, 4, 6).unwrap()),
+        )
+        .unwrap();
+        zip.write_all(b"#!/bin/sh").unwrap();
+        zip.finish().unwrap();
+
+        let info = archive_info(&path, NameEncoding::Auto).unwrap();
+
+        assert_eq!(info.entries, 2);
+        assert_eq!(info.comment.as_deref(), Some("Built by CI"));
+
+        let dos = info.dos_modified.as_ref().unwrap();
+        assert_eq!(dos.oldest, "2021-06-01T12:00:00");
+        assert_eq!(dos.newest, "2023-01-02T03:04:06");
+
+        assert_eq!(info.created_by[0].host, "Unix");
+        assert_eq!(info.created_by[0].entries, 2);
+        assert_eq!(
+            info.unix_permissions
+                .iter()
+                .map(|p| p.mode.as_str())
+                .collect::<Vec<_>>(),
+            vec!["0644", "0755"]
+        );
+        assert!(!info.zip64);
+        assert_eq!(info.data_descriptors, 0);
+
+        let text = render_text(&info);
+        assert!(text.contains("   Comment:          Built by CI\n"));
+        assert!(text.contains("   Zip64:            no\n"));
+        assert!(text.contains("   Name encodings:   ASCII (2)\n"));
+    }
+}
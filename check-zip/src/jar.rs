@@ -0,0 +1,813 @@
+//! Checks of Java archives (JAR, WAR, EAR) beyond the ZIP structure: the
+//! syntax of the manifest, the digests of signed jars, class file headers and
+//! entries no signature covers.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek};
+use std::ops::Range;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use zip::ZipArchive;
+
+use crate::error::ErrorKind;
+use crate::names::{NameEncoding, zip_file_name};
+
+/// Extensions of Java archives, checked as such even without a manifest
+pub(crate) const JAR_EXTENSIONS: [&str; 3] = ["jar", "war", "ear"];
+
+const MANIFEST_NAME: &str = "META-INF/MANIFEST.MF";
+
+/// Longest manifest line in bytes, without its line break
+const MAX_LINE_LENGTH: usize = 72;
+
+/// Longest attribute name
+const MAX_NAME_LENGTH: usize = 70;
+
+/// Extensions of the signature block files next to a `.SF` file
+const SIGNATURE_BLOCK_EXTENSIONS: [&str; 3] = ["RSA", "DSA", "EC"];
+
+const CLASS_MAGIC: u32 = 0xCAFE_BABE;
+/// Java 1.1. There is no upper bound, as every Java release adds a version.
+const MIN_CLASS_VERSION: u16 = 45;
+
+/// A problem found in a Java archive
+#[derive(Debug)]
+pub(crate) struct JarFinding {
+    pub index: usize,
+    pub name: String,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// Section of a manifest or signature file
+#[derive(Debug, Default)]
+struct Section {
+    attributes: Vec<(String, String)>,
+    /// Bytes of the section up to and including the blank line ending it,
+    /// which is what signature files digest
+    raw: Range<usize>,
+}
+
+impl Section {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `(algorithm, value)` of every attribute named `<algorithm><suffix>`,
+    /// such as `SHA-256-Digest`
+    fn digests<'a>(&'a self, suffix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.attributes.iter().filter_map(move |(key, value)| {
+            let split = key.len().checked_sub(suffix.len())?;
+
+            (key.is_char_boundary(split) && key[split..].eq_ignore_ascii_case(suffix))
+                .then(|| (&key[..split], value.as_str()))
+        })
+    }
+}
+
+/// A parsed manifest or signature file
+#[derive(Debug, Default)]
+struct Manifest {
+    main: Section,
+    /// Sections of individual entries, each starting with a `Name` attribute
+    entries: Vec<Section>,
+    /// Index in `entries` of the first section of each name
+    by_name: HashMap<String, usize>,
+}
+
+impl Manifest {
+    fn entry(&self, name: &str) -> Option<&Section> {
+        self.by_name.get(name).map(|&i| &self.entries[i])
+    }
+}
+
+/// Parse a manifest or signature file, failing on the first line that breaks
+/// the JAR manifest syntax
+fn parse_manifest(data: &[u8]) -> Result<Manifest, String> {
+    let mut manifest = Manifest::default();
+    let mut section: Option<Section> = Some(Section::default());
+    // Attribute being read, which continuation lines append to
+    let mut attribute: Option<(String, Vec<u8>)> = None;
+    let mut position = 0;
+    let mut number = 0;
+
+    while position < data.len() {
+        number += 1;
+
+        let rest = &data[position..];
+        let (length, break_length) = match rest.iter().position(|&b| b == b'\r' || b == b'\n') {
+            Some(i) if rest[i..].starts_with(b"\r\n") => (i, 2),
+            Some(i) => (i, 1),
+            None => return Err(format!("line {} doesn't end with a line break", number)),
+        };
+        let line = &rest[..length];
+        let start = position;
+        position += length + break_length;
+
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(format!(
+                "line {} is {} bytes long, more than {}",
+                number,
+                line.len(),
+                MAX_LINE_LENGTH
+            ));
+        }
+
+        if let Some(continued) = line.strip_prefix(b" ") {
+            match attribute.as_mut() {
+                Some((_, value)) => value.extend_from_slice(continued),
+                None => return Err(format!("line {} continues no attribute", number)),
+            }
+            continue;
+        }
+
+        if let Some(current) = section.as_mut() {
+            finish_attribute(current, attribute.take(), number - 1)?;
+        }
+
+        if line.is_empty() {
+            // A blank line ends the current section; more of them are skipped
+            if let Some(mut current) = section.take() {
+                current.raw.end = position;
+
+                if manifest.main.raw.end == 0 {
+                    manifest.main = current;
+                } else {
+                    manifest.entries.push(current);
+                }
+            }
+            continue;
+        }
+
+        let Some(separator) = line.windows(2).position(|w| w == b": ") else {
+            return Err(format!("line {} is not a `Name: value` attribute", number));
+        };
+        let name = &line[..separator];
+
+        if name.is_empty()
+            || name.len() > MAX_NAME_LENGTH
+            || !name
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(format!(
+                "line {} has an invalid attribute name `{}`",
+                number,
+                String::from_utf8_lossy(name)
+            ));
+        }
+
+        let name = String::from_utf8_lossy(name).into_owned();
+
+        if section.is_none() {
+            if !name.eq_ignore_ascii_case("Name") {
+                return Err(format!(
+                    "section at line {} starts with {} instead of Name",
+                    number, name
+                ));
+            }
+
+            section = Some(Section {
+                raw: start..start,
+                ..Default::default()
+            });
+        }
+
+        attribute = Some((name, line[separator + 2..].to_vec()));
+    }
+
+    if let Some(mut current) = section {
+        finish_attribute(&mut current, attribute, number)?;
+        current.raw.end = data.len();
+
+        if manifest.main.raw.end == 0 && manifest.entries.is_empty() {
+            manifest.main = current;
+        } else if !current.attributes.is_empty() {
+            manifest.entries.push(current);
+        }
+    }
+
+    for (i, section) in manifest.entries.iter().enumerate() {
+        if let Some(name) = section.get("Name") {
+            manifest.by_name.entry(name.to_string()).or_insert(i);
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Add an attribute whose value is complete to `section`
+fn finish_attribute(
+    section: &mut Section,
+    attribute: Option<(String, Vec<u8>)>,
+    number: usize,
+) -> Result<(), String> {
+    if let Some((name, value)) = attribute {
+        let value = String::from_utf8(value)
+            .map_err(|_| format!("attribute {} ending at line {} isn't UTF-8", name, number))?;
+
+        section.attributes.push((name, value));
+    }
+
+    Ok(())
+}
+
+/// Incremental digest in one of the algorithms signed jars use
+enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    /// Hasher of an algorithm named as in digest attributes, such as `SHA-256` or `SHA1`
+    fn new(algorithm: &str) -> Option<Self> {
+        match algorithm.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Some(Hasher::Sha1(Sha1::new())),
+            "SHA256" => Some(Hasher::Sha256(Sha256::new())),
+            "SHA384" => Some(Hasher::Sha384(Sha384::new())),
+            "SHA512" => Some(Hasher::Sha512(Sha512::new())),
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha384(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha384(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Whether the base64 `expected` digest of `data` is right, or `None` for
+/// algorithms that can't be computed
+fn digest_matches(algorithm: &str, expected: &str, data: &[u8]) -> Option<bool> {
+    let mut hasher = Hasher::new(algorithm)?;
+    hasher.update(data);
+
+    Some(is_digest(expected, hasher.finalize()))
+}
+
+/// Whether `digest` is the base64 `expected` one
+fn is_digest(expected: &str, digest: Vec<u8>) -> bool {
+    STANDARD.decode(expected.trim()).ok() == Some(digest)
+}
+
+/// Whether an archive is a Java archive, by its extension or its manifest
+pub(crate) fn is_jar<R: Read + Seek>(path: &Path, archive: &ZipArchive<R>) -> bool {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    JAR_EXTENSIONS.contains(&extension.as_str()) || archive.index_for_name(MANIFEST_NAME).is_some()
+}
+
+/// Whether an entry is part of the signature itself, and so not signed
+fn is_signature_related(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+
+    let Some(file) = upper.strip_prefix("META-INF/") else {
+        return false;
+    };
+
+    !file.contains('/')
+        && (file == "MANIFEST.MF"
+            || file.starts_with("SIG-")
+            || file.ends_with(".SF")
+            || SIGNATURE_BLOCK_EXTENSIONS
+                .iter()
+                .any(|ext| file.strip_suffix(ext).is_some_and(|f| f.ends_with('.'))))
+}
+
+/// Upper-case base name of a signature file, such as `CERT` for `META-INF/CERT.SF`
+fn signature_file_base(name: &str) -> Option<String> {
+    name.to_ascii_uppercase()
+        .strip_prefix("META-INF/")
+        .and_then(|file| file.strip_suffix(".SF"))
+        .filter(|base| !base.contains('/'))
+        .map(String::from)
+}
+
+/// Whether an archive is signed as a jar, which APKs call signature scheme v1
+pub(crate) fn has_signature_file<R: Read + Seek>(archive: &ZipArchive<R>) -> bool {
+    archive
+        .file_names()
+        .any(|name| signature_file_base(name).is_some())
+}
+
+/// Problem with the header of a class file, if it has one
+fn check_class(header: &[u8]) -> Option<String> {
+    let [a, b, c, d, e, f, g, h, ..] = *header else {
+        return Some(format!(
+            "is {} bytes, too short for a class file",
+            header.len()
+        ));
+    };
+
+    let magic = u32::from_be_bytes([a, b, c, d]);
+    let minor = u16::from_be_bytes([e, f]);
+    let major = u16::from_be_bytes([g, h]);
+
+    if magic != CLASS_MAGIC {
+        Some(format!("starts with {:08X} instead of CAFEBABE", magic))
+    } else if major < MIN_CLASS_VERSION {
+        Some(format!(
+            "has class file version {}.{}, older than {}",
+            major, minor, MIN_CLASS_VERSION
+        ))
+    } else {
+        None
+    }
+}
+
+/// Problems of a signature file against the manifest, and the names of the
+/// entries it covers
+fn check_signature_file(
+    signature: &Manifest,
+    manifest: &Manifest,
+    manifest_data: &[u8],
+) -> (Vec<String>, HashSet<String>) {
+    let mut problems = vec![];
+    let whole = signature
+        .main
+        .digests("-Digest-Manifest")
+        .filter_map(|(algorithm, value)| digest_matches(algorithm, value, manifest_data))
+        .collect::<Vec<_>>();
+
+    // A digest of the whole manifest covers every entry in it; when it is
+    // missing or the manifest grew since signing, each section is checked
+    if !whole.is_empty() && whole.iter().all(|&matches| matches) {
+        let names = manifest
+            .entries
+            .iter()
+            .filter_map(|section| section.get("Name").map(String::from))
+            .collect();
+
+        return (problems, names);
+    }
+
+    let main_data = &manifest_data[manifest.main.raw.clone()];
+
+    for (algorithm, value) in signature.main.digests("-Digest-Manifest-Main-Attributes") {
+        if digest_matches(algorithm, value, main_data) == Some(false) {
+            problems.push(format!(
+                "{} digest of the manifest main attributes doesn't match",
+                algorithm
+            ));
+        }
+    }
+
+    let mut covered = HashSet::new();
+
+    for section in &signature.entries {
+        let Some(name) = section.get("Name") else {
+            continue;
+        };
+
+        let Some(entry) = manifest.entry(name) else {
+            problems.push(format!("{} is signed but not in the manifest", name));
+            continue;
+        };
+
+        let data = &manifest_data[entry.raw.clone()];
+        let mut matched = false;
+
+        for (algorithm, value) in section.digests("-Digest") {
+            match digest_matches(algorithm, value, data) {
+                Some(true) => matched = true,
+                Some(false) => problems.push(format!(
+                    "{} digest of the manifest section of {} doesn't match",
+                    algorithm, name
+                )),
+                None => {}
+            }
+        }
+
+        if matched {
+            covered.insert(name.to_string());
+        }
+    }
+
+    (problems, covered)
+}
+
+/// Read the data of an entry, or `None` when it can't be read, which the
+/// ZIP check already reported
+fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    limit: u64,
+) -> Option<Vec<u8>> {
+    let mut data = vec![];
+
+    archive
+        .by_index(index)
+        .ok()?
+        .take(limit)
+        .read_to_end(&mut data)
+        .ok()?;
+
+    Some(data)
+}
+
+/// Stream the data of an entry through every hasher, returning `false` when
+/// it can't be read, which the ZIP check already reported
+fn hash_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    hashers: &mut [Hasher],
+) -> bool {
+    let Ok(mut file) = archive.by_index(index) else {
+        return false;
+    };
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => return true,
+            Ok(n) => hashers
+                .iter_mut()
+                .for_each(|hasher| hasher.update(&buf[..n])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Check the manifest, signatures and class files of a Java archive whose
+/// entries are all readable
+pub(crate) fn check_jar<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name_encoding: NameEncoding,
+) -> Vec<JarFinding> {
+    let mut entries = vec![];
+
+    for i in 0..archive.len() {
+        if let Ok(file) = archive.by_index_raw(i) {
+            entries.push((i, zip_file_name(&file, name_encoding), file.is_dir()));
+        }
+    }
+
+    let mut findings = vec![];
+    let mut finding = |index: usize, name: &str, kind: ErrorKind, message: String| {
+        findings.push(JarFinding {
+            index,
+            name: name.to_string(),
+            kind,
+            message,
+        });
+    };
+
+    let manifest_entry = entries
+        .iter()
+        .find(|(_, name, _)| name.eq_ignore_ascii_case(MANIFEST_NAME));
+    let manifest = manifest_entry.and_then(|(index, name, _)| {
+        let data = read_entry(archive, *index, u64::MAX)?;
+
+        match parse_manifest(&data) {
+            Ok(manifest) => Some((manifest, data)),
+            Err(e) => {
+                finding(
+                    *index,
+                    name,
+                    ErrorKind::BadManifest,
+                    format!("Invalid manifest {}: {}", name, e),
+                );
+                None
+            }
+        }
+    });
+
+    // Signature files and the entries covered by any of them
+    let mut signed = false;
+    let mut covered = HashSet::new();
+
+    for (index, name, _) in &entries {
+        let Some(base) = signature_file_base(name) else {
+            continue;
+        };
+
+        signed = true;
+
+        let has_block = SIGNATURE_BLOCK_EXTENSIONS.iter().any(|ext| {
+            let block = format!("META-INF/{}.{}", base, ext);
+            entries
+                .iter()
+                .any(|(_, other, _)| other.eq_ignore_ascii_case(&block))
+        });
+
+        if !has_block {
+            finding(
+                *index,
+                name,
+                ErrorKind::BadSignature,
+                format!("Signature file {} has no signature block", name),
+            );
+        }
+
+        if manifest_entry.is_none() {
+            finding(
+                *index,
+                name,
+                ErrorKind::BadSignature,
+                format!(
+                    "Signature file {} signs a jar without {}",
+                    name, MANIFEST_NAME
+                ),
+            );
+        }
+
+        let Some(data) = read_entry(archive, *index, u64::MAX) else {
+            continue;
+        };
+
+        let signature = match parse_manifest(&data) {
+            Ok(signature) => signature,
+            Err(e) => {
+                finding(
+                    *index,
+                    name,
+                    ErrorKind::BadSignature,
+                    format!("Invalid signature file {}: {}", name, e),
+                );
+                continue;
+            }
+        };
+
+        if let Some((manifest, manifest_data)) = &manifest {
+            let (problems, names) = check_signature_file(&signature, manifest, manifest_data);
+
+            for problem in problems {
+                finding(
+                    *index,
+                    name,
+                    ErrorKind::BadSignature,
+                    format!("Signature file {}: {}", name, problem),
+                );
+            }
+
+            covered.extend(names);
+        }
+    }
+
+    for (index, name, is_dir) in &entries {
+        if *is_dir {
+            continue;
+        }
+
+        if name.ends_with(".class")
+            && let Some(header) = read_entry(archive, *index, 8)
+            && let Some(problem) = check_class(&header)
+        {
+            finding(
+                *index,
+                name,
+                ErrorKind::BadClass,
+                format!("Class file {} {}", name, problem),
+            );
+        }
+
+        let Some((manifest, _)) = manifest.as_ref().filter(|_| signed) else {
+            continue;
+        };
+
+        if is_signature_related(name) {
+            continue;
+        }
+
+        let section = manifest.entry(name);
+        let digests = section.map_or(vec![], |section| section.digests("-Digest").collect());
+
+        if digests.is_empty() {
+            finding(
+                *index,
+                name,
+                ErrorKind::UnsignedEntry,
+                format!("{} has no digest in the manifest of a signed jar", name),
+            );
+            continue;
+        }
+
+        if !covered.contains(name) {
+            finding(
+                *index,
+                name,
+                ErrorKind::UnsignedEntry,
+                format!("{} is covered by no signature file", name),
+            );
+        }
+
+        // Digests in algorithms that can't be computed are ignored
+        let (expected, mut hashers): (Vec<_>, Vec<_>) = digests
+            .into_iter()
+            .filter_map(|(algorithm, value)| Some(((algorithm, value), Hasher::new(algorithm)?)))
+            .unzip();
+
+        if hashers.is_empty() || !hash_entry(archive, *index, &mut hashers) {
+            continue;
+        }
+
+        for ((algorithm, value), hasher) in expected.into_iter().zip(hashers) {
+            if !is_digest(value, hasher.finalize()) {
+                finding(
+                    *index,
+                    name,
+                    ErrorKind::DigestMismatch,
+                    format!(
+                        "{} digest of {} doesn't match the manifest",
+                        algorithm, name
+                    ),
+                );
+            }
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    fn digest(algorithm: &str, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(algorithm).unwrap();
+        hasher.update(data);
+        STANDARD.encode(hasher.finalize())
+    }
+
+    /// A jar signed over `signed` that holds `written`, so entries can be
+    /// changed or added after signing
+    fn signed_jar(signed: &[(&str, &[u8])], written: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut manifest = String::from("Manifest-Version: 1.0\r\nCreated-By: test\r\n\r\n");
+        let mut signature = String::new();
+
+        for (name, data) in signed {
+            let section = format!(
+                "Name: {}\r\nSHA-256-Digest: {}\r\n\r\n",
+                name,
+                digest("SHA-256", data)
+            );
+            signature.push_str(&format!(
+                "Name: {}\r\nSHA-256-Digest: {}\r\n\r\n",
+                name,
+                digest("SHA-256", section.as_bytes())
+            ));
+            manifest.push_str(&section);
+        }
+
+        let signature = format!(
+            "Signature-Version: 1.0\r\nSHA-256-Digest-Manifest: {}\r\n\r\n{}",
+            digest("SHA-256", manifest.as_bytes()),
+            signature
+        );
+
+        let mut archive = vec![];
+        let mut zip = ZipWriter::new(Cursor::new(&mut archive));
+        let options = SimpleFileOptions::default();
+
+        let files = [
+            (MANIFEST_NAME, manifest.as_bytes()),
+            ("META-INF/TEST.SF", signature.as_bytes()),
+            ("META-INF/TEST.RSA", b"block".as_slice()),
+        ];
+
+        for (name, data) in files.iter().chain(written) {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+
+        zip.finish().unwrap();
+        archive
+    }
+
+    fn check(archive: Vec<u8>) -> Vec<(String, ErrorKind)> {
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+
+        check_jar(&mut archive, NameEncoding::Auto)
+            .into_iter()
+            .map(|finding| (finding.name, finding.kind))
+            .collect()
+    }
+
+    const CLASS: &[u8] = b"\xCA\xFE\xBA\xBE\x00\x00\x00\x41rest";
+
+    #[test]
+    fn test_parse_manifest() {
+        let data = b"Manifest-Version: 1.0\nClass-Path: a.jar\n  b.jar\n\n\nName: a/B.class\r\nSHA-256-Digest: x\r\n\r\n";
+        let manifest = parse_manifest(data).unwrap();
+
+        assert_eq!(manifest.main.get("class-path"), Some("a.jar b.jar"));
+        assert_eq!(manifest.main.raw, 0..49);
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries[0].get("Name"), Some("a/B.class"));
+        assert_eq!(&data[manifest.entries[0].raw.clone()], &data[50..]);
+        assert_eq!(
+            manifest
+                .entry("a/B.class")
+                .map(|section| section.raw.clone()),
+            Some(50..data.len())
+        );
+        assert!(manifest.entry("a/C.class").is_none());
+
+        let long = format!("Manifest-Version: 1.0\nX: {}\n", "a".repeat(70));
+        assert!(
+            parse_manifest(long.as_bytes())
+                .unwrap_err()
+                .contains("line 2")
+        );
+
+        assert!(parse_manifest(b" a\n").is_err());
+        assert!(parse_manifest(b"Manifest-Version 1.0\n").is_err());
+        assert!(parse_manifest(b"Manifest-Version: 1.0").is_err());
+        assert!(parse_manifest(b"Manifest-Version: 1.0\n\nX: a\n").is_err());
+        assert!(parse_manifest(b"Bad Name: a\n").is_err());
+    }
+
+    #[test]
+    fn test_check_class() {
+        assert_eq!(check_class(CLASS), None);
+        assert_eq!(check_class(b"\xCA\xFE\xBA\xBE\xFF\xFF\x00\x45"), None);
+        assert!(check_class(b"\xCA\xFE").is_some());
+        assert!(check_class(b"PK\x03\x04\x00\x00\x00\x41").is_some());
+        assert!(check_class(b"\xCA\xFE\xBA\xBE\x00\x00\x00\x20").is_some());
+        // Releases newer than this check, such as Java 27
+        assert_eq!(check_class(b"\xCA\xFE\xBA\xBE\x00\x00\x00\x47"), None);
+        assert_eq!(check_class(b"\xCA\xFE\xBA\xBE\x00\x00\x00\x99"), None);
+    }
+
+    #[test]
+    fn test_is_signature_related() {
+        assert!(is_signature_related("META-INF/MANIFEST.MF"));
+        assert!(is_signature_related("META-INF/CERT.RSA"));
+        assert!(is_signature_related("meta-inf/cert.sf"));
+        assert!(!is_signature_related("META-INF/maven/pom.xml"));
+        assert!(!is_signature_related("META-INF/versions/9/A.RSA"));
+        assert!(!is_signature_related("a/B.class"));
+    }
+
+    #[test]
+    fn test_check_jar() {
+        let entries: [(&str, &[u8]); 2] = [("a/B.class", CLASS), ("a/c.txt", b"text")];
+        let late: [(&str, &[u8]); 3] = [entries[0], entries[1], ("a/d.txt", b"late")];
+        let tampered: [(&str, &[u8]); 2] = [entries[0], ("a/c.txt", b"TEXT")];
+        let bad_class: [(&str, &[u8]); 1] = [("a/B.class", b"not a class")];
+
+        assert!(check(signed_jar(&entries, &entries)).is_empty());
+        assert_eq!(
+            check(signed_jar(&entries, &late)),
+            vec![(String::from("a/d.txt"), ErrorKind::UnsignedEntry)]
+        );
+        assert_eq!(
+            check(signed_jar(&entries, &tampered)),
+            vec![(String::from("a/c.txt"), ErrorKind::DigestMismatch)]
+        );
+        assert_eq!(
+            check(signed_jar(&bad_class, &bad_class)),
+            vec![(String::from("a/B.class"), ErrorKind::BadClass)]
+        );
+
+        // Signature files with nothing to sign
+        let mut unsigned = vec![];
+        let mut zip = ZipWriter::new(Cursor::new(&mut unsigned));
+        for (name, data) in [
+            (
+                "META-INF/TEST.SF",
+                b"Signature-Version: 1.0\r\n\r\n".as_slice(),
+            ),
+            ("META-INF/TEST.RSA", b"block"),
+            ("a/c.txt", b"text"),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        assert_eq!(
+            check(unsigned),
+            vec![(String::from("META-INF/TEST.SF"), ErrorKind::BadSignature)]
+        );
+    }
+}
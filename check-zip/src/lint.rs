@@ -0,0 +1,536 @@
+//! `lint` subcommand: metadata that reads fine but breaks reproducible builds
+//! and strict extractors, such as empty DOS dates, timestamps from the future,
+//! timestamps that disagree with each other, sizes that disagree with Zip64
+//! and names that fit no encoding.
+//!
+//! Findings are printed as `archive.zip!/path/in/zip: [code] message`.
+
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use clap::Args;
+use colour::e_red;
+use zip::ZipArchive;
+
+use crate::exit;
+use crate::format::{
+    CentralDirectoryHeader, ZIP64_EXTRA_FIELD, dos_datetime, extended_modified_time, extra_fields,
+    extra_modified_time, ntfs_modified_time, read_central_directory, read_u64,
+};
+use crate::names::{DecodedName, NameEncoding, NameSource, decode_name};
+use crate::search::{default_jobs, search_roots};
+use crate::walk::{run_workers, walk};
+
+/// Largest distance between local time and UTC, from UTC-12 to UTC+14
+const MAX_UTC_OFFSET: i64 = 14 * 3600;
+/// Time zones are whole quarter hours away from UTC
+const UTC_OFFSET_STEP: i64 = 15 * 60;
+/// DOS timestamps only have even seconds
+const DOS_RESOLUTION: i64 = 2;
+
+#[derive(Args)]
+pub(crate) struct LintArgs {
+    /// Archives and folders to lint [default: current directory]
+    pub paths: Vec<PathBuf>,
+
+    /// Flag timestamps before this date or UTC time (YYYY-MM-DD, YYYY-MM-DDTHH:MM:SS
+    /// or RFC 3339) [default: 1980-01-01]
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub not_before: Option<NaiveDateTime>,
+
+    /// Flag timestamps after this date or UTC time [default: one day from now]
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub not_after: Option<NaiveDateTime>,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+/// Parse a date, a date and time in UTC, or an RFC 3339 time
+pub(crate) fn parse_time(value: &str) -> Result<NaiveDateTime, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.naive_utc());
+    }
+
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(time);
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN))
+        .map_err(|_| {
+            format!(
+                "invalid time '{}', expected YYYY-MM-DD, YYYY-MM-DDTHH:MM:SS or RFC 3339",
+                value
+            )
+        })
+}
+
+/// Kind of metadata problem. The codes are stable, like those of `ErrorKind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LintKind {
+    /// The DOS date or time has fields out of range, such as month 0
+    InvalidDosDate,
+    /// A timestamp lies outside `--not-before` and `--not-after`
+    OutOfWindow,
+    /// The DOS timestamp and the extra field timestamps disagree
+    TimestampMismatch,
+    /// The 32-bit sizes and the Zip64 extra field disagree
+    Zip64Mismatch,
+    /// The name isn't valid in its flagged or given encoding, or in any guessed one
+    InvalidName,
+}
+
+impl LintKind {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            LintKind::InvalidDosDate => "invalid_dos_date",
+            LintKind::OutOfWindow => "out_of_window",
+            LintKind::TimestampMismatch => "timestamp_mismatch",
+            LintKind::Zip64Mismatch => "zip64_mismatch",
+            LintKind::InvalidName => "invalid_name",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct LintFinding {
+    pub kind: LintKind,
+    pub message: String,
+}
+
+impl LintFinding {
+    fn new(kind: LintKind, message: String) -> Self {
+        Self { kind, message }
+    }
+}
+
+/// Acceptable range of timestamps, in UTC
+#[derive(Clone, Copy)]
+pub(crate) struct Window {
+    pub not_before: NaiveDateTime,
+    pub not_after: NaiveDateTime,
+}
+
+impl Window {
+    fn from_args(args: &LintArgs) -> Self {
+        Self {
+            not_before: args.not_before.unwrap_or_else(|| {
+                NaiveDate::from_ymd_opt(1980, 1, 1)
+                    .unwrap()
+                    .and_time(NaiveTime::MIN)
+            }),
+            not_after: args
+                .not_after
+                .unwrap_or_else(|| Utc::now().naive_utc() + TimeDelta::days(1)),
+        }
+    }
+
+    /// Finding for a timestamp outside the window, labelled by where it comes from
+    fn check(&self, label: &str, time: NaiveDateTime, suffix: &str) -> Option<LintFinding> {
+        let (relation, bound) = if time < self.not_before {
+            ("before", self.not_before)
+        } else if time > self.not_after {
+            ("after", self.not_after)
+        } else {
+            return None;
+        };
+
+        Some(LintFinding::new(
+            LintKind::OutOfWindow,
+            format!(
+                "{} timestamp {}{} is {} {}{}",
+                label,
+                format_time(time),
+                suffix,
+                relation,
+                format_time(bound),
+                suffix
+            ),
+        ))
+    }
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn utc(seconds: i64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(seconds, 0).map(|time| time.naive_utc())
+}
+
+/// Fields of a DOS timestamp as written, valid or not
+fn raw_dos_datetime(date: u16, time: u16) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        1980 + (date >> 9),
+        (date >> 5) & 0x0F,
+        date & 0x1F,
+        time >> 11,
+        (time >> 5) & 0x3F,
+        (time & 0x1F) * 2
+    )
+}
+
+/// Whether a local DOS time and a UTC time can be the same moment in some time zone
+fn same_moment_in_some_zone(dos: NaiveDateTime, utc: i64) -> bool {
+    let offset = dos.and_utc().timestamp() - utc;
+    let drift = offset.rem_euclid(UTC_OFFSET_STEP);
+
+    offset.abs() <= MAX_UTC_OFFSET + DOS_RESOLUTION
+        && (drift <= DOS_RESOLUTION || drift >= UTC_OFFSET_STEP - DOS_RESOLUTION)
+}
+
+/// Check the timestamps of an entry against each other and the window
+fn lint_timestamps(header: &CentralDirectoryHeader, window: &Window) -> Vec<LintFinding> {
+    let mut findings = vec![];
+    let dos = dos_datetime(header.modified_date, header.modified_time);
+
+    match dos {
+        Some(dos) => findings.extend(window.check("DOS", dos, "")),
+        None => findings.push(LintFinding::new(
+            LintKind::InvalidDosDate,
+            format!(
+                "DOS timestamp {} is not a valid date",
+                raw_dos_datetime(header.modified_date, header.modified_time)
+            ),
+        )),
+    }
+
+    let extended = extended_modified_time(&header.extra);
+    let ntfs = ntfs_modified_time(&header.extra);
+
+    for (label, seconds) in [("extended", extended), ("NTFS", ntfs)] {
+        if let Some(time) = seconds.and_then(utc) {
+            findings.extend(window.check(label, time, "Z"));
+        }
+    }
+
+    if let (Some(extended), Some(ntfs)) = (extended, ntfs)
+        && extended != ntfs
+    {
+        findings.push(LintFinding::new(
+            LintKind::TimestampMismatch,
+            format!(
+                "extended timestamp {}Z differs from NTFS timestamp {}Z",
+                utc(extended).map_or(extended.to_string(), format_time),
+                utc(ntfs).map_or(ntfs.to_string(), format_time)
+            ),
+        ));
+    }
+
+    if let (Some(dos), Some(extra)) = (dos, extra_modified_time(&header.extra))
+        && !same_moment_in_some_zone(dos, extra)
+    {
+        findings.push(LintFinding::new(
+            LintKind::TimestampMismatch,
+            format!(
+                "extra field timestamp {}Z doesn't match DOS timestamp {} in any time zone",
+                utc(extra).map_or(extra.to_string(), format_time),
+                format_time(dos)
+            ),
+        ));
+    }
+
+    findings
+}
+
+/// Check the 32-bit sizes of an entry against its Zip64 extra field
+fn lint_zip64(header: &CentralDirectoryHeader) -> Option<LintFinding> {
+    let stored = [
+        ("uncompressed", header.stored_uncompressed_size),
+        ("compressed", header.stored_compressed_size),
+    ];
+    let overflowed: Vec<&str> = stored
+        .iter()
+        .filter(|(_, size)| *size == u32::MAX)
+        .map(|(name, _)| *name)
+        .collect();
+
+    let zip64 = extra_fields(&header.extra)
+        .find(|(id, _)| *id == ZIP64_EXTRA_FIELD)
+        .map(|(_, data)| data);
+
+    let message = match zip64 {
+        None if !overflowed.is_empty() => format!(
+            "{} size is 0xFFFFFFFF, but there is no Zip64 extra field",
+            overflowed.join(" and ")
+        ),
+        None => return None,
+        Some(data) if data.len() < overflowed.len() * 8 => format!(
+            "Zip64 extra field has {} bytes, too few for the {} size",
+            data.len(),
+            overflowed.join(" and ")
+        ),
+        // Some writers always store both sizes, which then have to match the header
+        Some(mut data) if overflowed.is_empty() && data.len() >= 16 => {
+            let uncompressed = read_u64(&mut data).ok()?;
+            let compressed = read_u64(&mut data).ok()?;
+
+            if uncompressed == u64::from(header.stored_uncompressed_size)
+                && compressed == u64::from(header.stored_compressed_size)
+            {
+                return None;
+            }
+
+            format!(
+                "header sizes {}/{} contradict Zip64 sizes {}/{} (uncompressed/compressed)",
+                header.stored_uncompressed_size,
+                header.stored_compressed_size,
+                uncompressed,
+                compressed
+            )
+        }
+        Some(_) => return None,
+    };
+
+    Some(LintFinding::new(LintKind::Zip64Mismatch, message))
+}
+
+/// Check that the name of an entry is valid in the encoding it was decoded with
+fn lint_name(name: &DecodedName) -> Option<LintFinding> {
+    if name.valid {
+        return None;
+    }
+
+    let message = match name.source {
+        NameSource::Utf8Flag => String::from("name is flagged as UTF-8 but isn't valid UTF-8"),
+        NameSource::Cp437 => {
+            String::from("name is neither UTF-8, GBK nor Shift-JIS; shown as CP437")
+        }
+        source => format!("name isn't valid {}", source.label()),
+    };
+
+    Some(LintFinding::new(LintKind::InvalidName, message))
+}
+
+/// Every finding of an entry
+pub(crate) fn lint_entry(
+    header: &CentralDirectoryHeader,
+    name: &DecodedName,
+    window: &Window,
+) -> Vec<LintFinding> {
+    let mut findings = lint_timestamps(header, window);
+    findings.extend(lint_zip64(header));
+    findings.extend(lint_name(name));
+    findings
+}
+
+/// Lines of the findings of every entry of an archive
+fn lint_archive(path: &Path, window: &Window, encoding: NameEncoding) -> io::Result<Vec<String>> {
+    let archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let (start, entries) = (archive.central_directory_start(), archive.len());
+    let headers = read_central_directory(&mut archive.into_inner(), start, entries)?;
+
+    Ok(headers
+        .iter()
+        .flat_map(|header| {
+            let name = decode_name(&header.name, header.flags, &header.extra, encoding);
+
+            lint_entry(header, &name, window)
+                .into_iter()
+                .map(move |finding| {
+                    format!(
+                        "{}!/{}: [{}] {}",
+                        path.display(),
+                        name.text,
+                        finding.kind.code(),
+                        finding.message
+                    )
+                })
+        })
+        .collect())
+}
+
+/// Run the `lint` subcommand and return its exit code
+pub(crate) fn lint(args: LintArgs, encoding: NameEncoding) -> u8 {
+    let window = Window::from_args(&args);
+
+    let paths = match search_roots(args.paths) {
+        Ok(paths) => paths,
+        Err(path) => {
+            e_red!("❌ Path does not exist: {}\n", path.display());
+            return exit::USAGE;
+        }
+    };
+
+    let walk = walk(paths);
+    let walk_errors = walk.walk_errors > 0;
+    let flagged = Arc::new(AtomicBool::new(false));
+    let unreadable = Arc::new(AtomicBool::new(false));
+
+    let (worker_flagged, worker_unreadable) = (flagged.clone(), unreadable.clone());
+
+    run_workers(
+        walk.paths,
+        false,
+        default_jobs(args.jobs),
+        move |path| match lint_archive(&path, &window, encoding) {
+            Ok(lines) if lines.is_empty() => {}
+            Ok(lines) => {
+                worker_flagged.store(true, Ordering::Relaxed);
+
+                let mut stdout = io::stdout().lock();
+
+                for line in lines {
+                    let _ = writeln!(stdout, "{}", line);
+                }
+            }
+            Err(e) => {
+                worker_unreadable.store(true, Ordering::Relaxed);
+                e_red!("❌ Cannot lint {}: {}\n", path.display(), e);
+            }
+        },
+    );
+
+    // Like a linter: 0 when clean, 1 with findings, and the check's codes when
+    // not everything could be read
+    if unreadable.load(Ordering::Relaxed) {
+        exit::UNREADABLE
+    } else if walk_errors {
+        exit::WALK_ERROR
+    } else if flagged.load(Ordering::Relaxed) {
+        exit::LINT_FINDINGS
+    } else {
+        exit::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-03-15 13:45:30 as a DOS date and time
+    const DATE: u16 = (44 << 9) | (3 << 5) | 15;
+    const TIME: u16 = (13 << 11) | (45 << 5) | 15;
+    /// 2024-03-15 12:45:30 UTC, the same moment as DATE and TIME in UTC+1
+    const UTC: i64 = 1_710_506_730;
+
+    fn header(date: u16, time: u16, extra: Vec<u8>) -> CentralDirectoryHeader {
+        CentralDirectoryHeader {
+            version_made_by: 0x031E,
+            flags: 0,
+            method: 0,
+            crc32: 0,
+            compressed_size: 5,
+            uncompressed_size: 5,
+            stored_compressed_size: 5,
+            stored_uncompressed_size: 5,
+            local_header_offset: 0,
+            modified_time: time,
+            modified_date: date,
+            external_attributes: 0,
+            name: b"a.txt".to_vec(),
+            extra,
+        }
+    }
+
+    fn extended(seconds: i64) -> Vec<u8> {
+        let mut extra = vec![0x55, 0x54, 0x05, 0x00, 0x01];
+        extra.extend_from_slice(&(seconds as i32).to_le_bytes());
+        extra
+    }
+
+    fn window() -> Window {
+        Window {
+            not_before: parse_time("1980-01-01").unwrap(),
+            not_after: parse_time("2025-01-01T00:00:00Z").unwrap(),
+        }
+    }
+
+    fn lint(header: &CentralDirectoryHeader) -> Vec<LintFinding> {
+        let name = decode_name(
+            &header.name,
+            header.flags,
+            &header.extra,
+            NameEncoding::Auto,
+        );
+        lint_entry(header, &name, &window())
+    }
+
+    fn kinds(header: &CentralDirectoryHeader) -> Vec<LintKind> {
+        lint(header)
+            .into_iter()
+            .map(|finding| finding.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let midnight = parse_time("2024-03-15").unwrap();
+
+        assert_eq!(parse_time("2024-03-15T00:00:00").unwrap(), midnight);
+        assert_eq!(parse_time("2024-03-15T01:00:00+01:00").unwrap(), midnight);
+        assert!(parse_time("15/03/2024").is_err());
+    }
+
+    #[test]
+    fn test_lint_timestamps() {
+        assert_eq!(kinds(&header(DATE, TIME, extended(UTC))), vec![]);
+        // Seven minutes away from any whole quarter-hour time zone
+        assert_eq!(
+            kinds(&header(DATE, TIME, extended(UTC + 7 * 60))),
+            vec![LintKind::TimestampMismatch]
+        );
+
+        let empty = lint(&header(0, 0, vec![]));
+        assert_eq!(empty[0].kind, LintKind::InvalidDosDate);
+        assert_eq!(
+            empty[0].message,
+            "DOS timestamp 1980-00-00 00:00:00 is not a valid date"
+        );
+
+        // 2107-12-31, the last DOS date
+        let future = (127 << 9) | (12 << 5) | 31;
+        assert_eq!(
+            kinds(&header(future, 0, vec![])),
+            vec![LintKind::OutOfWindow]
+        );
+        assert_eq!(
+            kinds(&header(DATE, TIME, extended(0))),
+            vec![LintKind::OutOfWindow, LintKind::TimestampMismatch]
+        );
+    }
+
+    #[test]
+    fn test_lint_zip64() {
+        let zip64 = |values: &[u64]| {
+            let mut extra = vec![0x01, 0x00, (values.len() * 8) as u8, 0x00];
+
+            for value in values {
+                extra.extend_from_slice(&value.to_le_bytes());
+            }
+
+            extra
+        };
+
+        let mut overflowed = header(DATE, TIME, vec![]);
+        overflowed.stored_uncompressed_size = u32::MAX;
+        assert_eq!(kinds(&overflowed), vec![LintKind::Zip64Mismatch]);
+
+        overflowed.extra = zip64(&[5 << 32]);
+        assert_eq!(kinds(&overflowed), vec![]);
+
+        assert_eq!(kinds(&header(DATE, TIME, zip64(&[5, 5]))), vec![]);
+        assert_eq!(
+            kinds(&header(DATE, TIME, zip64(&[5 << 32, 5]))),
+            vec![LintKind::Zip64Mismatch]
+        );
+    }
+
+    #[test]
+    fn test_lint_name() {
+        let mut entry = header(DATE, TIME, vec![]);
+        entry.name = b"caf\x82.txt".to_vec();
+        assert_eq!(kinds(&entry), vec![LintKind::InvalidName]);
+
+        entry.name = b"\xB2\xE2\xCA\xD4.txt".to_vec();
+        assert_eq!(kinds(&entry), vec![]);
+    }
+}
@@ -0,0 +1,290 @@
+//! Log file that lines are streamed to as archives finish, so a crash keeps
+//! everything logged so far.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeZone};
+use colour::red;
+
+/// Name of the log file when `--log` is a folder; zero-padded so names sort by time
+pub(crate) const DEFAULT_TEMPLATE: &str = "check-zip_%Y%m%dT%H%M%S%.3f.log";
+
+/// How the log file is opened and rotated
+pub(crate) struct LogOptions {
+    /// Append to an existing log file instead of replacing it
+    pub append: bool,
+    /// Rotate the log file before it grows past this many bytes
+    pub max_size: Option<u64>,
+    /// Number of rotated files kept, as `<log>.1` (newest) to `<log>.N`
+    pub max_files: usize,
+}
+
+pub(crate) struct LogFile {
+    path: PathBuf,
+    /// `None` once writing failed, so the error is only reported once
+    file: Option<File>,
+    written: u64,
+    max_size: Option<u64>,
+    max_files: usize,
+}
+
+impl LogFile {
+    pub(crate) fn create(path: PathBuf, options: &LogOptions) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(options.append)
+            .truncate(!options.append)
+            .open(&path)?;
+
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file: Some(file),
+            written,
+            max_size: options.max_size,
+            max_files: options.max_files,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether every line so far made it to disk
+    pub(crate) fn is_ok(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Write a line straight to disk, rotating the file first if it would grow too large
+    pub(crate) fn write_line(&mut self, line: &str) {
+        if self.file.is_none() {
+            return;
+        }
+
+        if let Err(e) = self.try_write_line(line) {
+            red!(
+                "❌ Failed to write log file {}: {}\n",
+                self.path.display(),
+                e
+            );
+            self.file = None;
+        }
+    }
+
+    fn try_write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64;
+
+        if self
+            .max_size
+            .is_some_and(|max| self.written > 0 && self.written + len > max)
+        {
+            self.rotate()?;
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(line.as_bytes())?;
+            self.written += len;
+        }
+
+        Ok(())
+    }
+
+    /// Shift `<log>.N-1` to `<log>.N`, down to `<log>` becoming `<log>.1`, and start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(&self.path, self.max_files));
+
+            for n in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, n);
+
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = Some(File::create(&self.path)?);
+        self.written = 0;
+
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Parse `--log`, checking that its file name only uses known strftime specifiers
+pub(crate) fn parse_log_template(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+
+    if let Some(name) = file_template(&path).and_then(|name| name.to_str())
+        && StrftimeItems::new(name).any(|item| item == Item::Error)
+    {
+        return Err(format!("invalid strftime template: {}", value));
+    }
+
+    Ok(path)
+}
+
+/// Resolve `--log` into the path of the log file.
+///
+/// A folder, or a path ending in a separator, gets a file named after
+/// `DEFAULT_TEMPLATE`; strftime specifiers such as `%Y-%m-%d` in the file name
+/// are expanded with `now`. Folder names are taken as they are.
+pub(crate) fn log_path<Tz: TimeZone>(log: &Path, now: &DateTime<Tz>) -> PathBuf
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let Some(name) = file_template(log) else {
+        return log.join(now.format(DEFAULT_TEMPLATE).to_string());
+    };
+
+    // Leave names without specifiers alone, so non UTF-8 names survive
+    match name.to_str() {
+        Some(name) if name.contains('%') => log.with_file_name(now.format(name).to_string()),
+        _ => log.to_path_buf(),
+    }
+}
+
+/// File name given by `--log`, or `None` when it names a folder
+fn file_template(log: &Path) -> Option<&OsStr> {
+    let is_folder = log.is_dir()
+        || log
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::is_separator);
+
+    if is_folder { None } else { log.file_name() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn options(max_size: Option<u64>, max_files: usize) -> LogOptions {
+        LogOptions {
+            append: false,
+            max_size,
+            max_files,
+        }
+    }
+
+    #[test]
+    fn test_log_path_expands_template() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 7, 3, 4, 5).unwrap();
+
+        assert_eq!(
+            log_path(Path::new("logs/run_%Y-%m-%dT%H%M%S.log"), &now),
+            PathBuf::from("logs/run_2026-01-07T030405.log")
+        );
+        assert_eq!(
+            log_path(Path::new("plain.log"), &now),
+            PathBuf::from("plain.log")
+        );
+    }
+
+    #[test]
+    fn test_log_path_in_folder_sorts_by_time() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 1, 7, 3, 4, 5).unwrap();
+
+        assert_eq!(
+            log_path(temp_dir.path(), &now),
+            temp_dir.path().join("check-zip_20260107T030405.000.log")
+        );
+        assert_eq!(
+            log_path(Path::new("logs/"), &now),
+            PathBuf::from("logs/check-zip_20260107T030405.000.log")
+        );
+    }
+
+    #[test]
+    fn test_log_path_keeps_percent_in_folders() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 7, 3, 4, 5).unwrap();
+
+        assert_eq!(
+            log_path(Path::new("100%/run_%Y-%m-%d.log"), &now),
+            PathBuf::from("100%/run_2026-01-07.log")
+        );
+        assert_eq!(
+            log_path(Path::new("100%/plain.log"), &now),
+            PathBuf::from("100%/plain.log")
+        );
+        assert_eq!(
+            log_path(Path::new("100%/"), &now),
+            PathBuf::from("100%/check-zip_20260107T030405.000.log")
+        );
+    }
+
+    #[test]
+    fn test_parse_log_template() {
+        assert!(parse_log_template("check-zip_%Y%m%d.log").is_ok());
+        assert!(parse_log_template("check-zip_%Q.log").is_err());
+        // Only the file name is a template
+        assert!(parse_log_template("100%/check-zip_%Y%m%d.log").is_ok());
+        assert!(parse_log_template("100%/").is_ok());
+    }
+
+    #[test]
+    fn test_append_keeps_existing_lines() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("check.log");
+
+        fs::write(&path, "old\n").unwrap();
+
+        let mut log = LogFile::create(
+            path.clone(),
+            &LogOptions {
+                append: true,
+                ..options(None, 0)
+            },
+        )
+        .unwrap();
+        log.write_line("new\n");
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\nnew\n");
+    }
+
+    #[test]
+    fn test_rotation_keeps_max_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("check.log");
+
+        let mut log = LogFile::create(path.clone(), &options(Some(8), 2)).unwrap();
+
+        for line in ["one\n", "two\n", "three\n", "four\n", "five\n"] {
+            log.write_line(line);
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "five\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "four\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "three\n"
+        );
+        assert!(!rotated_path(&path, 3).exists());
+    }
+}
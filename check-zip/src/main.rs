@@ -1,340 +1,1427 @@
-use chrono::{Datelike, Timelike};
 use clap::Parser;
 use colour::{green, red, yellow};
+use std::collections::HashSet;
 use std::env::current_dir;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use zip::ZipArchive;
+use zip::result::ZipError;
 
+mod actions;
+mod apk;
 mod cli;
+mod decode;
+mod duplicates;
+mod error;
+mod exit;
+mod file_list;
+mod format;
+mod html;
+mod incomplete;
+mod info;
+mod jar;
+mod lint;
+mod log_file;
+mod metrics;
+mod names;
+mod progress;
+mod repack;
+mod report;
+mod search;
+mod stream;
+mod throttle;
+mod walk;
+mod watch;
+
+use actions::handle_corrupted;
+use apk::ApkSignatures;
+use cli::{Cli, Command};
+use decode::{Checksum, Decoder};
+use duplicates::{find_duplicates, handle_duplicates, print_duplicates};
+use error::{CheckError, ErrorKind};
+use file_list::read_file_list;
+use format::{
+    CENTRAL_DIRECTORY_HEADER_SIGNATURE, CentralDirectoryHeader, Encryption, central_header_at,
+    method_name, read_u32,
+};
+use html::write_html_report;
+use incomplete::{IncompleteReason, Snapshot};
+use log_file::{LogFile, LogOptions, log_path};
+use metrics::MetricsExport;
+use names::{NameEncoding, zip_file_name};
+use progress::create_progress_bar;
+use report::{ArchiveReport, BaselineDiff, Report};
+use serde::{Deserialize, Serialize};
+use stream::check_zip_stream;
+use throttle::{Bandwidth, DeadlineReader, IoLimiter, ThrottledReader};
+use walk::{run_workers, walk};
+use watch::{WatchOptions, watch};
+
+/// Extensions of the files picked up when walking folders: ZIP and Java
+/// archives, and Android packages
+const ZIP_EXTENSIONS: [&str; 5] = ["zip", "jar", "war", "ear", "apk"];
+
+#[derive(Clone)]
+enum ZipFileStatus {
+    /// With details worth showing, such as the signature schemes of an APK
+    Valid(Option<String>),
+    /// Some entries are encrypted; all others were fully verified
+    PasswordProtected(EncryptedEntries),
+    Corrupted(Corruption),
+    /// Some entries use compression methods that can't be decoded; all others
+    /// were fully verified
+    Unsupported(UnsupportedEntries),
+    /// The check gave up after `--timeout-per-file`
+    TimedOut,
+    /// The file could not be read, which says nothing about the archive
+    Unreadable(CheckError),
+    /// The archive is still being downloaded or written
+    Incomplete(IncompleteReason),
+    /// The archive is readable, but its manifest, signatures or class files
+    /// break the rules of a Java archive. Never deleted or quarantined.
+    PolicyViolation(Corruption),
+}
+
+impl ZipFileStatus {
+    /// Machine-readable codes of all statuses, in the order they are reported
+    const CODES: [&'static str; 8] = [
+        "valid",
+        "encrypted",
+        "corrupted",
+        "unsupported",
+        "timed_out",
+        "unreadable",
+        "incomplete",
+        "policy_violation",
+    ];
+
+    /// Stable, machine-readable code of the status
+    fn code(&self) -> &'static str {
+        match self {
+            ZipFileStatus::Valid(_) => "valid",
+            ZipFileStatus::PasswordProtected(_) => "encrypted",
+            ZipFileStatus::Corrupted(_) => "corrupted",
+            ZipFileStatus::Unsupported(_) => "unsupported",
+            ZipFileStatus::TimedOut => "timed_out",
+            ZipFileStatus::Unreadable(_) => "unreadable",
+            ZipFileStatus::Incomplete(_) => "incomplete",
+            ZipFileStatus::PolicyViolation(_) => "policy_violation",
+        }
+    }
 
-use cli::Cli;
+    fn corrupted(kind: ErrorKind, message: String) -> Self {
+        ZipFileStatus::Corrupted(Corruption {
+            error: CheckError::new(kind, message),
+            entries: vec![],
+        })
+    }
 
-enum ZipFileStatus {
-    Valid,
-    PasswordProtected,
-    Corrupted(String),
-    #[allow(dead_code)] // Reserved for future use, currently only in match expressions
-    Unsupported,
+    /// Kind of error of a corrupted, unreadable or policy violating archive
+    fn error_kind(&self) -> Option<ErrorKind> {
+        match self {
+            ZipFileStatus::Corrupted(corruption) | ZipFileStatus::PolicyViolation(corruption) => {
+                Some(corruption.error.kind)
+            }
+            ZipFileStatus::Unreadable(error) => Some(error.kind),
+            _ => None,
+        }
+    }
+
+    /// Details shown next to the status in logs and reports
+    fn message(&self) -> Option<String> {
+        match self {
+            ZipFileStatus::Corrupted(corruption) | ZipFileStatus::PolicyViolation(corruption) => {
+                Some(corruption.error.message.clone())
+            }
+            ZipFileStatus::Unreadable(error) => Some(error.message.clone()),
+            ZipFileStatus::Incomplete(reason) => Some(reason.describe()),
+            ZipFileStatus::PasswordProtected(encrypted) => Some(encrypted.describe()),
+            ZipFileStatus::Unsupported(unsupported) => Some(unsupported.describe()),
+            ZipFileStatus::Valid(note) => note.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// Entry names listed per kind before the rest are only counted
+const NAMES_SHOWN: usize = 3;
+
+/// List entry names grouped by kind, such as `ZipCrypto: a.txt, b.txt; AES-256: c.txt`
+fn list_by_kind<K: PartialEq>(entries: &[(String, K)], label: impl Fn(&K) -> String) -> String {
+    let mut kinds: Vec<(&K, Vec<&str>)> = vec![];
+
+    for (name, kind) in entries {
+        match kinds.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, names)) => names.push(name),
+            None => kinds.push((kind, vec![name])),
+        }
+    }
+
+    let kinds: Vec<String> = kinds
+        .iter()
+        .map(|(kind, names)| {
+            let mut listed = names[..names.len().min(NAMES_SHOWN)].join(", ");
+
+            if names.len() > NAMES_SHOWN {
+                listed.push_str(&format!(" and {} more", names.len() - NAMES_SHOWN));
+            }
+
+            format!("{}: {}", label(kind), listed)
+        })
+        .collect();
+
+    kinds.join("; ")
+}
+
+/// Encrypted entries of an archive, with the scheme of each
+#[derive(Clone, Default)]
+struct EncryptedEntries {
+    /// Number of entries in the archive, encrypted or not
+    total: usize,
+    entries: Vec<(String, Encryption)>,
+}
+
+impl EncryptedEntries {
+    /// Whether any entry uses a broken scheme
+    fn is_weak(&self) -> bool {
+        self.entries.iter().any(|(_, scheme)| scheme.is_weak())
+    }
+
+    /// Summary such as `2 of 5 entries encrypted (ZipCrypto: a.txt; AES-256: b.txt)`
+    fn describe(&self) -> String {
+        format!(
+            "{} of {} entries encrypted ({})",
+            self.entries.len(),
+            self.total,
+            list_by_kind(&self.entries, |scheme| scheme.name().to_string())
+        )
+    }
+}
+
+/// Entries of an archive in compression methods that can't be decoded, with
+/// the method ID of each
+#[derive(Clone, Default)]
+struct UnsupportedEntries {
+    /// Number of entries in the archive
+    total: usize,
+    entries: Vec<(String, u16)>,
+}
+
+impl UnsupportedEntries {
+    /// Summary such as `1 of 5 entries in unsupported compression methods (WavPack (97): a.wav)`
+    fn describe(&self) -> String {
+        format!(
+            "{} of {} entries in unsupported compression methods ({})",
+            self.entries.len(),
+            self.total,
+            list_by_kind(&self.entries, |method| format!(
+                "{} ({})",
+                method_name(*method),
+                method
+            ))
+        )
+    }
 }
 
-#[derive(Default)]
+/// Why an archive is corrupted
+#[derive(Clone)]
+struct Corruption {
+    /// The first failure, or a summary of all of them with `--all-errors`
+    error: CheckError,
+    /// Every entry that failed, collected with `--all-errors`
+    entries: Vec<EntryError>,
+}
+
+/// An entry that failed verification
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct EntryError {
+    index: usize,
+    name: String,
+    /// Offset of the entry's local header in the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offset: Option<u64>,
+    /// Compression method ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<u16>,
+    kind: ErrorKind,
+    message: String,
+}
+
+impl EntryError {
+    /// Line of the tree printed under a corrupted archive, such as
+    /// `#3 docs/a.txt @ 1024 (Deflated): crc_mismatch - CRC mismatch in docs/a.txt`
+    fn describe(&self) -> String {
+        let mut line = format!("#{} {}", self.index, self.name);
+
+        if let Some(offset) = self.offset {
+            line.push_str(&format!(" @ {}", offset));
+        }
+
+        if let Some(method) = self.method {
+            line.push_str(&format!(" ({})", method_name(method)));
+        }
+
+        format!("{}: {} - {}", line, self.kind, self.message)
+    }
+}
+
+/// One checked archive, kept for reports
+struct FileRecord {
+    path: PathBuf,
+    /// File or folder given on the command line that led to the archive
+    root: PathBuf,
+    size: u64,
+    status: ZipFileStatus,
+}
+
+/// Settings shared by every archive check
+#[derive(Clone, Default)]
+struct CheckOptions {
+    /// Read bandwidth shared by all workers
+    bandwidth: Option<Arc<Bandwidth>>,
+    /// Limit on workers reading from disk at the same time
+    io_limit: Option<Arc<IoLimiter>>,
+    /// Time budget of a single archive check
+    timeout: Option<Duration>,
+    /// Check every entry of a corrupted archive instead of stopping at the first failure
+    all_errors: bool,
+    /// Encoding of entry names without the UTF-8 flag
+    name_encoding: NameEncoding,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 struct CheckResult {
     pub total: usize,
     pub valid: usize,
     pub skipped: usize,
     pub corrupted: usize,
+    /// Password protected archives, also counted in `skipped`
+    pub encrypted: usize,
+    /// Password protected archives with ZipCrypto entries, also counted in `encrypted`
+    pub weak_crypto: usize,
+    /// Archives that could not be fully verified, also counted in `skipped`
+    pub suspicious: usize,
+    /// Archives whose check exceeded `--timeout-per-file`, also counted in `suspicious`
+    pub timed_out: usize,
+    /// Files that could not be read, also counted in `skipped`
+    pub unreadable: usize,
+    /// Archives still being downloaded or written, also counted in `skipped`
+    pub incomplete: usize,
+    /// Readable Java archives with bad manifests, signatures or class files
+    pub policy_violations: usize,
+    /// Directories the walker failed to read
+    pub walk_errors: usize,
+    /// Archives that are corrupted now but were not in the `--baseline` report
+    pub regressions: usize,
+}
+
+impl CheckResult {
+    /// Archives that got a status of any kind so far
+    fn checked(&self) -> usize {
+        self.valid + self.corrupted + self.skipped + self.policy_violations
+    }
+}
+
+/// State shared by the workers of a run
+#[derive(Clone, Default)]
+struct RunState {
+    result: Arc<Mutex<CheckResult>>,
+    /// Log file that lines are streamed to, in the order archives finished
+    log: Arc<Mutex<Option<LogFile>>>,
+    /// Corrupted archives, offered for deletion at the end
+    corrupted: Arc<Mutex<Vec<PathBuf>>>,
+    /// Every checked archive, in the order they finished
+    records: Arc<Mutex<Vec<FileRecord>>>,
 }
 
-fn check_zip_file(path: &PathBuf) -> ZipFileStatus {
+impl RunState {
+    /// Write a line to the log file, if there is one
+    fn log(&self, line: &str) {
+        if let Some(log) = self.log.lock().unwrap().as_mut() {
+            log.write_line(line);
+        }
+    }
+}
+
+fn check_zip_file(path: &PathBuf, options: &CheckOptions) -> ZipFileStatus {
+    let before = Snapshot::of(path);
+
+    if let Some(reason) = incomplete::detect_before(path, before) {
+        return ZipFileStatus::Incomplete(reason);
+    }
+
+    // Hold an I/O permit for the whole check, if the number of readers is limited
+    let _permit = options.io_limit.as_deref().map(IoLimiter::acquire);
+
+    // The time budget starts once the file may be read
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+
+    let status = classify_failure(verify_zip_file(path, options, deadline));
+
+    // Whatever was found, a file that changed under the check is not done yet
+    if let Some(reason) = incomplete::detect_after(path, before) {
+        return ZipFileStatus::Incomplete(reason);
+    }
+
+    match status {
+        ZipFileStatus::Corrupted(corruption)
+            if corruption.error.kind == ErrorKind::Truncated
+                && incomplete::ends_before_central_directory(path) =>
+        {
+            ZipFileStatus::Incomplete(IncompleteReason::MissingCentralDirectory)
+        }
+        status => status,
+    }
+}
+
+/// Tell failures that are not the archive's fault apart from corruption
+fn classify_failure(status: ZipFileStatus) -> ZipFileStatus {
+    match status {
+        // Reads fail once the deadline passed, unless the corruption was found first
+        ZipFileStatus::Corrupted(corruption) if corruption.error.kind == ErrorKind::TimedOut => {
+            ZipFileStatus::TimedOut
+        }
+        ZipFileStatus::Corrupted(corruption) if corruption.error.kind.is_unreadable() => {
+            ZipFileStatus::Unreadable(corruption.error)
+        }
+        status => status,
+    }
+}
+
+/// Error standing for all failing entries: the only one, or the first one
+/// and how many failed
+fn summarize_failures(failures: &[EntryError], total: usize) -> CheckError {
+    match failures {
+        [failure] => CheckError::new(failure.kind, failure.message.clone()),
+        [first, ..] => CheckError::new(
+            first.kind,
+            format!(
+                "{} of {} entries failed, first: {}",
+                failures.len(),
+                total,
+                first.message
+            ),
+        ),
+        [] => unreachable!(),
+    }
+}
+
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+fn verify_zip_file(
+    path: &PathBuf,
+    options: &CheckOptions,
+    deadline: Option<Instant>,
+) -> ZipFileStatus {
     // Try to open the file
     let file = match File::open(path) {
         Ok(f) => f,
-        Err(e) => return ZipFileStatus::Corrupted(format!("Cannot open file: {}", e)),
+        Err(e) => {
+            return ZipFileStatus::corrupted(
+                ErrorKind::of_io(&e),
+                format!("Cannot open file: {}", e),
+            );
+        }
     };
 
     // Try to read the zip archive
-    let mut archive = match ZipArchive::new(file) {
+    let mut file = DeadlineReader::new(
+        ThrottledReader::new(file, options.bandwidth.clone()),
+        deadline,
+    );
+
+    let mut archive = match ZipArchive::new(&mut file) {
         Ok(archive) => archive,
-        Err(e) => return ZipFileStatus::Corrupted(format!("Invalid zip format: {}", e)),
+        Err(e) => {
+            return ZipFileStatus::corrupted(
+                ErrorKind::of_archive(&mut file, &e),
+                format!("Invalid zip format: {}", e),
+            );
+        }
     };
 
-    // Check if any file in the archive is password protected
+    // Read every entry to the end so the CRC of its data is verified.
+    // Entries `ZipArchive` can't read are checked from their raw headers once
+    // the archive has been walked. With `--all-errors`, failing entries are
+    // collected and the check goes on.
+    let mut deferred = vec![];
+    let mut failures = vec![];
+
     for i in 0..archive.len() {
-        match archive.by_index(i) {
-            Ok(file) => {
-                // Check if the file is encrypted
-                if file.encrypted() {
-                    return ZipFileStatus::PasswordProtected;
+        if is_past(deadline) {
+            return ZipFileStatus::TimedOut;
+        }
+
+        let (readable, failure) = match archive.by_index(i) {
+            Ok(mut file) => (
+                true,
+                io::copy(&mut file, &mut io::sink()).err().map(|e| {
+                    (
+                        ErrorKind::of_io(&e),
+                        format!(
+                            "Cannot verify data of {}: {}",
+                            zip_file_name(&file, options.name_encoding),
+                            e
+                        ),
+                    )
+                }),
+            ),
+            // Encrypted, or in a compression method `ZipArchive` doesn't decode
+            Err(ZipError::UnsupportedArchive(_)) => (false, None),
+            Err(e) => (
+                true,
+                Some((
+                    ErrorKind::of_entry(&e),
+                    format!("Cannot read file at index {}: {}", i, e),
+                )),
+            ),
+        };
+
+        let failure = if readable {
+            failure
+        } else {
+            match archive.by_index_raw(i) {
+                Ok(file) => {
+                    deferred.push(DeferredEntry {
+                        index: i,
+                        name: zip_file_name(&file, options.name_encoding),
+                        central_header_start: file.central_header_start(),
+                        data_start: file.data_start(),
+                        compressed_size: file.compressed_size(),
+                        crc32: file.crc32(),
+                        size: file.size(),
+                    });
+
+                    None
                 }
+                Err(e) => Some((
+                    ErrorKind::of_entry(&e),
+                    format!("Cannot read file at index {}: {}", i, e),
+                )),
             }
-            Err(e) => {
-                let message = format!("{}", e);
+        };
+
+        if let Some((kind, message)) = failure {
+            if !options.all_errors {
+                return ZipFileStatus::corrupted(kind, message);
+            }
+
+            let name = archive
+                .by_index_raw(i)
+                .map(|file| zip_file_name(&file, options.name_encoding))
+                .unwrap_or_else(|_| archive.name_for_index(i).unwrap_or_default().to_string());
+
+            failures.push(EntryError {
+                index: i,
+                name,
+                offset: None,
+                method: None,
+                kind,
+                message,
+            });
+        }
+    }
+
+    // Java archives get their manifest, signatures and class files checked
+    // once their entries are known to be readable. What is found there is
+    // only reported if the rest of the archive turns out intact.
+    let mut violations = vec![];
+
+    if failures.is_empty() && jar::is_jar(path, &archive) {
+        for finding in jar::check_jar(&mut archive, options.name_encoding) {
+            violations.push(EntryError {
+                index: finding.index,
+                name: finding.name,
+                offset: None,
+                method: None,
+                kind: finding.kind,
+                message: finding.message,
+            });
+
+            if !options.all_errors {
+                break;
+            }
+        }
+
+        if is_past(deadline) {
+            return ZipFileStatus::TimedOut;
+        }
+    }
+
+    // Android packages must carry their manifest in binary XML; the signing
+    // block is checked once the archive has been walked
+    let apk = failures.is_empty() && apk::is_apk(path, &archive);
+    let mut signatures = ApkSignatures::default();
+
+    if apk {
+        if let Err(error) = apk::check_android_manifest(&mut archive) {
+            if !options.all_errors {
+                return ZipFileStatus::Corrupted(Corruption {
+                    error,
+                    entries: vec![],
+                });
+            }
+
+            // A missing manifest is listed after the last entry
+            failures.push(EntryError {
+                index: archive
+                    .index_for_name(apk::ANDROID_MANIFEST)
+                    .unwrap_or(archive.len()),
+                name: apk::ANDROID_MANIFEST.to_string(),
+                offset: None,
+                method: None,
+                kind: error.kind,
+                message: error.message,
+            });
+        }
+
+        if jar::has_signature_file(&archive) {
+            signatures.schemes.push("v1");
+        }
+    }
+
+    let total = archive.len();
+    let central_directory_start = archive.central_directory_start();
+    let archive_offset = archive.offset();
+    let reader = archive.into_inner();
+    let mut encrypted = EncryptedEntries {
+        total,
+        ..Default::default()
+    };
+    let mut unsupported = UnsupportedEntries {
+        total,
+        ..Default::default()
+    };
+
+    for entry in deferred {
+        if is_past(deadline) {
+            return ZipFileStatus::TimedOut;
+        }
 
-                if message.contains("Password required to decrypt file") {
-                    return ZipFileStatus::PasswordProtected;
+        let failure = match central_header_at(reader, entry.central_header_start) {
+            Ok(header) => {
+                if let Some(scheme) = Encryption::detect(header.flags, header.method, &header.extra)
+                {
+                    encrypted.entries.push((entry.name.clone(), scheme));
+                    None
+                } else if let Some(decoder) =
+                    Decoder::for_method(header.method, header.flags, Some(entry.size))
+                {
+                    verify_raw_entry(reader, &entry, decoder).err()
+                } else {
+                    unsupported
+                        .entries
+                        .push((entry.name.clone(), header.method));
+                    None
                 }
+            }
+            Err(e) => Some((
+                match ErrorKind::of_io(&e) {
+                    ErrorKind::TimedOut => ErrorKind::TimedOut,
+                    _ => ErrorKind::BadCentralHeader,
+                },
+                format!(
+                    "Cannot read central directory header of {}: {}",
+                    entry.name, e
+                ),
+            )),
+        };
 
-                return ZipFileStatus::Corrupted(format!("Cannot read file at index {}: {}", i, e));
+        if let Some((kind, message)) = failure {
+            if !options.all_errors {
+                return ZipFileStatus::corrupted(kind, message);
             }
+
+            failures.push(EntryError {
+                index: entry.index,
+                name: entry.name,
+                offset: None,
+                method: None,
+                kind,
+                message,
+            });
         }
     }
 
-    ZipFileStatus::Valid
+    if apk {
+        match apk::check_signing_block(reader) {
+            Ok(schemes) => signatures.schemes.extend(schemes),
+            Err(error) if !options.all_errors => {
+                return ZipFileStatus::Corrupted(Corruption {
+                    error,
+                    entries: vec![],
+                });
+            }
+            // The signing block sits between the last entry and the central directory
+            Err(error) => failures.push(EntryError {
+                index: total,
+                name: String::from("APK Signing Block"),
+                offset: None,
+                method: None,
+                kind: error.kind,
+                message: error.message,
+            }),
+        }
+    }
+
+    if !failures.is_empty() {
+        failures.sort_by_key(|failure| failure.index);
+        locate_entries(
+            reader,
+            central_directory_start,
+            archive_offset,
+            &mut failures,
+        );
+
+        ZipFileStatus::Corrupted(Corruption {
+            error: summarize_failures(&failures, total),
+            entries: failures,
+        })
+    } else if !violations.is_empty() {
+        let error = summarize_failures(&violations, total);
+
+        ZipFileStatus::PolicyViolation(Corruption {
+            error,
+            // Only the first one was looked for without `--all-errors`
+            entries: if options.all_errors {
+                violations
+            } else {
+                vec![]
+            },
+        })
+    } else if !encrypted.entries.is_empty() {
+        ZipFileStatus::PasswordProtected(encrypted)
+    } else if !unsupported.entries.is_empty() {
+        ZipFileStatus::Unsupported(unsupported)
+    } else {
+        ZipFileStatus::Valid(apk.then(|| signatures.describe()))
+    }
 }
 
-fn print_summary(result: &CheckResult) {
-    println!("========================================================");
+/// Fill in the local header offset and method of failed entries, which are
+/// sorted by index, from the central directory
+fn locate_entries<R: Read + Seek>(
+    reader: &mut R,
+    central_directory_start: u64,
+    archive_offset: u64,
+    failures: &mut [EntryError],
+) {
+    if reader
+        .seek(SeekFrom::Start(central_directory_start))
+        .is_err()
+    {
+        return;
+    }
 
-    yellow!("📊 Validation Complete - Summary Statistics:\n");
-    println!("   Total files checked: {}", result.total);
-    green!("✅ Intact files: {}\n", result.valid);
-    red!("❌ Corrupted files: {}\n", result.corrupted);
-    yellow!(
-        "⏭️ Skipped files (password protected or unsupported): {}\n",
-        result.skipped
-    );
+    let mut reader = BufReader::new(reader);
+    let last = failures.last().map_or(0, |failure| failure.index);
+
+    for index in 0..=last {
+        let header = match read_u32(&mut reader) {
+            Ok(CENTRAL_DIRECTORY_HEADER_SIGNATURE) => CentralDirectoryHeader::read(&mut reader),
+            _ => return,
+        };
+
+        let Ok(header) = header else {
+            return;
+        };
+
+        for failure in failures.iter_mut().filter(|failure| failure.index == index) {
+            failure.offset = Some(archive_offset + header.local_header_offset);
+            failure.method = Some(header.method);
+        }
+    }
 }
 
-fn main() {
-    let args = Cli::parse();
+/// An entry `ZipArchive` could not read, with what is needed to check it from raw headers
+struct DeferredEntry {
+    index: usize,
+    name: String,
+    central_header_start: u64,
+    data_start: u64,
+    compressed_size: u64,
+    crc32: u32,
+    size: u64,
+}
+
+/// Decompress an entry with a decoder of our own, and compare its CRC and size
+fn verify_raw_entry<R: Read + Seek>(
+    reader: &mut R,
+    entry: &DeferredEntry,
+    decoder: Decoder,
+) -> Result<(), (ErrorKind, String)> {
+    let mut checksum = Checksum::default();
+
+    reader
+        .seek(SeekFrom::Start(entry.data_start))
+        .and_then(|_| {
+            decoder.decompress(
+                BufReader::new(reader.take(entry.compressed_size)),
+                &mut checksum,
+                Some(entry.size),
+            )
+        })
+        .map_err(|e| {
+            (
+                ErrorKind::of_io(&e),
+                format!("Cannot verify data of {}: {}", entry.name, e),
+            )
+        })?;
+
+    let crc32 = checksum.hasher.finalize();
+
+    if crc32 != entry.crc32 {
+        return Err((
+            ErrorKind::CrcMismatch,
+            format!(
+                "CRC mismatch in {}: expected {:08x}, computed {:08x}",
+                entry.name, entry.crc32, crc32
+            ),
+        ));
+    }
+
+    if checksum.len != entry.size {
+        return Err((
+            ErrorKind::SizeMismatch,
+            format!(
+                "Size mismatch in {}: expected {}, decompressed {}",
+                entry.name, entry.size, checksum.len
+            ),
+        ));
+    }
+
+    Ok(())
+}
 
-    let zip_extensions = ["zip"];
+/// How a summary line is coloured on the console
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tone {
+    Plain,
+    Good,
+    Warning,
+    Bad,
+}
 
-    let path = args
-        .path
-        .unwrap_or_else(|| current_dir().expect("Failed to get current directory"));
+/// The summary statistics of a run, for both the console and the log
+fn summary_lines(result: &CheckResult) -> Vec<(Tone, String)> {
+    let mut lines = vec![
+        (
+            Tone::Plain,
+            String::from("========================================================\n"),
+        ),
+        (
+            Tone::Warning,
+            String::from("📊 Validation Complete - Summary Statistics:\n"),
+        ),
+        (
+            Tone::Plain,
+            format!("   Total files checked: {}\n", result.total),
+        ),
+        (Tone::Good, format!("✅ Intact files: {}\n", result.valid)),
+        (
+            Tone::Bad,
+            format!("❌ Corrupted files: {}\n", result.corrupted),
+        ),
+        (
+            Tone::Warning,
+            format!(
+                "⏭️ Skipped files (password protected, unsupported, timed out, unreadable or incomplete): {}\n",
+                result.skipped
+            ),
+        ),
+    ];
+
+    let counts = [
+        (
+            Tone::Warning,
+            "⚠️ Java archives violating their format (manifest, signatures or class files)",
+            result.policy_violations,
+        ),
+        (
+            Tone::Warning,
+            "🔓 Archives with weak ZipCrypto encryption",
+            result.weak_crypto,
+        ),
+        (Tone::Warning, "⏱️ Timed out files", result.timed_out),
+        (Tone::Warning, "⏳ Incomplete files", result.incomplete),
+        (Tone::Bad, "🚫 Unreadable files", result.unreadable),
+        (Tone::Bad, "🚫 Unreadable directories", result.walk_errors),
+    ];
+
+    for (tone, label, count) in counts {
+        if count > 0 {
+            lines.push((tone, format!("{}: {}\n", label, count)));
+        }
+    }
 
-    let save_log = args.log.is_some();
+    lines
+}
 
-    let now = chrono::Local::now();
+/// Print the summary statistics, and return the lines for the log
+fn print_summary(result: &CheckResult) -> Vec<String> {
+    summary_lines(result)
+        .into_iter()
+        .map(|(tone, line)| {
+            match tone {
+                Tone::Plain => print!("{}", line),
+                Tone::Good => green!("{}", line),
+                Tone::Warning => yellow!("{}", line),
+                Tone::Bad => red!("{}", line),
+            }
 
-    let file_name = format!(
-        "check-zip_{}{}{}{}{}{}{}.log",
-        now.year(),
-        now.month(),
-        now.day(),
-        now.hour(),
-        now.minute(),
-        now.second(),
-        now.timestamp_subsec_millis(),
+            line
+        })
+        .collect()
+}
+
+/// Print how the run differs from the baseline report, and return the lines for the log
+fn print_baseline_diff(diff: &BaselineDiff, baseline: &Path) -> Vec<String> {
+    let mut lines = vec![format!(
+        "🔁 Compared with baseline {}: {} newly corrupted, {} newly fixed, {} removed, {} added\n",
+        baseline.display(),
+        diff.newly_corrupted.len(),
+        diff.newly_fixed.len(),
+        diff.removed.len(),
+        diff.added.len()
+    )];
+    yellow!("{}", lines[0]);
+
+    for archive in &diff.newly_corrupted {
+        let line = format!(
+            "📉 [NEWLY CORRUPTED] {} - {}\n",
+            archive.path,
+            archive.message.as_deref().unwrap_or_default()
+        );
+        red!("{}", line);
+        lines.push(line);
+    }
+
+    for archive in &diff.newly_fixed {
+        let line = format!("📈 [NEWLY FIXED] {}\n", archive.path);
+        green!("{}", line);
+        lines.push(line);
+    }
+
+    for (label, archives) in [("➖ [REMOVED]", &diff.removed), ("➕ [ADDED]", &diff.added)] {
+        for archive in archives {
+            let line = format!("{} {} ({})\n", label, archive.path, archive.status);
+            yellow!("{}", line);
+            lines.push(line);
+        }
+    }
+
+    lines
+}
+
+/// Count a checked archive and format its log line
+fn record_status(result: &mut CheckResult, status: &ZipFileStatus, name: &Path) -> String {
+    match status {
+        ZipFileStatus::Valid(note) => {
+            result.valid += 1;
+
+            match note {
+                Some(note) => format!("✅ [VALID] {} - {}\n", name.display(), note),
+                None => format!("✅ [VALID] {}\n", name.display()),
+            }
+        }
+        ZipFileStatus::PasswordProtected(encrypted) => {
+            result.skipped += 1;
+            result.encrypted += 1;
+
+            if encrypted.is_weak() {
+                result.weak_crypto += 1;
+            }
+
+            format!(
+                "🔐 [PASSWORD PROTECTED] {} - {}\n",
+                name.display(),
+                encrypted.describe()
+            )
+        }
+        ZipFileStatus::Corrupted(corruption) => {
+            result.corrupted += 1;
+            corruption_lines("❌ [CORRUPTED]", name, corruption)
+        }
+        ZipFileStatus::PolicyViolation(corruption) => {
+            result.policy_violations += 1;
+            corruption_lines("⚠️ [POLICY VIOLATION]", name, corruption)
+        }
+        ZipFileStatus::Unsupported(unsupported) => {
+            result.skipped += 1;
+            result.suspicious += 1;
+            format!(
+                "⏭️ [UNSUPPORTED] {} - {}\n",
+                name.display(),
+                unsupported.describe()
+            )
+        }
+        ZipFileStatus::TimedOut => {
+            result.skipped += 1;
+            result.suspicious += 1;
+            result.timed_out += 1;
+            format!("⏱️ [TIMED OUT] {}\n", name.display())
+        }
+        ZipFileStatus::Unreadable(error) => {
+            result.skipped += 1;
+            result.unreadable += 1;
+            format!("🚫 [UNREADABLE] {} - {}\n", name.display(), error)
+        }
+        ZipFileStatus::Incomplete(reason) => {
+            result.skipped += 1;
+            result.incomplete += 1;
+            format!(
+                "⏳ [INCOMPLETE] {} - {}\n",
+                name.display(),
+                reason.describe()
+            )
+        }
+    }
+}
+
+/// Status line of an archive, followed by a tree of its failing entries
+fn corruption_lines(label: &str, name: &Path, corruption: &Corruption) -> String {
+    let mut line = format!(
+        "{} {} - {}\n",
+        label,
+        name.display(),
+        corruption.error.message
     );
 
-    let log = args.log.unwrap_or_else(|| PathBuf::from(file_name.clone()));
+    for (i, entry) in corruption.entries.iter().enumerate() {
+        let branch = if i + 1 == corruption.entries.len() {
+            "└─"
+        } else {
+            "├─"
+        };
 
-    let log = if log.is_dir() {
-        log.join(file_name)
-    } else {
-        log
-    };
+        line.push_str(&format!("   {} {}\n", branch, entry.describe()));
+    }
 
-    let cwd = path.clone();
+    line
+}
 
-    let cores = match thread::available_parallelism() {
-        Ok(cores) => cores.get(),
-        Err(_) => 1,
+fn print_status(status: &ZipFileStatus, log_line: &str) {
+    match status {
+        ZipFileStatus::Valid(_) => green!("{}", log_line),
+        ZipFileStatus::PasswordProtected(_) => yellow!("{}", log_line),
+        ZipFileStatus::Corrupted(_) => red!("{}", log_line),
+        ZipFileStatus::Unsupported(_) => yellow!("{}", log_line),
+        ZipFileStatus::TimedOut => yellow!("{}", log_line),
+        ZipFileStatus::Unreadable(_) => red!("{}", log_line),
+        ZipFileStatus::Incomplete(_) => yellow!("{}", log_line),
+        ZipFileStatus::PolicyViolation(_) => yellow!("{}", log_line),
+    }
+}
+
+/// Check the given files, and recursively find ZIP files in the given folders,
+/// on a pool of workers
+fn check_paths(
+    paths: Vec<PathBuf>,
+    queue_per_device: bool,
+    jobs: usize,
+    options: &CheckOptions,
+    state: &RunState,
+) {
+    // A single folder keeps the familiar output relative to it; otherwise
+    // paths are shown as they were given
+    let cwd = match paths.as_slice() {
+        [path] if path.is_dir() => Some(path.clone()),
+        _ => None,
     };
 
-    let path_mutex = Arc::new(Mutex::new(Vec::<PathBuf>::new()));
+    let headline = match &cwd {
+        Some(cwd) => format!(
+            "🔍 Recursively checking all ZIP files in current directory ({:})...\n",
+            cwd.display()
+        ),
+        None => format!("🔍 Checking ZIP files in {} locations...\n", paths.len()),
+    };
 
-    let path_lock = path_mutex.clone();
+    let roots = Arc::new(paths.clone());
 
-    let result_mutex = Arc::new(Mutex::new(CheckResult::default()));
+    let walk = walk(paths);
 
-    let result_lock = result_mutex.clone();
+    {
+        let mut result = state.result.lock().unwrap();
+        result.total = walk.paths.len();
+        result.walk_errors = walk.walk_errors;
+    }
 
-    let log_mutex = Arc::new(Mutex::new(Vec::<String>::new()));
+    yellow!("{}", headline);
 
-    let walker_thread = thread::spawn(move || {
-        let mut stack = vec![path.clone()];
+    let progress = create_progress_bar(walk.paths.len(), walk.total_bytes);
 
-        while let Some(current_path) = stack.pop() {
-            let ext = if let Some(ext) = current_path.extension() {
-                ext.to_str().unwrap()
-            } else {
-                ""
-            };
+    let worker_progress = progress.clone();
+    let options = options.clone();
+    let state = state.clone();
 
-            if current_path.is_dir() {
-                if let Ok(entries) = current_path.read_dir() {
-                    for entry in entries.flatten() {
-                        stack.push(entry.path());
-                    }
-                }
-            } else if zip_extensions.contains(&ext) {
-                let mut vec = path_lock.lock().unwrap();
+    run_workers(walk.paths, queue_per_device, jobs, move |path| {
+        let progress = &worker_progress;
 
-                vec.push(current_path);
-            }
-        }
+        // Process the zip file at 'path'
+        let status = check_zip_file(&path, &options);
 
-        let total = {
-            let path = path_lock.lock().unwrap();
-            path.len()
+        let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+
+        let rel_path = match &cwd {
+            Some(cwd) => path.strip_prefix(cwd).unwrap_or(&path),
+            None => &path,
         };
 
-        let mut result = result_lock.lock().unwrap();
-        result.total = total;
+        let log_line = {
+            let mut result = state.result.lock().unwrap();
+
+            let log_line = record_status(&mut result, &status, rel_path);
+
+            progress.set_message(format!("{}/{}", result.checked(), result.total));
+
+            log_line
+        }; // result lock is released here
+
+        if let ZipFileStatus::Corrupted(_) = status {
+            let mut corrupted_files = state.corrupted.lock().unwrap();
+            corrupted_files.push(path.clone());
+        }
+
+        let root = roots.iter().find(|root| path.starts_with(root));
+
+        state.records.lock().unwrap().push(FileRecord {
+            root: root.unwrap_or(&path).clone(),
+            path: path.clone(),
+            size,
+            status: status.clone(),
+        });
+
+        progress.inc(size);
+
+        // Print outside of lock to avoid blocking other threads. The
+        // progress bar is suspended so the line scrolls above it.
+        progress.suspend(|| print_status(&status, &log_line));
+
+        // Acquire the log lock separately after the result lock is released
+        state.log(&log_line);
     });
 
-    walker_thread.join().expect("Walker thread panicked");
+    progress.finish_and_clear();
+}
+
+/// Check a single ZIP archive streamed on stdin
+fn check_stdin(options: &CheckOptions, state: &RunState) {
+    yellow!("🔍 Checking ZIP stream from stdin...\n");
 
-    yellow!(
-        "🔍 Recursively checking all ZIP files in current directory ({:})...\n",
-        cwd.clone().display()
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let stdin = DeadlineReader::new(
+        ThrottledReader::new(io::stdin().lock(), options.bandwidth.clone()),
+        deadline,
     );
 
-    let mut children: Vec<JoinHandle<()>> = vec![];
-
-    let corrupted = Arc::new(Mutex::new(Vec::<PathBuf>::new()));
-
-    for _ in 0..cores {
-        let path_lock = path_mutex.clone();
-        let result_lock = result_mutex.clone();
-        let log_lock = log_mutex.clone();
-        let corrupted = corrupted.clone();
-
-        let cwd = cwd.clone();
-
-        children.push(thread::spawn(move || {
-            loop {
-                let path_option = {
-                    let mut vec = path_lock.lock().unwrap();
-                    vec.pop()
-                };
-
-                match path_option {
-                    Some(path) => {
-                        // Process the zip file at 'path'
-                        let status = check_zip_file(&path);
-
-                        let rel_path = path.strip_prefix(&cwd).unwrap_or(&path);
-
-                        let log_line = {
-                            let mut result = result_lock.lock().unwrap();
-
-                            match status {
-                                ZipFileStatus::Valid => {
-                                    result.valid += 1;
-                                    format!("✅ [VALID] {}\n", rel_path.display())
-                                }
-                                ZipFileStatus::PasswordProtected => {
-                                    result.skipped += 1;
-                                    format!("🔐 [PASSWORD PROTECTED] {}\n", rel_path.display())
-                                }
-                                ZipFileStatus::Corrupted(ref msg) => {
-                                    result.corrupted += 1;
-
-                                    {
-                                        let mut corrupted_files = corrupted.lock().unwrap();
-                                        corrupted_files.push(path.clone());
-                                    }
-
-                                    format!("❌ [CORRUPTED] {} - {}\n", rel_path.display(), msg)
-                                }
-                                ZipFileStatus::Unsupported => {
-                                    result.skipped += 1;
-                                    format!("⏭️ [UNSUPPORTED] {}\n", rel_path.display())
-                                }
-                            }
-                        }; // result_lock is released here
-
-                        // Print outside of lock to avoid blocking other threads
-                        match status {
-                            ZipFileStatus::Valid => green!("{}", log_line),
-                            ZipFileStatus::PasswordProtected => yellow!("{}", log_line),
-                            ZipFileStatus::Corrupted(_) => red!("{}", log_line),
-                            ZipFileStatus::Unsupported => yellow!("{}", log_line),
-                        }
-
-                        // Acquire log_lock separately after result_lock is released
-                        let mut log = log_lock.lock().unwrap();
-
-                        log.push(log_line);
-                    }
-                    None => break,
-                }
+    let status = classify_failure(check_zip_stream(stdin, options.name_encoding));
+
+    let log_line = {
+        let mut result = state.result.lock().unwrap();
+        result.total += 1;
+
+        record_status(&mut result, &status, Path::new("<stdin>"))
+    };
+
+    print_status(&status, &log_line);
+
+    state.log(&log_line);
+
+    state.records.lock().unwrap().push(FileRecord {
+        path: PathBuf::from("-"),
+        root: PathBuf::from("-"),
+        size: 0,
+        status,
+    });
+}
+
+fn main() -> ExitCode {
+    let args = Cli::parse();
+
+    match args.command {
+        Some(Command::Find(find_args)) => {
+            return ExitCode::from(search::find(find_args, args.name_encoding));
+        }
+        Some(Command::Grep(grep_args)) => {
+            return ExitCode::from(search::grep(grep_args, args.name_encoding));
+        }
+        Some(Command::Info(info_args)) => {
+            return ExitCode::from(info::info(info_args, args.name_encoding));
+        }
+        Some(Command::Lint(lint_args)) => {
+            return ExitCode::from(lint::lint(lint_args, args.name_encoding));
+        }
+        Some(Command::Repack(repack_args)) => {
+            return ExitCode::from(repack::repack(repack_args, args.name_encoding));
+        }
+        None => {}
+    }
+
+    let stdin_path = Path::new("-");
+
+    if args.paths.len() > 1 && args.paths.iter().any(|p| p == stdin_path) {
+        red!("❌ `-` reads a single ZIP stream and can't be combined with other paths\n");
+        return ExitCode::from(exit::USAGE);
+    }
+
+    let read_stdin = args.stdin || args.paths.first().is_some_and(|p| p == stdin_path);
+
+    if read_stdin && args.files_from.as_deref() == Some(stdin_path) {
+        red!("❌ Can't read both a ZIP stream and a file list from stdin\n");
+        return ExitCode::from(exit::USAGE);
+    }
+
+    if let Some(path) = args.paths.iter().find(|p| !read_stdin && !p.exists()) {
+        red!("❌ Path does not exist: {}\n", path.display());
+        return ExitCode::from(exit::USAGE);
+    }
+
+    let mut paths = args.paths;
+
+    if let Some(source) = &args.files_from {
+        match read_file_list(source, args.null) {
+            Ok(list) => paths.extend(list),
+            Err(e) => {
+                red!("❌ Cannot read file list {}: {}\n", source.display(), e);
+                return ExitCode::from(exit::USAGE);
             }
-        }))
+        }
+    } else if paths.is_empty() {
+        paths.push(current_dir().expect("Failed to get current directory"));
     }
 
-    for child in children {
-        let id = child.thread().id();
+    let log = match &args.log {
+        Some(log) => {
+            let path = log_path(log, &chrono::Local::now());
+            let log_options = LogOptions {
+                append: args.log_append,
+                max_size: args.log_max_size,
+                max_files: args.log_max_files,
+            };
+
+            match LogFile::create(path.clone(), &log_options) {
+                Ok(log) => Some(log),
+                Err(e) => {
+                    red!("❌ Cannot open log file {}: {}\n", path.display(), e);
+                    return ExitCode::from(exit::USAGE);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let cores = match thread::available_parallelism() {
+        Ok(cores) => cores.get(),
+        Err(_) => 1,
+    };
+
+    let jobs = args.jobs.map(NonZeroUsize::get).unwrap_or(cores);
+
+    let options = CheckOptions {
+        bandwidth: args
+            .max_bandwidth
+            .map(|rate| Arc::new(Bandwidth::new(rate))),
+        io_limit: args.io_jobs.map(|n| Arc::new(IoLimiter::new(n.get()))),
+        timeout: args.timeout_per_file,
+        all_errors: args.all_errors,
+        name_encoding: args.name_encoding,
+    };
+
+    let baseline = match &args.baseline {
+        Some(path) => match Report::read(path) {
+            Ok(report) => Some(report),
+            Err(e) => {
+                red!("❌ Cannot read baseline {}: {}\n", path.display(), e);
+                return ExitCode::from(exit::USAGE);
+            }
+        },
+        None => None,
+    };
+
+    let state = RunState {
+        log: Arc::new(Mutex::new(log)),
+        ..Default::default()
+    };
+
+    let metrics = args.metrics_file.map(|path| MetricsExport {
+        path,
+        format: args.metrics_format,
+        roots: if read_stdin {
+            vec![PathBuf::from("-")]
+        } else {
+            paths.clone()
+        },
+        started: Instant::now(),
+    });
+
+    if args.watch {
+        let watch_options = WatchOptions {
+            settle: Duration::from_secs(args.settle),
+            jobs,
+            on_corrupted: args.on_corrupted,
+            quarantine_dir: args.quarantine_dir,
+            metrics,
+        };
 
-        child
-            .join()
-            .unwrap_or_else(|_| panic!("Failed to join thread: {:?}", id));
+        return match watch(&paths, watch_options, &options, &state) {
+            Ok(()) => ExitCode::from(exit::SUCCESS),
+            Err(e) => {
+                red!("❌ Cannot watch for changes: {}\n", e);
+                ExitCode::from(exit::WALK_ERROR)
+            }
+        };
     }
 
-    let result = result_mutex.lock().unwrap();
+    if read_stdin {
+        check_stdin(&options, &state);
+    } else {
+        check_paths(paths, args.per_device, jobs, &options, &state);
+    }
 
-    println!();
+    let mut result = state.result.lock().unwrap();
 
-    print_summary(&result);
+    println!();
 
-    if save_log {
-        let (tx, rx) = std::sync::mpsc::channel();
+    let summary = print_summary(&result);
 
-        let mut log_content = log_mutex.lock().unwrap();
+    let mut baseline_lines = vec![];
 
-        log_content.push("========================================================\n".to_string());
-        log_content.push(String::from(
-            "📊 Validation Complete - Summary Statistics:\n",
-        ));
-        log_content.push(format!("   Total files checked: {}\n", result.total));
-        log_content.push(format!("✅ Intact files: {}\n", result.valid));
-        log_content.push(format!("❌ Corrupted files: {}\n", result.corrupted));
-        log_content.push(format!(
-            "⏭️ Skipped files (password protected or unsupported): {}\n",
-            result.skipped
-        ));
+    if let (Some(baseline), Some(path)) = (&baseline, &args.baseline) {
+        let current: Vec<ArchiveReport> = state
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(ArchiveReport::from)
+            .collect();
+        let diff = report::diff(&baseline.archives, &current);
 
-        let content = log_content.clone();
+        result.regressions = diff.newly_corrupted.len();
+        baseline_lines = print_baseline_diff(&diff, path);
+    }
 
-        drop(log_content);
+    if let Some(log) = state.log.lock().unwrap().as_mut() {
+        for line in summary.iter().chain(&baseline_lines) {
+            log.write_line(line);
+        }
 
-        let log_path = log.clone();
+        if log.is_ok() {
+            green!(
+                "📝 Log file saved successfully at: {}\n",
+                log.path().display()
+            );
+        }
+    }
 
-        let tx = tx.clone();
+    if let Some(html) = &args.html {
+        let records = state.records.lock().unwrap();
 
-        thread::spawn(move || match std::fs::write(&log_path, content.concat()) {
+        match write_html_report(html, &result, &records) {
             Ok(_) => {
-                tx.send(Ok(())).unwrap();
+                green!("📄 HTML report written to: {}\n", html.display());
             }
             Err(e) => {
-                tx.send(Err(e)).unwrap();
+                red!("❌ Failed to write HTML report: {}\n", e);
             }
-        });
+        }
+    }
 
-        match rx.recv().unwrap() {
+    if let Some(json) = &args.json {
+        let records = state.records.lock().unwrap();
+
+        match Report::new(&result, &records).write(json) {
             Ok(_) => {
-                green!("📝 Log file saved successfully at: {}\n", log.display());
+                green!("🧾 JSON report written to: {}\n", json.display());
             }
             Err(e) => {
-                red!("❌ Failed to save log file: {}\n", e);
+                red!("❌ Failed to write JSON report: {}\n", e);
             }
         }
     }
 
-    println!();
+    let code = exit::exit_code(&result, &args.fail_on);
+
+    drop(result);
+
+    if args.find_duplicates {
+        // Corrupted archives are dealt with by --on-corrupted instead, and
+        // unreadable, incomplete or timed out files are never touched
+        let records = state.records.lock().unwrap();
+        let candidates: Vec<&FileRecord> = records
+            .iter()
+            .filter(|record| {
+                !matches!(
+                    record.status,
+                    ZipFileStatus::Corrupted(_)
+                        | ZipFileStatus::Unreadable(_)
+                        | ZipFileStatus::Incomplete(_)
+                        | ZipFileStatus::TimedOut
+                )
+            })
+            .collect();
+        let archives: Vec<(PathBuf, u64)> = candidates
+            .iter()
+            .map(|record| (record.path.clone(), record.size))
+            .collect();
+        let opaque: HashSet<PathBuf> = candidates
+            .iter()
+            .filter(|record| {
+                matches!(
+                    record.status,
+                    ZipFileStatus::PasswordProtected(_) | ZipFileStatus::Unsupported(_)
+                )
+            })
+            .map(|record| record.path.clone())
+            .collect();
+        drop(records);
+
+        let groups = find_duplicates(&archives, &opaque);
+
+        print_duplicates(&groups, opaque.len());
+        handle_duplicates(&groups, args.on_duplicate);
+    }
 
-    yellow!("Do you want to delete all corrupted zip files? (y/N): ");
-
-    use std::io::{self, Write};
-    io::stdout().flush().unwrap();
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-
-    if input.trim().eq_ignore_ascii_case("y") {
-        let handle = thread::spawn(move || {
-            let corrupted_files = corrupted.lock().unwrap();
-            for file in corrupted_files.iter() {
-                match std::fs::remove_file(file) {
-                    Ok(_) => {
-                        green!("🗑️ Deleted corrupted file: {}\n", file.display());
-                    }
-                    Err(e) => {
-                        red!("❌ Failed to delete file {}: {}\n", file.display(), e);
-                    }
-                }
+    if let Some(metrics) = &metrics {
+        match metrics.write(&state) {
+            Ok(_) => {
+                green!("📈 Metrics written to: {}\n", metrics.path.display());
             }
-        });
-
-        handle.join().expect("Failed to join deletion thread");
+            Err(e) => {
+                red!("❌ Failed to write metrics file: {}\n", e);
+            }
+        }
     }
+
+    let corrupted = state.corrupted.lock().unwrap();
+
+    handle_corrupted(
+        &corrupted,
+        args.on_corrupted,
+        args.quarantine_dir.as_deref(),
+    );
+
+    ExitCode::from(code)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs::File;
-    use std::io::Write;
+    use std::io::{Cursor, Write};
     use tempfile::TempDir;
-    use zip::write::{FileOptions, ZipWriter};
     use zip::CompressionMethod;
+    use zip::unstable::write::FileOptionsExt;
+    use zip::write::{FileOptions, ZipWriter};
 
     /// Helper function to create a valid ZIP file with test content
     fn create_valid_zip(path: &PathBuf) -> std::io::Result<()> {
@@ -385,10 +1472,10 @@ mod tests {
 
         create_valid_zip(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for valid ZIP file"),
@@ -402,10 +1489,10 @@ mod tests {
 
         create_empty_zip(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Empty ZIPs are valid
             }
             _ => panic!("Expected Valid status for empty ZIP file"),
@@ -419,11 +1506,16 @@ mod tests {
 
         create_corrupted_zip(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Corrupted(msg) => {
-                assert!(msg.contains("Invalid zip format") || msg.contains("Cannot read"));
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(
+                    corruption.error.message.contains("Invalid zip format")
+                        || corruption.error.message.contains("Cannot read")
+                );
+                // The file starts like an archive but has no central directory
+                assert_eq!(corruption.error.kind, ErrorKind::Truncated);
             }
             _ => panic!("Expected Corrupted status for corrupted ZIP file"),
         }
@@ -436,11 +1528,12 @@ mod tests {
 
         create_non_zip_file(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Corrupted(msg) => {
-                assert!(msg.contains("Invalid zip format"));
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("Invalid zip format"));
+                assert_eq!(corruption.error.kind, ErrorKind::NotAZip);
             }
             _ => panic!("Expected Corrupted status for non-ZIP file"),
         }
@@ -451,16 +1544,68 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let zip_path = temp_dir.path().join("nonexistent.zip");
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Corrupted(msg) => {
-                assert!(msg.contains("Cannot open file"));
+            ZipFileStatus::Unreadable(error) => {
+                assert!(error.message.contains("Cannot open file"));
+                assert_eq!(error.kind, ErrorKind::NotFound);
             }
-            _ => panic!("Expected Corrupted status for nonexistent file"),
+            _ => panic!("Expected Unreadable status for nonexistent file"),
         }
     }
 
+    #[test]
+    fn test_check_unreadable_file() {
+        let temp_dir = TempDir::new().unwrap();
+        // Opens fine, but fails on the first read
+        let path = temp_dir.path().join("folder.zip");
+        std::fs::create_dir(&path).unwrap();
+
+        let status = check_zip_file(&path, &CheckOptions::default());
+
+        assert!(matches!(status, ZipFileStatus::Unreadable(_)));
+
+        let mut result = CheckResult::default();
+        let line = record_status(&mut result, &status, &path);
+
+        assert!(line.starts_with("🚫 [UNREADABLE]"));
+        assert_eq!(result.unreadable, 1);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.corrupted, 0);
+    }
+
+    #[test]
+    fn test_check_incomplete_archives() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty = temp_dir.path().join("empty.zip");
+        let unfinished = temp_dir.path().join("unfinished.zip");
+
+        std::fs::write(&empty, b"").unwrap();
+
+        create_valid_zip(&unfinished).unwrap();
+        let data = std::fs::read(&unfinished).unwrap();
+        let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        std::fs::write(&unfinished, &data[..central]).unwrap();
+
+        assert!(matches!(
+            check_zip_file(&empty, &CheckOptions::default()),
+            ZipFileStatus::Incomplete(IncompleteReason::Empty)
+        ));
+        assert!(matches!(
+            check_zip_file(&unfinished, &CheckOptions::default()),
+            ZipFileStatus::Incomplete(IncompleteReason::MissingCentralDirectory)
+        ));
+
+        // Cut inside an entry, it's corrupted rather than unfinished
+        std::fs::write(&unfinished, &data[..central - 3]).unwrap();
+
+        assert!(matches!(
+            check_zip_file(&unfinished, &CheckOptions::default()),
+            ZipFileStatus::Corrupted(_)
+        ));
+    }
+
     #[test]
     fn test_check_result_default() {
         let result = CheckResult::default();
@@ -473,18 +1618,20 @@ mod tests {
 
     #[test]
     fn test_check_result_counters() {
-        let mut result = CheckResult::default();
-
-        result.total = 10;
-        result.valid = 7;
-        result.corrupted = 2;
-        result.skipped = 1;
+        let result = CheckResult {
+            total: 10,
+            valid: 6,
+            corrupted: 2,
+            skipped: 1,
+            policy_violations: 1,
+            ..Default::default()
+        };
 
         assert_eq!(result.total, 10);
-        assert_eq!(result.valid, 7);
+        assert_eq!(result.valid, 6);
         assert_eq!(result.corrupted, 2);
         assert_eq!(result.skipped, 1);
-        assert_eq!(result.valid + result.corrupted + result.skipped, 10);
+        assert_eq!(result.checked(), 10);
     }
 
     #[test]
@@ -500,16 +1647,16 @@ mod tests {
 
         // Add multiple files
         for i in 0..10 {
-            zip.start_file(&format!("file{}.txt", i), options).unwrap();
+            zip.start_file(format!("file{}.txt", i), options).unwrap();
             zip.write_all(format!("Content {}", i).as_bytes()).unwrap();
         }
 
         zip.finish().unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for multi-file ZIP"),
@@ -540,10 +1687,10 @@ mod tests {
 
         zip.finish().unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for nested folder ZIP"),
@@ -568,10 +1715,10 @@ mod tests {
 
         zip.finish().unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for large ZIP file"),
@@ -594,10 +1741,10 @@ mod tests {
 
         zip.finish().unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for stored ZIP file"),
@@ -612,6 +1759,7 @@ mod tests {
             valid: 80,
             corrupted: 15,
             skipped: 5,
+            ..Default::default()
         };
 
         // This should not panic
@@ -626,6 +1774,49 @@ mod tests {
         print_summary(&result);
     }
 
+    #[test]
+    fn test_summary_lines_report_unreadable() {
+        let result = CheckResult {
+            total: 3,
+            valid: 1,
+            skipped: 2,
+            unreadable: 2,
+            ..Default::default()
+        };
+
+        let lines = print_summary(&result);
+
+        assert!(lines.contains(&String::from(
+            "⏭️ Skipped files (password protected, unsupported, timed out, unreadable or incomplete): 2\n"
+        )));
+        assert!(lines.contains(&String::from("🚫 Unreadable files: 2\n")));
+        assert!(!lines.iter().any(|line| line.contains("directories")));
+    }
+
+    #[test]
+    fn test_summary_lines_report_incomplete_weak_crypto_and_timeouts() {
+        let result = CheckResult {
+            total: 6,
+            valid: 2,
+            skipped: 4,
+            weak_crypto: 1,
+            timed_out: 1,
+            incomplete: 2,
+            ..Default::default()
+        };
+
+        // The same lines are written to the log
+        let lines = print_summary(&result);
+
+        for expected in [
+            "🔓 Archives with weak ZipCrypto encryption: 1\n",
+            "⏱️ Timed out files: 1\n",
+            "⏳ Incomplete files: 2\n",
+        ] {
+            assert!(lines.contains(&String::from(expected)), "{}", expected);
+        }
+    }
+
     #[test]
     fn test_path_with_spaces() {
         let temp_dir = TempDir::new().unwrap();
@@ -633,10 +1824,10 @@ mod tests {
 
         create_valid_zip(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for path with spaces"),
@@ -650,13 +1841,356 @@ mod tests {
 
         create_valid_zip(&zip_path).unwrap();
 
-        let result = check_zip_file(&zip_path);
+        let result = check_zip_file(&zip_path, &CheckOptions::default());
 
         match result {
-            ZipFileStatus::Valid => {
+            ZipFileStatus::Valid(_) => {
                 // Test passed
             }
             _ => panic!("Expected Valid status for path with special chars"),
         }
     }
+
+    #[test]
+    fn test_check_reports_encryption_per_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("mixed.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        let options: FileOptions<()> = FileOptions::default();
+
+        zip.start_file("plain.txt", options).unwrap();
+        zip.write_all(b"Hello, World!").unwrap();
+        zip.start_file("weak.txt", options.with_deprecated_encryption(b"pw"))
+            .unwrap();
+        zip.write_all(b"Weak").unwrap();
+        zip.start_file(
+            "strong.txt",
+            options.with_aes_encryption(zip::AesMode::Aes256, "pw"),
+        )
+        .unwrap();
+        zip.write_all(b"Strong").unwrap();
+        zip.finish().unwrap();
+
+        match check_zip_file(&zip_path, &CheckOptions::default()) {
+            ZipFileStatus::PasswordProtected(encrypted) => {
+                assert!(encrypted.is_weak());
+                assert_eq!(
+                    encrypted.describe(),
+                    "2 of 3 entries encrypted (ZipCrypto: weak.txt; AES-256: strong.txt)"
+                );
+            }
+            _ => panic!("Expected PasswordProtected status for encrypted entries"),
+        }
+    }
+
+    #[test]
+    fn test_check_verifies_unencrypted_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("mixed.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        let options: FileOptions<()> = FileOptions::default();
+
+        zip.start_file("secret.txt", options.with_deprecated_encryption(b"pw"))
+            .unwrap();
+        zip.write_all(b"Secret").unwrap();
+        zip.start_file(
+            "plain.txt",
+            options.compression_method(CompressionMethod::Stored),
+        )
+        .unwrap();
+        zip.write_all(b"Hello, World!").unwrap();
+        zip.finish().unwrap();
+
+        // Flip a byte of the unencrypted entry, which comes after the encrypted one
+        let mut data = std::fs::read(&zip_path).unwrap();
+        let at = data.windows(5).position(|w| w == b"Hello").unwrap();
+        data[at] ^= 0xFF;
+        std::fs::write(&zip_path, data).unwrap();
+
+        match check_zip_file(&zip_path, &CheckOptions::default()) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("plain.txt"))
+            }
+            _ => panic!("Expected Corrupted status for a damaged unencrypted entry"),
+        }
+    }
+
+    /// Change the compression method in every local and central directory header
+    fn set_method(data: &mut [u8], method: u16) {
+        for at in 0..data.len().saturating_sub(4) {
+            let offset = match &data[at..at + 4] {
+                b"PK\x03\x04" => 8,
+                b"PK\x01\x02" => 10,
+                _ => continue,
+            };
+
+            data[at + offset..at + offset + 2].copy_from_slice(&method.to_le_bytes());
+        }
+    }
+
+    fn create_zip_with_method(path: &PathBuf, method: CompressionMethod, new_id: u16) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let options: FileOptions<()> = FileOptions::default().compression_method(method);
+
+        zip.start_file("track.txt", options).unwrap();
+        zip.write_all(&b"Hello, World! ".repeat(20)).unwrap();
+        zip.finish().unwrap();
+
+        let mut data = std::fs::read(path).unwrap();
+        set_method(&mut data, new_id);
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_check_reports_unsupported_method() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("wavpack.zip");
+
+        create_zip_with_method(&zip_path, CompressionMethod::Stored, 97);
+
+        match check_zip_file(&zip_path, &CheckOptions::default()) {
+            ZipFileStatus::Unsupported(unsupported) => assert_eq!(
+                unsupported.describe(),
+                "1 of 1 entries in unsupported compression methods (WavPack (97): track.txt)"
+            ),
+            _ => panic!("Expected Unsupported status for a WavPack entry"),
+        }
+    }
+
+    #[test]
+    fn test_check_decodes_methods_zip_cannot() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("zstd.zip");
+
+        // Zstandard under its deprecated method ID, which only our decoder reads
+        create_zip_with_method(&zip_path, CompressionMethod::Zstd, 20);
+
+        assert!(matches!(
+            check_zip_file(&zip_path, &CheckOptions::default()),
+            ZipFileStatus::Valid(_)
+        ));
+
+        let mut data = std::fs::read(&zip_path).unwrap();
+        let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        // CRC fields of the local and central directory headers
+        data[14] ^= 0xFF;
+        data[central + 16] ^= 0xFF;
+        std::fs::write(&zip_path, data).unwrap();
+
+        match check_zip_file(&zip_path, &CheckOptions::default()) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("CRC mismatch"))
+            }
+            _ => panic!("Expected Corrupted status for a CRC mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_check_reports_jar_findings_as_policy_violation() {
+        let temp_dir = TempDir::new().unwrap();
+        let jar_path = temp_dir.path().join("lib.jar");
+
+        let mut zip = ZipWriter::new(File::create(&jar_path).unwrap());
+        let options: FileOptions<()> = FileOptions::default();
+
+        for name in ["A.class", "B.class"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"not a class file").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut result = CheckResult::default();
+        let status = check_zip_file(&jar_path, &CheckOptions::default());
+        record_status(&mut result, &status, &jar_path);
+
+        match &status {
+            ZipFileStatus::PolicyViolation(violation) => {
+                assert_eq!(violation.error.kind, ErrorKind::BadClass);
+                assert!(violation.entries.is_empty());
+            }
+            _ => panic!("Expected PolicyViolation status for a bad class file"),
+        }
+        assert_eq!(result.corrupted, 0);
+        assert_eq!(result.policy_violations, 1);
+
+        let options = CheckOptions {
+            all_errors: true,
+            ..Default::default()
+        };
+
+        match check_zip_file(&jar_path, &options) {
+            ZipFileStatus::PolicyViolation(violation) => assert_eq!(violation.entries.len(), 2),
+            _ => panic!("Expected PolicyViolation status for bad class files"),
+        }
+    }
+
+    #[test]
+    fn test_check_collects_all_apk_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let apk_path = temp_dir.path().join("app.apk");
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options: FileOptions<()> = FileOptions::default();
+
+        zip.start_file("AndroidManifest.xml", options).unwrap();
+        zip.write_all(b"<manifest package=\"app\"/>").unwrap();
+        zip.start_file("classes.dex", options).unwrap();
+        zip.write_all(b"dex\n035\0").unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
+
+        // A signing block whose size fields disagree, before the central directory
+        let end = data.len() - 22;
+        let central_directory_start =
+            u32::from_le_bytes(data[end + 16..end + 20].try_into().unwrap()) as usize;
+        let mut block = 999u64.to_le_bytes().to_vec();
+        block.extend(24u64.to_le_bytes());
+        block.extend(b"APK Sig Block 42");
+        data.splice(central_directory_start..central_directory_start, block);
+        let end = data.len() - 22;
+        let moved = (central_directory_start + 32) as u32;
+        data[end + 16..end + 20].copy_from_slice(&moved.to_le_bytes());
+        std::fs::write(&apk_path, data).unwrap();
+
+        match check_zip_file(&apk_path, &CheckOptions::default()) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert_eq!(corruption.error.kind, ErrorKind::BadAndroidManifest);
+                assert!(corruption.entries.is_empty());
+            }
+            _ => panic!("Expected Corrupted status for a plain text manifest"),
+        }
+
+        let options = CheckOptions {
+            all_errors: true,
+            ..Default::default()
+        };
+
+        match check_zip_file(&apk_path, &options) {
+            ZipFileStatus::Corrupted(corruption) => {
+                let entries: Vec<_> = corruption
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.index, entry.kind))
+                    .collect();
+
+                assert_eq!(
+                    entries,
+                    vec![
+                        (0, ErrorKind::BadAndroidManifest),
+                        (2, ErrorKind::BadSigningBlock)
+                    ]
+                );
+            }
+            _ => panic!("Expected Corrupted status for a broken APK"),
+        }
+    }
+
+    #[test]
+    fn test_check_collects_all_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("rotten.zip");
+
+        let mut zip = ZipWriter::new(File::create(&zip_path).unwrap());
+        let options: FileOptions<()> =
+            FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(format!("Content of {}", name).as_bytes())
+                .unwrap();
+        }
+        zip.finish().unwrap();
+
+        // Flip a byte in the data of the first and last entries
+        let mut data = std::fs::read(&zip_path).unwrap();
+        for name in ["a.txt", "c.txt"] {
+            let content = format!("Content of {}", name);
+            let at = data
+                .windows(content.len())
+                .position(|w| w == content.as_bytes())
+                .unwrap();
+            data[at] ^= 0xFF;
+        }
+        std::fs::write(&zip_path, data).unwrap();
+
+        match check_zip_file(&zip_path, &CheckOptions::default()) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("a.txt"));
+                assert!(corruption.entries.is_empty());
+            }
+            _ => panic!("Expected Corrupted status for a CRC mismatch"),
+        }
+
+        let options = CheckOptions {
+            all_errors: true,
+            ..Default::default()
+        };
+
+        let status = check_zip_file(&zip_path, &options);
+
+        match &status {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(
+                    corruption
+                        .error
+                        .message
+                        .starts_with("2 of 3 entries failed")
+                );
+                assert_eq!(corruption.entries.len(), 2);
+                assert_eq!(corruption.entries[0].index, 0);
+                assert_eq!(corruption.entries[0].name, "a.txt");
+                assert_eq!(corruption.entries[0].offset, Some(0));
+                assert_eq!(corruption.entries[0].method, Some(0));
+                assert_eq!(corruption.entries[0].kind, ErrorKind::CrcMismatch);
+                assert_eq!(corruption.entries[1].name, "c.txt");
+                assert!(corruption.entries[1].offset > Some(0));
+            }
+            _ => panic!("Expected Corrupted status for a CRC mismatch"),
+        }
+
+        let line = record_status(&mut CheckResult::default(), &status, &zip_path);
+        let lines: Vec<&str> = line.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("   ├─ #0 a.txt @ 0 (Stored): crc_mismatch"));
+        assert!(lines[2].starts_with("   └─ #2 c.txt"));
+    }
+
+    #[test]
+    fn test_check_times_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let zip_path = temp_dir.path().join("slow.zip");
+
+        create_valid_zip(&zip_path).unwrap();
+
+        let options = CheckOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+
+        match check_zip_file(&zip_path, &options) {
+            ZipFileStatus::TimedOut => {
+                // Test passed
+            }
+            _ => panic!("Expected TimedOut status once the budget is spent"),
+        }
+    }
+
+    #[test]
+    fn test_classify_failure_keeps_corruption_found_in_time() {
+        // A check may finish after its deadline; what it found before still counts
+        let status = classify_failure(ZipFileStatus::corrupted(
+            ErrorKind::CrcMismatch,
+            String::from("CRC mismatch in a.txt"),
+        ));
+        assert!(matches!(status, ZipFileStatus::Corrupted(corruption)
+            if corruption.error.kind == ErrorKind::CrcMismatch));
+
+        let status = classify_failure(ZipFileStatus::corrupted(
+            ErrorKind::TimedOut,
+            String::from("Cannot verify data of a.txt: per-file timeout exceeded"),
+        ));
+        assert!(matches!(status, ZipFileStatus::TimedOut));
+    }
 }
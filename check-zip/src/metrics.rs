@@ -0,0 +1,287 @@
+//! Metrics export in the Prometheus text format, for node_exporter's textfile
+//! collector, or in the OpenMetrics text format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cli::MetricsFormat;
+use crate::{FileRecord, RunState, ZipFileStatus};
+
+/// Where and how to export metrics, and when the run started
+pub(crate) struct MetricsExport {
+    pub path: PathBuf,
+    pub format: MetricsFormat,
+    pub roots: Vec<PathBuf>,
+    pub started: Instant,
+}
+
+impl MetricsExport {
+    /// Render the metrics of the run so far and atomically replace the metrics file
+    pub(crate) fn write(&self, state: &RunState) -> io::Result<()> {
+        let content = {
+            let records = state.records.lock().unwrap();
+            let walk_errors = state.result.lock().unwrap().walk_errors;
+
+            render_metrics(
+                &records,
+                &self.roots,
+                walk_errors,
+                self.started.elapsed().as_secs_f64(),
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+                self.format,
+            )
+        };
+
+        write_atomically(&self.path, &content)
+    }
+}
+
+/// Write to a temporary file next to `path` and rename it into place, so the
+/// collector never reads a half-written file
+fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+
+    let tmp = path.with_file_name(tmp_name);
+
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Format label of an archive, from its extension
+pub(crate) fn archive_format(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| String::from("zip"))
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_metrics(
+    records: &[FileRecord],
+    roots: &[PathBuf],
+    walk_errors: usize,
+    duration: f64,
+    timestamp: f64,
+    format: MetricsFormat,
+) -> String {
+    // (root, format) -> status -> (archives, bytes)
+    let mut groups = BTreeMap::<(String, String), BTreeMap<&str, (u64, u64)>>::new();
+
+    for root in roots {
+        groups
+            .entry((root.display().to_string(), String::from("zip")))
+            .or_default();
+    }
+
+    for record in records {
+        let key = (
+            record.root.display().to_string(),
+            archive_format(&record.path),
+        );
+        let counts = groups
+            .entry(key)
+            .or_default()
+            .entry(record.status.code())
+            .or_default();
+
+        counts.0 += 1;
+        counts.1 += record.size;
+    }
+
+    let openmetrics = format == MetricsFormat::OpenMetrics;
+    // OpenMetrics names the counter family without the `_total` suffix
+    let counter_family = |name: &str| {
+        if openmetrics {
+            name.trim_end_matches("_total").to_string()
+        } else {
+            name.to_string()
+        }
+    };
+
+    let mut out = String::new();
+
+    let family = counter_family("check_zip_archives_total");
+    writeln!(out, "# HELP {} Archives checked, by status.", family).unwrap();
+    writeln!(out, "# TYPE {} counter", family).unwrap();
+
+    for ((root, format), counts) in &groups {
+        for status in ZipFileStatus::CODES {
+            let (archives, _) = counts.get(status).copied().unwrap_or_default();
+
+            writeln!(
+                out,
+                "check_zip_archives_total{{root=\"{}\",format=\"{}\",status=\"{}\"}} {}",
+                escape_label(root),
+                escape_label(format),
+                status,
+                archives
+            )
+            .unwrap();
+        }
+    }
+
+    let family = counter_family("check_zip_verified_bytes_total");
+    writeln!(out, "# HELP {} Bytes of archives checked.", family).unwrap();
+    writeln!(out, "# TYPE {} counter", family).unwrap();
+
+    if openmetrics {
+        writeln!(out, "# UNIT {} bytes", family).unwrap();
+    }
+
+    for ((root, format), counts) in &groups {
+        let bytes: u64 = counts.values().map(|(_, bytes)| bytes).sum();
+
+        writeln!(
+            out,
+            "check_zip_verified_bytes_total{{root=\"{}\",format=\"{}\"}} {}",
+            escape_label(root),
+            escape_label(format),
+            bytes
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "# HELP check_zip_walk_errors Directories that could not be read."
+    )
+    .unwrap();
+    writeln!(out, "# TYPE check_zip_walk_errors gauge").unwrap();
+    writeln!(out, "check_zip_walk_errors {}", walk_errors).unwrap();
+
+    writeln!(
+        out,
+        "# HELP check_zip_run_duration_seconds Duration of the run."
+    )
+    .unwrap();
+    writeln!(out, "# TYPE check_zip_run_duration_seconds gauge").unwrap();
+
+    if openmetrics {
+        writeln!(out, "# UNIT check_zip_run_duration_seconds seconds").unwrap();
+    }
+
+    writeln!(out, "check_zip_run_duration_seconds {:.3}", duration).unwrap();
+
+    writeln!(
+        out,
+        "# HELP check_zip_last_run_timestamp_seconds Time the metrics were last written."
+    )
+    .unwrap();
+    writeln!(out, "# TYPE check_zip_last_run_timestamp_seconds gauge").unwrap();
+
+    if openmetrics {
+        writeln!(out, "# UNIT check_zip_last_run_timestamp_seconds seconds").unwrap();
+    }
+
+    writeln!(out, "check_zip_last_run_timestamp_seconds {:.3}", timestamp).unwrap();
+
+    if openmetrics {
+        writeln!(out, "# EOF").unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn record(path: &str, root: &str, size: u64, status: ZipFileStatus) -> FileRecord {
+        FileRecord {
+            path: PathBuf::from(path),
+            root: PathBuf::from(root),
+            size,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let records = vec![
+            record("/data/a.zip", "/data", 100, ZipFileStatus::Valid(None)),
+            record(
+                "/data/b.zip",
+                "/data",
+                50,
+                ZipFileStatus::corrupted(ErrorKind::BadData, String::from("bad")),
+            ),
+            record("/data/c.jar", "/data", 10, ZipFileStatus::Valid(None)),
+        ];
+
+        let out = render_metrics(
+            &records,
+            &[PathBuf::from("/data")],
+            0,
+            1.5,
+            1700000000.0,
+            MetricsFormat::Prometheus,
+        );
+
+        assert!(out.contains("# TYPE check_zip_archives_total counter\n"));
+        assert!(out.contains(
+            "check_zip_archives_total{root=\"/data\",format=\"zip\",status=\"valid\"} 1\n"
+        ));
+        assert!(out.contains(
+            "check_zip_archives_total{root=\"/data\",format=\"zip\",status=\"corrupted\"} 1\n"
+        ));
+        assert!(out.contains(
+            "check_zip_archives_total{root=\"/data\",format=\"jar\",status=\"valid\"} 1\n"
+        ));
+        assert!(
+            out.contains("check_zip_verified_bytes_total{root=\"/data\",format=\"zip\"} 150\n")
+        );
+        assert!(out.contains("check_zip_run_duration_seconds 1.500\n"));
+        assert!(!out.contains("# EOF"));
+    }
+
+    #[test]
+    fn test_render_openmetrics() {
+        let out = render_metrics(
+            &[],
+            &[PathBuf::from("/data")],
+            0,
+            0.0,
+            0.0,
+            MetricsFormat::OpenMetrics,
+        );
+
+        assert!(out.contains("# TYPE check_zip_archives counter\n"));
+        assert!(out.contains(
+            "check_zip_archives_total{root=\"/data\",format=\"zip\",status=\"valid\"} 0\n"
+        ));
+        assert!(out.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_write_atomically_replaces_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("check_zip.prom");
+
+        write_atomically(&path, "old\n").unwrap();
+        write_atomically(&path, "new\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
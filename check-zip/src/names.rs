@@ -0,0 +1,311 @@
+//! Entry names in the encoding they were written in. Names flagged as UTF-8
+//! are UTF-8; the others are whatever the creator's code page was, which is
+//! guessed unless `--name-encoding` tells it.
+
+use std::io::Read;
+
+use clap::ValueEnum;
+use encoding_rs::{Encoding, GBK, SHIFT_JIS};
+use zip::read::ZipFile;
+
+use crate::format::{FLAG_UTF8, extra_fields};
+
+/// Info-ZIP Unicode path extra field: version, CRC-32 of the header's name, UTF-8 name
+pub(crate) const UNICODE_PATH_EXTRA_FIELD: u16 = 0x7075;
+
+/// Characters of bytes 0x80 to 0xFF in code page 437, the default of the ZIP specification
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// How names without the UTF-8 flag are decoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum NameEncoding {
+    /// Guess between UTF-8, GBK and Shift-JIS, falling back to CP437
+    #[default]
+    Auto,
+    /// UTF-8, as many Unix tools write it without setting the flag
+    Utf8,
+    /// GBK, the code page of Chinese Windows
+    Gbk,
+    /// Shift-JIS, the code page of Japanese Windows
+    ShiftJis,
+    /// IBM PC code page 437, the default of the ZIP specification
+    Cp437,
+}
+
+/// Where the text of a decoded name comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum NameSource {
+    /// Only ASCII characters, which read the same in every encoding
+    Ascii,
+    /// The language encoding flag (bit 11) is set
+    Utf8Flag,
+    /// The Info-ZIP Unicode path extra field
+    UnicodePath,
+    Utf8,
+    Gbk,
+    ShiftJis,
+    Cp437,
+}
+
+impl NameSource {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            NameSource::Ascii => "ASCII",
+            NameSource::Utf8Flag => "UTF-8 flag",
+            NameSource::UnicodePath => "Unicode path field",
+            NameSource::Utf8 => "UTF-8",
+            NameSource::Gbk => "GBK",
+            NameSource::ShiftJis => "Shift-JIS",
+            NameSource::Cp437 => "CP437",
+        }
+    }
+}
+
+/// An entry name, how it was decoded, and whether its bytes are valid in that encoding
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DecodedName {
+    pub text: String,
+    pub source: NameSource,
+    /// False if the bytes are invalid in the flagged or chosen encoding, or in
+    /// every guessed one
+    pub valid: bool,
+}
+
+impl DecodedName {
+    fn new(text: String, source: NameSource, valid: bool) -> Self {
+        Self {
+            text,
+            source,
+            valid,
+        }
+    }
+}
+
+/// Decode the raw name of a header with its flags and extra fields
+pub(crate) fn decode_name(
+    raw: &[u8],
+    flags: u16,
+    extra: &[u8],
+    encoding: NameEncoding,
+) -> DecodedName {
+    if raw.is_ascii() {
+        return DecodedName::new(ascii(raw), NameSource::Ascii, true);
+    }
+
+    if flags & FLAG_UTF8 != 0 {
+        return utf8(raw, NameSource::Utf8Flag);
+    }
+
+    if let Some(name) = unicode_path(raw, extra) {
+        return DecodedName::new(name, NameSource::UnicodePath, true);
+    }
+
+    match encoding {
+        NameEncoding::Auto => guess(raw),
+        NameEncoding::Utf8 => utf8(raw, NameSource::Utf8),
+        NameEncoding::Gbk => decode_with(raw, GBK, NameSource::Gbk),
+        NameEncoding::ShiftJis => decode_with(raw, SHIFT_JIS, NameSource::ShiftJis),
+        NameEncoding::Cp437 => DecodedName::new(cp437(raw), NameSource::Cp437, true),
+    }
+}
+
+/// Name of an entry opened by `ZipArchive`. It doesn't expose the flags, but
+/// decodes flagged names as UTF-8 and the others as CP437, so a non-ASCII name
+/// it shows the same as the UTF-8 reading of the bytes was flagged.
+pub(crate) fn zip_file_name<R: Read>(file: &ZipFile<'_, R>, encoding: NameEncoding) -> String {
+    let raw = file.name_raw();
+    let flagged = file.name() == String::from_utf8_lossy(raw);
+    let flags = if flagged { FLAG_UTF8 } else { 0 };
+
+    decode_name(raw, flags, file.extra_data().unwrap_or(&[]), encoding).text
+}
+
+fn ascii(raw: &[u8]) -> String {
+    raw.iter().map(|&b| b as char).collect()
+}
+
+fn utf8(raw: &[u8], source: NameSource) -> DecodedName {
+    match std::str::from_utf8(raw) {
+        Ok(name) => DecodedName::new(name.to_string(), source, true),
+        Err(_) => DecodedName::new(String::from_utf8_lossy(raw).into_owned(), source, false),
+    }
+}
+
+fn decode_with(raw: &[u8], encoding: &'static Encoding, source: NameSource) -> DecodedName {
+    let (name, _, had_errors) = encoding.decode(raw);
+    DecodedName::new(name.into_owned(), source, !had_errors)
+}
+
+fn cp437(raw: &[u8]) -> String {
+    raw.iter()
+        .map(|&b| {
+            if b < 0x80 {
+                b as char
+            } else {
+                CP437_HIGH[b as usize - 0x80]
+            }
+        })
+        .collect()
+}
+
+/// The UTF-8 name of the Unicode path extra field, if it was written for this very name
+fn unicode_path(raw: &[u8], extra: &[u8]) -> Option<String> {
+    let (_, data) = extra_fields(extra).find(|(id, _)| *id == UNICODE_PATH_EXTRA_FIELD)?;
+    let (&version, rest) = data.split_first()?;
+    let crc32 = u32::from_le_bytes(rest.get(..4)?.try_into().unwrap());
+
+    // A CRC that doesn't match means the name was changed after the field was written
+    if version != 1 || crc32 != crc32fast::hash(raw) {
+        return None;
+    }
+
+    std::str::from_utf8(&rest[4..]).ok().map(str::to_string)
+}
+
+/// How much a candidate decoding looks like real names, from the bytes and the text
+type Score = fn(&[u8], &str) -> i32;
+
+/// Pick the candidate that decodes without errors and looks most like real
+/// names; UTF-8 first, since mistaking other bytes for it is unlikely
+fn guess(raw: &[u8]) -> DecodedName {
+    if let Ok(name) = std::str::from_utf8(raw) {
+        return DecodedName::new(name.to_string(), NameSource::Utf8, true);
+    }
+
+    let candidates: [(&'static Encoding, NameSource, Score); 2] = [
+        (GBK, NameSource::Gbk, |raw, _| gbk_score(raw)),
+        (SHIFT_JIS, NameSource::ShiftJis, |_, name| {
+            shift_jis_score(name)
+        }),
+    ];
+
+    candidates
+        .into_iter()
+        .filter_map(|(encoding, source, score)| {
+            let name = encoding.decode_without_bom_handling_and_without_replacement(raw)?;
+            Some((score(raw, &name), source, name.into_owned()))
+        })
+        // The first of equal scores wins, which makes GBK the tie-breaker
+        .reduce(|best, candidate| {
+            if candidate.0 > best.0 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .map(|(_, source, name)| DecodedName::new(name, source, true))
+        .unwrap_or_else(|| DecodedName::new(cp437(raw), NameSource::Cp437, false))
+}
+
+/// How much the bytes look like GBK: common hanzi of GB2312 score, while the
+/// rare characters GBK added to it count against
+fn gbk_score(raw: &[u8]) -> i32 {
+    let mut score = 0;
+    let mut bytes = raw.iter();
+
+    while let Some(&lead) = bytes.next() {
+        if lead < 0x80 {
+            continue;
+        }
+
+        let trail = bytes.next().copied().unwrap_or(0);
+
+        score += match (lead, trail) {
+            (0xB0..=0xF7, 0xA1..=0xFE) => 2,
+            (0xA1..=0xA9, 0xA1..=0xFE) => 0,
+            _ => -1,
+        };
+    }
+
+    score
+}
+
+/// How much decoded text looks like Japanese: kana score most, kanji less,
+/// and half-width katakana, rare in file names, count against
+fn shift_jis_score(name: &str) -> i32 {
+    name.chars()
+        .map(|c| match c {
+            '\u{3041}'..='\u{30FF}' => 2,
+            '\u{4E00}'..='\u{9FFF}' => 1,
+            '\u{FF61}'..='\u{FF9F}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guessed(raw: &[u8]) -> (String, NameSource) {
+        let name = decode_name(raw, 0, &[], NameEncoding::Auto);
+        assert!(name.valid);
+        (name.text, name.source)
+    }
+
+    #[test]
+    fn test_guess_encoding() {
+        // 测试文件.txt and 数据.csv in GBK
+        let chinese = b"\xB2\xE2\xCA\xD4\xCE\xC4\xBC\xFE.txt";
+        let data = b"\xCA\xFD\xBE\xDD.csv";
+        // テスト.txt and 資料.txt in Shift-JIS
+        let katakana = b"\x83\x65\x83\x58\x83\x67.txt";
+        let kanji = b"\x8E\x91\x97\xBF.txt";
+
+        assert_eq!(
+            guessed(chinese),
+            (String::from("测试文件.txt"), NameSource::Gbk)
+        );
+        assert_eq!(guessed(data), (String::from("数据.csv"), NameSource::Gbk));
+        assert_eq!(
+            guessed(katakana),
+            (String::from("テスト.txt"), NameSource::ShiftJis)
+        );
+        assert_eq!(
+            guessed(kanji),
+            (String::from("資料.txt"), NameSource::ShiftJis)
+        );
+        assert_eq!(
+            guessed("café.txt".as_bytes()),
+            (String::from("café.txt"), NameSource::Utf8)
+        );
+    }
+
+    #[test]
+    fn test_decode_name() {
+        let flagged = decode_name("café".as_bytes(), FLAG_UTF8, &[], NameEncoding::Gbk);
+        assert_eq!(flagged.source, NameSource::Utf8Flag);
+
+        let broken = decode_name(b"caf\xE9", FLAG_UTF8, &[], NameEncoding::Auto);
+        assert!(!broken.valid);
+        assert_eq!(broken.text, "caf\u{FFFD}");
+
+        // CP437 é, which is neither UTF-8, GBK nor Shift-JIS
+        let dos = decode_name(b"caf\x82.txt", 0, &[], NameEncoding::Auto);
+        assert_eq!((dos.text.as_str(), dos.valid), ("café.txt", false));
+        assert!(decode_name(b"caf\x82.txt", 0, &[], NameEncoding::Cp437).valid);
+
+        let forced = decode_name(b"\x83\x65\x83\x58\x83\x67", 0, &[], NameEncoding::ShiftJis);
+        assert_eq!(forced.text, "テスト");
+
+        let raw = b"caf\x82";
+        let mut extra = vec![0x75, 0x70, 10, 0, 1];
+        extra.extend_from_slice(&crc32fast::hash(raw).to_le_bytes());
+        extra.extend_from_slice("café".as_bytes());
+
+        let unicode = decode_name(raw, 0, &extra, NameEncoding::Auto);
+        assert_eq!(
+            (unicode.text.as_str(), unicode.source),
+            ("café", NameSource::UnicodePath)
+        );
+    }
+}
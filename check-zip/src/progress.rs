@@ -0,0 +1,30 @@
+use std::io::{IsTerminal, stdout};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+const TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {msg} files | {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec}, ETA {eta})";
+
+/// Create a progress bar pinned to the bottom of the terminal.
+///
+/// The bar tracks verified bytes as its position, so indicatif can derive
+/// throughput and ETA; the processed file count is carried in the message.
+/// When stdout is not a TTY a hidden bar is returned, so callers never have
+/// to special-case piped output.
+pub(crate) fn create_progress_bar(total_files: usize, total_bytes: u64) -> ProgressBar {
+    if !stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::with_draw_target(Some(total_bytes), ProgressDrawTarget::stdout());
+
+    bar.set_style(
+        ProgressStyle::with_template(TEMPLATE)
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    bar.set_message(format!("0/{}", total_files));
+    bar.enable_steady_tick(Duration::from_millis(100));
+
+    bar
+}
@@ -0,0 +1,545 @@
+//! `repack` subcommand: rewrite valid archives in a canonical form, with
+//! another compression, without metadata that breaks reproducibility, sorted
+//! or decrypted. The original is only replaced once every entry of the new
+//! archive hashes the same as before.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::{Args, ValueEnum};
+use colour::{e_red, green};
+use indicatif::HumanBytes;
+use sha2::{Digest, Sha256};
+use zip::result::{ZipError, ZipResult};
+use zip::write::{FullFileOptions, ZipWriter};
+use zip::{CompressionMethod, DateTime, ZipArchive};
+
+use crate::duplicates::{Hash, hash_reader};
+use crate::exit;
+use crate::format::{
+    AES_EXTRA_FIELD, EXTENDED_TIMESTAMP_EXTRA_FIELD, NTFS_EXTRA_FIELD, ZIP64_EXTRA_FIELD,
+    extra_fields,
+};
+use crate::names::{NameEncoding, UNICODE_PATH_EXTRA_FIELD, zip_file_name};
+use crate::search::{default_jobs, search_roots};
+use crate::walk::{run_workers, walk};
+
+/// Environment variable holding the password when no `--password-file` is given
+const PASSWORD_ENV: &str = "CHECK_ZIP_PASSWORD";
+
+/// Extra fields the writer makes itself, or that no longer apply to the new entry
+const REGENERATED_EXTRA_FIELDS: [u16; 3] =
+    [ZIP64_EXTRA_FIELD, AES_EXTRA_FIELD, UNICODE_PATH_EXTRA_FIELD];
+
+#[derive(Args)]
+pub(crate) struct RepackArgs {
+    /// Archives and folders to repack
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Write the repacked archive to this file instead of replacing the original;
+    /// only with a single archive
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Compression method of every entry [default: the entry's own, or deflated
+    /// when it can't be written]
+    #[arg(long, value_enum, value_name = "METHOD")]
+    pub method: Option<RepackMethod>,
+
+    /// Compression level: 0-9 for deflated, bzip2 and xz, -7 to 22 for zstd
+    #[arg(long, value_name = "LEVEL", allow_hyphen_values = true)]
+    pub level: Option<i64>,
+
+    /// Drop the extra fields of every entry
+    #[arg(long)]
+    pub strip_extra: bool,
+
+    /// Set every timestamp to 1980-01-01 00:00:00 and drop the timestamp extra fields
+    #[arg(long)]
+    pub strip_timestamps: bool,
+
+    /// Sort entries by name
+    #[arg(long)]
+    pub sort: bool,
+
+    /// Decrypt encrypted entries with the password on the first line of this
+    /// file, or of stdin with `-`, and write them unencrypted [default: the
+    /// CHECK_ZIP_PASSWORD environment variable]
+    #[arg(long, value_name = "PATH")]
+    pub password_file: Option<PathBuf>,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+/// Compression methods entries can be written with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RepackMethod {
+    Stored,
+    Deflated,
+    Bzip2,
+    Zstd,
+    Xz,
+}
+
+impl RepackMethod {
+    fn compression(&self) -> CompressionMethod {
+        match self {
+            RepackMethod::Stored => CompressionMethod::Stored,
+            RepackMethod::Deflated => CompressionMethod::Deflated,
+            RepackMethod::Bzip2 => CompressionMethod::Bzip2,
+            RepackMethod::Zstd => CompressionMethod::Zstd,
+            RepackMethod::Xz => CompressionMethod::Xz,
+        }
+    }
+}
+
+/// How entries are rewritten
+#[derive(Clone)]
+pub(crate) struct RepackOptions {
+    pub method: Option<RepackMethod>,
+    pub level: Option<i64>,
+    pub strip_extra: bool,
+    pub strip_timestamps: bool,
+    pub sort: bool,
+    pub password: Option<String>,
+    pub name_encoding: NameEncoding,
+}
+
+/// Name, hash and size of an entry's data
+type EntryDigest = (String, Hash, u64);
+
+/// Reader that hashes what passes through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// Method the entry is written with when none is given: its own, if the
+/// writer supports it
+fn keep_method(method: CompressionMethod) -> CompressionMethod {
+    match method {
+        CompressionMethod::Stored
+        | CompressionMethod::Deflated
+        | CompressionMethod::Bzip2
+        | CompressionMethod::Zstd
+        | CompressionMethod::Xz => method,
+        _ => CompressionMethod::Deflated,
+    }
+}
+
+fn invalid(message: String) -> ZipError {
+    ZipError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Message of a repack failure; `ZipError` only says "i/o error" for I/O errors
+fn error_message(e: &ZipError) -> String {
+    match e {
+        ZipError::Io(e) => e.to_string(),
+        e => e.to_string(),
+    }
+}
+
+/// Write every entry of `archive` to `writer` and return the digests of their data
+fn copy_entries<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    writer: &mut ZipWriter<File>,
+    options: &RepackOptions,
+) -> ZipResult<Vec<EntryDigest>> {
+    let mut order: Vec<usize> = (0..archive.len()).collect();
+
+    if options.sort {
+        order.sort_by_key(|&i| archive.name_for_index(i).unwrap_or_default().to_string());
+    }
+
+    let mut digests = vec![];
+
+    for i in order {
+        let encrypted = archive.by_index_raw(i)?.encrypted();
+
+        let mut file = match (&options.password, encrypted) {
+            (Some(password), true) => archive.by_index_decrypt(i, password.as_bytes())?,
+            (None, true) => {
+                let name = zip_file_name(&archive.by_index_raw(i)?, options.name_encoding);
+                return Err(invalid(format!(
+                    "{} is encrypted; give --password-file or set {} to repack it",
+                    name, PASSWORD_ENV
+                )));
+            }
+            (_, false) => archive.by_index(i)?,
+        };
+
+        let name = zip_file_name(&file, options.name_encoding);
+
+        let modified = if options.strip_timestamps {
+            DateTime::default()
+        } else {
+            file.last_modified().unwrap_or_default()
+        };
+
+        let mut entry_options = FullFileOptions::default()
+            .compression_method(
+                options
+                    .method
+                    .map_or(keep_method(file.compression()), |m| m.compression()),
+            )
+            .compression_level(options.level)
+            .last_modified_time(modified)
+            .large_file(file.size() >= u32::MAX as u64);
+
+        if let Some(mode) = file.unix_mode() {
+            entry_options = entry_options.unix_permissions(mode);
+        }
+
+        if !options.strip_extra {
+            for (id, data) in extra_fields(file.extra_data().unwrap_or(&[])) {
+                let timestamp = id == EXTENDED_TIMESTAMP_EXTRA_FIELD || id == NTFS_EXTRA_FIELD;
+
+                if REGENERATED_EXTRA_FIELDS.contains(&id) || (options.strip_timestamps && timestamp)
+                {
+                    continue;
+                }
+
+                entry_options
+                    .add_extra_data(id, data.into(), false)
+                    .map_err(|e| {
+                        invalid(format!(
+                            "Cannot keep extra field 0x{:04x} of {}, use --strip-extra: {}",
+                            id, name, e
+                        ))
+                    })?;
+            }
+        }
+
+        let mut reader = HashingReader {
+            inner: &mut file,
+            hasher: Sha256::new(),
+            len: 0,
+        };
+
+        if reader.inner.is_dir() {
+            writer.add_directory(name.clone(), entry_options)?;
+        } else if reader.inner.is_symlink() {
+            let mut target = String::new();
+            reader.read_to_string(&mut target)?;
+            writer.add_symlink(name.clone(), target, entry_options)?;
+        } else {
+            writer.start_file(name.clone(), entry_options)?;
+            io::copy(&mut reader, writer)?;
+        }
+
+        digests.push((name, reader.hasher.finalize().into(), reader.len));
+    }
+
+    Ok(digests)
+}
+
+/// Digests of every entry of a written archive
+fn digest_entries(path: &Path) -> ZipResult<Vec<EntryDigest>> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    let mut digests = vec![];
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        let (name, size) = (entry.name().to_string(), entry.size());
+
+        digests.push((name, hash_reader(entry)?, size));
+    }
+
+    Ok(digests)
+}
+
+/// Compare the entries of the original and the new archive, in any order
+fn compare_digests(mut before: Vec<EntryDigest>, mut after: Vec<EntryDigest>) -> ZipResult<()> {
+    if before.len() != after.len() {
+        return Err(invalid(format!(
+            "Repacked archive has {} entries instead of {}",
+            after.len(),
+            before.len()
+        )));
+    }
+
+    before.sort();
+    after.sort();
+
+    match before.iter().zip(&after).find(|(a, b)| a != b) {
+        Some((original, _)) => Err(invalid(format!(
+            "Repacked {} doesn't match the original",
+            original.0
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Repack one archive into `output` and return its old and new size
+pub(crate) fn repack_archive(
+    path: &Path,
+    output: &Path,
+    options: &RepackOptions,
+) -> ZipResult<(u64, u64)> {
+    let file = File::open(path)?;
+    let (original_len, permissions) = {
+        let metadata = file.metadata()?;
+        (metadata.len(), metadata.permissions())
+    };
+    let mut archive = ZipArchive::new(BufReader::new(file))?;
+
+    let mut tmp_name = output.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.repack", std::process::id()));
+
+    let tmp = output.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut writer = ZipWriter::new(File::create(&tmp)?);
+        writer.set_raw_comment(archive.comment().into());
+
+        let before = copy_entries(&mut archive, &mut writer, options)?;
+        writer.finish()?.sync_all()?;
+
+        compare_digests(before, digest_entries(&tmp)?)?;
+
+        // Keep the permissions of the file being replaced
+        fs::set_permissions(&tmp, permissions)?;
+        fs::rename(&tmp, output)?;
+
+        Ok((original_len, fs::metadata(output)?.len()))
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Read the password from the first line of `file`, or `-` for stdin, and
+/// otherwise from `PASSWORD_ENV`. Passwords on the command line would show in
+/// the process list and the shell history.
+fn read_password(file: Option<&Path>) -> io::Result<Option<String>> {
+    let Some(file) = file else {
+        return Ok(std::env::var(PASSWORD_ENV).ok());
+    };
+
+    let mut contents = String::new();
+
+    if file == Path::new("-") {
+        io::stdin().lock().read_line(&mut contents)?;
+    } else {
+        contents = fs::read_to_string(file)?;
+    }
+
+    Ok(Some(
+        contents.lines().next().unwrap_or_default().to_string(),
+    ))
+}
+
+/// Run the `repack` subcommand and return its exit code
+pub(crate) fn repack(args: RepackArgs, name_encoding: NameEncoding) -> u8 {
+    let paths = match search_roots(args.paths) {
+        Ok(paths) => paths,
+        Err(path) => {
+            e_red!("❌ Path does not exist: {}\n", path.display());
+            return exit::USAGE;
+        }
+    };
+
+    let walk = walk(paths);
+
+    if args.output.is_some() && walk.paths.len() != 1 {
+        e_red!(
+            "❌ --output needs exactly one archive, found {}\n",
+            walk.paths.len()
+        );
+        return exit::USAGE;
+    }
+
+    let password = match read_password(args.password_file.as_deref()) {
+        Ok(password) => password,
+        Err(e) => {
+            e_red!("❌ Cannot read password: {}\n", e);
+            return exit::USAGE;
+        }
+    };
+
+    let options = RepackOptions {
+        method: args.method,
+        level: args.level,
+        strip_extra: args.strip_extra,
+        strip_timestamps: args.strip_timestamps,
+        sort: args.sort,
+        password,
+        name_encoding,
+    };
+    let output = args.output;
+    let walk_errors = walk.walk_errors > 0;
+    let failed = Arc::new(AtomicBool::new(false));
+    let worker_failed = failed.clone();
+
+    run_workers(walk.paths, false, default_jobs(args.jobs), move |path| {
+        let target = output.clone().unwrap_or_else(|| path.clone());
+
+        match repack_archive(&path, &target, &options) {
+            Ok((before, after)) => green!(
+                "✅ [REPACKED] {} - {} → {}\n",
+                target.display(),
+                HumanBytes(before),
+                HumanBytes(after)
+            ),
+            Err(e) => {
+                worker_failed.store(true, Ordering::Relaxed);
+                e_red!(
+                    "❌ Cannot repack {}: {}\n",
+                    path.display(),
+                    error_message(&e)
+                );
+            }
+        }
+    });
+
+    if failed.load(Ordering::Relaxed) {
+        exit::UNREADABLE
+    } else if walk_errors {
+        exit::WALK_ERROR
+    } else {
+        exit::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::unstable::write::FileOptionsExt;
+    use zip::write::SimpleFileOptions;
+
+    fn options() -> RepackOptions {
+        RepackOptions {
+            method: None,
+            level: None,
+            strip_extra: false,
+            strip_timestamps: false,
+            sort: false,
+            password: None,
+            name_encoding: NameEncoding::Auto,
+        }
+    }
+
+    fn create_archive(path: &Path, password: Option<&str>) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let mut entry_options = SimpleFileOptions::default()
+            .last_modified_time(DateTime::from_date_and_time(2024, 5, 1, 12, 0, 0).unwrap());
+
+        if let Some(password) = password {
+            entry_options = entry_options.with_deprecated_encryption(password.as_bytes());
+        }
+
+        for (name, data) in [("b.txt", "second"), ("a.txt", "first")] {
+            zip.start_file(name, entry_options).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_repack_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.zip");
+        create_archive(&path, None);
+
+        let options = RepackOptions {
+            method: Some(RepackMethod::Zstd),
+            strip_timestamps: true,
+            sort: true,
+            ..options()
+        };
+
+        repack_archive(&path, &path, &options).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let entry = archive.by_index(0).unwrap();
+
+        assert_eq!(entry.name(), "a.txt");
+        assert_eq!(entry.compression(), CompressionMethod::Zstd);
+        assert_eq!(entry.last_modified(), Some(DateTime::default()));
+        drop(entry);
+
+        let mut content = String::new();
+        archive
+            .by_index(1)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "second");
+
+        // No temporary file is left behind
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_repack_encrypted_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.zip");
+        let output = temp_dir.path().join("b.zip");
+        create_archive(&path, Some("secret"));
+
+        let original = fs::read(&path).unwrap();
+
+        assert!(repack_archive(&path, &path, &options()).is_err());
+        assert_eq!(fs::read(&path).unwrap(), original);
+
+        let options = RepackOptions {
+            password: Some(String::from("secret")),
+            ..options()
+        };
+        repack_archive(&path, &output, &options).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        assert!(!archive.by_index_raw(0).unwrap().encrypted());
+        assert_eq!(fs::read(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_read_password_from_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("password");
+        fs::write(&path, "secret\r\nignored\n").unwrap();
+
+        assert_eq!(
+            read_password(Some(&path)).unwrap(),
+            Some(String::from("secret"))
+        );
+        assert!(read_password(Some(&temp_dir.path().join("missing"))).is_err());
+    }
+
+    #[test]
+    fn test_compare_digests() {
+        let entry = |name: &str, byte: u8| (name.to_string(), [byte; 32], 1);
+
+        assert!(
+            compare_digests(
+                vec![entry("a", 1), entry("b", 2)],
+                vec![entry("b", 2), entry("a", 1)]
+            )
+            .is_ok()
+        );
+        assert!(compare_digests(vec![entry("a", 1)], vec![entry("a", 3)]).is_err());
+        assert!(compare_digests(vec![entry("a", 1)], vec![]).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! JSON report of a run, and the comparison of a run with a previous report.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorKind;
+use crate::watch::resolve;
+use crate::{CheckResult, EntryError, FileRecord, ZipFileStatus};
+
+/// Version of the report layout, bumped on incompatible changes
+const REPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Report {
+    pub version: u32,
+    /// Local time the report was written, in RFC 3339
+    pub generated: String,
+    pub summary: CheckResult,
+    pub archives: Vec<ArchiveReport>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ArchiveReport {
+    pub path: String,
+    /// Absolute path with symlinks resolved, which baselines are matched by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_path: Option<String>,
+    pub root: String,
+    pub size: u64,
+    /// One of `ZipFileStatus::CODES`
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Code of the kind of error of a corrupted archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorKind>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encrypted_entries: Vec<EncryptedEntryReport>,
+    /// Every failing entry of a corrupted archive, with `--all-errors`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entry_errors: Vec<EntryError>,
+}
+
+/// An encrypted entry of a password protected archive
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct EncryptedEntryReport {
+    pub name: String,
+    /// `ZipCrypto`, `AES-128`, `AES-192`, `AES-256` or `strong encryption`
+    pub encryption: String,
+}
+
+impl From<&FileRecord> for ArchiveReport {
+    fn from(record: &FileRecord) -> Self {
+        Self {
+            path: record.path.display().to_string(),
+            canonical_path: Some(resolve(&record.path).display().to_string()),
+            root: record.root.display().to_string(),
+            size: record.size,
+            status: record.status.code().to_string(),
+            message: record.status.message(),
+            error: record.status.error_kind(),
+            encrypted_entries: match &record.status {
+                ZipFileStatus::PasswordProtected(encrypted) => encrypted
+                    .entries
+                    .iter()
+                    .map(|(name, scheme)| EncryptedEntryReport {
+                        name: name.clone(),
+                        encryption: scheme.name().to_string(),
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+            entry_errors: match &record.status {
+                ZipFileStatus::Corrupted(corruption)
+                | ZipFileStatus::PolicyViolation(corruption) => corruption.entries.clone(),
+                _ => vec![],
+            },
+        }
+    }
+}
+
+impl ArchiveReport {
+    /// What the archive is matched by against a baseline; reports written
+    /// before canonical paths were recorded only have the path as given
+    fn key(&self) -> &str {
+        self.canonical_path.as_deref().unwrap_or(&self.path)
+    }
+}
+
+impl Report {
+    pub(crate) fn new(result: &CheckResult, records: &[FileRecord]) -> Self {
+        let mut archives: Vec<ArchiveReport> = records.iter().map(ArchiveReport::from).collect();
+
+        // Workers finish in any order; sorted reports diff cleanly
+        archives.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self {
+            version: REPORT_VERSION,
+            generated: chrono::Local::now().to_rfc3339(),
+            summary: result.clone(),
+            archives,
+        }
+    }
+
+    pub(crate) fn read(path: &Path) -> io::Result<Self> {
+        let report: Report = serde_json::from_slice(&fs::read(path)?)?;
+
+        if report.version > REPORT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("report version {} is not supported", report.version),
+            ));
+        }
+
+        Ok(report)
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+
+        fs::write(path, json)
+    }
+}
+
+/// Changes between a previous report and the current run
+#[derive(Default)]
+pub(crate) struct BaselineDiff {
+    /// Archives that were not corrupted before and are corrupted now
+    pub newly_corrupted: Vec<ArchiveReport>,
+    /// Archives that were corrupted before and are valid now
+    pub newly_fixed: Vec<ArchiveReport>,
+    /// Archives of the previous report that were not checked this time
+    pub removed: Vec<ArchiveReport>,
+    /// Archives checked this time that the previous report does not have
+    pub added: Vec<ArchiveReport>,
+}
+
+/// Compare the archives of the current run with those of a previous report, by canonical path
+pub(crate) fn diff(baseline: &[ArchiveReport], current: &[ArchiveReport]) -> BaselineDiff {
+    let previous: HashMap<&str, &ArchiveReport> = baseline
+        .iter()
+        .map(|archive| (archive.key(), archive))
+        .collect();
+    let checked: HashMap<&str, &ArchiveReport> = current
+        .iter()
+        .map(|archive| (archive.key(), archive))
+        .collect();
+
+    let mut diff = BaselineDiff::default();
+
+    for archive in current {
+        match previous.get(archive.key()) {
+            None => diff.added.push(archive.clone()),
+            Some(before) if before.status != "corrupted" && archive.status == "corrupted" => {
+                diff.newly_corrupted.push(archive.clone())
+            }
+            Some(before) if before.status == "corrupted" && archive.status == "valid" => {
+                diff.newly_fixed.push(archive.clone())
+            }
+            Some(_) => {}
+        }
+    }
+
+    diff.removed = baseline
+        .iter()
+        .filter(|archive| !checked.contains_key(archive.key()))
+        .cloned()
+        .collect();
+
+    for list in [
+        &mut diff.newly_corrupted,
+        &mut diff.newly_fixed,
+        &mut diff.removed,
+        &mut diff.added,
+    ] {
+        list.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CheckError;
+    use crate::format::Encryption;
+    use crate::{Corruption, EncryptedEntries};
+    use std::path::PathBuf;
+
+    fn archive(path: &str, status: &str) -> ArchiveReport {
+        ArchiveReport {
+            path: String::from(path),
+            canonical_path: None,
+            root: String::from("."),
+            size: 1,
+            status: String::from(status),
+            message: None,
+            error: None,
+            encrypted_entries: vec![],
+            entry_errors: vec![],
+        }
+    }
+
+    fn paths(archives: &[ArchiveReport]) -> Vec<&str> {
+        archives.iter().map(|a| a.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let baseline = vec![
+            archive("rot.zip", "valid"),
+            archive("fixed.zip", "corrupted"),
+            archive("still.zip", "corrupted"),
+            archive("gone.zip", "valid"),
+            archive("locked.zip", "encrypted"),
+        ];
+        let current = vec![
+            archive("rot.zip", "corrupted"),
+            archive("fixed.zip", "valid"),
+            archive("still.zip", "corrupted"),
+            archive("new.zip", "corrupted"),
+            archive("locked.zip", "corrupted"),
+        ];
+
+        let diff = diff(&baseline, &current);
+
+        assert_eq!(paths(&diff.newly_corrupted), vec!["locked.zip", "rot.zip"]);
+        assert_eq!(paths(&diff.newly_fixed), vec!["fixed.zip"]);
+        assert_eq!(paths(&diff.removed), vec!["gone.zip"]);
+        assert_eq!(paths(&diff.added), vec!["new.zip"]);
+    }
+
+    #[test]
+    fn test_diff_matches_root_spellings() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("a.zip"), "").unwrap();
+
+        let record = |root: PathBuf, status| {
+            ArchiveReport::from(&FileRecord {
+                path: root.join("a.zip"),
+                root,
+                size: 0,
+                status,
+            })
+        };
+
+        let baseline = vec![record(data.clone(), ZipFileStatus::Valid(None))];
+        let current = vec![record(
+            temp_dir
+                .path()
+                .join(".")
+                .join("data")
+                .join("..")
+                .join("data"),
+            ZipFileStatus::corrupted(ErrorKind::CrcMismatch, String::from("bad")),
+        )];
+
+        let diff = diff(&baseline, &current);
+
+        assert_eq!(diff.newly_corrupted.len(), 1);
+        assert!(diff.removed.is_empty());
+        assert!(diff.added.is_empty());
+    }
+
+    #[test]
+    fn test_report_round_trip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("report.json");
+        let records = vec![
+            FileRecord {
+                path: "a.zip".into(),
+                root: ".".into(),
+                size: 10,
+                status: ZipFileStatus::Corrupted(Corruption {
+                    error: CheckError::new(ErrorKind::CrcMismatch, String::from("bad")),
+                    entries: vec![EntryError {
+                        index: 3,
+                        name: String::from("broken.txt"),
+                        offset: Some(120),
+                        method: Some(8),
+                        kind: ErrorKind::CrcMismatch,
+                        message: String::from("bad"),
+                    }],
+                }),
+            },
+            FileRecord {
+                path: "b.zip".into(),
+                root: ".".into(),
+                size: 10,
+                status: ZipFileStatus::PasswordProtected(EncryptedEntries {
+                    total: 2,
+                    entries: vec![(String::from("secret.txt"), Encryption::ZipCrypto)],
+                }),
+            },
+        ];
+        let result = CheckResult {
+            total: 1,
+            corrupted: 1,
+            ..Default::default()
+        };
+
+        Report::new(&result, &records).write(&path).unwrap();
+        let report = Report::read(&path).unwrap();
+
+        assert_eq!(report.summary.corrupted, 1);
+        assert_eq!(report.archives[0].status, "corrupted");
+        assert_eq!(report.archives[0].message.as_deref(), Some("bad"));
+        assert_eq!(report.archives[0].error, Some(ErrorKind::CrcMismatch));
+        assert_eq!(report.archives[0].entry_errors[0].name, "broken.txt");
+        assert_eq!(report.archives[0].entry_errors[0].offset, Some(120));
+        assert_eq!(report.archives[1].encrypted_entries[0].name, "secret.txt");
+        assert_eq!(
+            report.archives[1].encrypted_entries[0].encryption,
+            "ZipCrypto"
+        );
+    }
+}
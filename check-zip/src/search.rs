@@ -0,0 +1,548 @@
+//! `find` and `grep` subcommands: search entry names and contents of many
+//! archives, on the same walker and worker pool as the integrity check.
+//!
+//! Matches are printed as `archive.zip!/path/in/zip`, followed by
+//! `:line:text` for content matches.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::Args;
+use colour::{e_red, e_yellow};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::RegexBuilder;
+use regex::bytes::Regex as BytesRegex;
+use zip::ZipArchive;
+
+use crate::decode::Decoder;
+use crate::exit;
+use crate::format::{Encryption, central_header_at, method_name, read_central_directory};
+use crate::names::{NameEncoding, decode_name, zip_file_name};
+use crate::walk::{run_workers, walk};
+
+/// Bytes at the start of an entry that are looked at to tell binary data apart
+const BINARY_SNIFF_SIZE: usize = 8 * 1024;
+
+#[derive(Args)]
+pub(crate) struct FindArgs {
+    /// Glob matched against entry paths, or a regular expression with --regex.
+    /// A glob without `/` is matched against the file name only
+    pub pattern: String,
+
+    /// Archives and folders to search [default: current directory]
+    pub paths: Vec<PathBuf>,
+
+    /// Treat PATTERN as a regular expression
+    #[arg(long)]
+    pub regex: bool,
+
+    /// Match case-insensitively
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Args)]
+pub(crate) struct GrepArgs {
+    /// Regular expression searched for in every line of the entries
+    pub pattern: String,
+
+    /// Archives and folders to search [default: current directory]
+    pub paths: Vec<PathBuf>,
+
+    /// Only search entries whose path matches this glob; can be repeated
+    #[arg(long = "name", value_name = "GLOB")]
+    pub names: Vec<String>,
+
+    /// Treat PATTERN as a literal string
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+
+    /// Match case-insensitively
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Search binary entries as if they were text
+    #[arg(short = 'a', long)]
+    pub text: bool,
+
+    /// Number of worker threads [default: number of CPU cores]
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+/// What searching one archive turned up
+#[derive(Default)]
+struct Found {
+    /// Lines to print, in entry order
+    lines: Vec<String>,
+    /// Entries that could not be searched, and why
+    skipped: Vec<String>,
+}
+
+/// An entry `ZipArchive` could not decode, searched from its raw data instead
+struct DeferredEntry {
+    location: String,
+    central_header_start: u64,
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+}
+
+/// How entry paths are matched
+enum NameMatcher {
+    /// Globs without a `/` match the file name, others the whole path
+    Glob {
+        matcher: GlobMatcher,
+        whole_path: bool,
+    },
+    Regex(regex::Regex),
+}
+
+impl NameMatcher {
+    fn glob(pattern: &str, ignore_case: bool) -> Result<Self, globset::Error> {
+        let matcher = GlobBuilder::new(pattern.trim_start_matches('/'))
+            .literal_separator(true)
+            .case_insensitive(ignore_case)
+            .build()?
+            .compile_matcher();
+
+        Ok(NameMatcher::Glob {
+            matcher,
+            whole_path: pattern.contains('/'),
+        })
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Glob {
+                matcher,
+                whole_path: true,
+            } => matcher.is_match(name),
+            NameMatcher::Glob { matcher, .. } => {
+                matcher.is_match(name.trim_end_matches('/').rsplit('/').next().unwrap_or(""))
+            }
+            NameMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Resolve the paths to search, exiting with a usage error when one is missing
+pub(crate) fn search_roots(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, PathBuf> {
+    if let Some(path) = paths.iter().find(|p| !p.exists()) {
+        return Err(path.clone());
+    }
+
+    if paths.is_empty() {
+        Ok(vec![PathBuf::from(".")])
+    } else {
+        Ok(paths)
+    }
+}
+
+pub(crate) fn default_jobs(jobs: Option<NonZeroUsize>) -> usize {
+    jobs.map(NonZeroUsize::get).unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1)
+    })
+}
+
+/// Run `search` on every archive below `paths` and print what it returns,
+/// one archive at a time so lines of different archives never interleave.
+/// Entries it had to skip are warned about, and make the search fail like
+/// unreadable archives do.
+fn search_archives<F>(paths: Vec<PathBuf>, jobs: usize, search: F) -> u8
+where
+    F: Fn(&Path) -> io::Result<Found> + Send + Sync + 'static,
+{
+    let paths = match search_roots(paths) {
+        Ok(paths) => paths,
+        Err(path) => {
+            e_red!("❌ Path does not exist: {}\n", path.display());
+            return exit::USAGE;
+        }
+    };
+
+    let walk = walk(paths);
+    let matched = Arc::new(AtomicBool::new(false));
+    let failed = Arc::new(AtomicBool::new(walk.walk_errors > 0));
+
+    let (worker_matched, worker_failed) = (matched.clone(), failed.clone());
+
+    run_workers(walk.paths, false, jobs, move |path| match search(&path) {
+        Ok(found) => {
+            for skipped in &found.skipped {
+                worker_failed.store(true, Ordering::Relaxed);
+                e_yellow!("⚠️ Cannot search {}\n", skipped);
+            }
+
+            if !found.lines.is_empty() {
+                worker_matched.store(true, Ordering::Relaxed);
+
+                let mut stdout = io::stdout().lock();
+
+                for line in found.lines {
+                    let _ = writeln!(stdout, "{}", line);
+                }
+            }
+        }
+        Err(e) => {
+            worker_failed.store(true, Ordering::Relaxed);
+            e_red!("❌ Cannot search {}: {}\n", path.display(), e);
+        }
+    });
+
+    // Like grep: 0 when something matched, 1 when nothing did, 2 on errors
+    if failed.load(Ordering::Relaxed) && !matched.load(Ordering::Relaxed) {
+        exit::USAGE
+    } else if matched.load(Ordering::Relaxed) {
+        exit::SUCCESS
+    } else {
+        exit::NO_MATCH
+    }
+}
+
+fn open_archive(path: &Path) -> io::Result<ZipArchive<BufReader<File>>> {
+    Ok(ZipArchive::new(BufReader::new(File::open(path)?))?)
+}
+
+fn entry_names(path: &Path, matcher: &NameMatcher, encoding: NameEncoding) -> io::Result<Found> {
+    let archive = open_archive(path)?;
+    let (start, entries) = (archive.central_directory_start(), archive.len());
+    let headers = read_central_directory(&mut archive.into_inner(), start, entries)?;
+
+    Ok(Found {
+        lines: headers
+            .iter()
+            .map(|header| decode_name(&header.name, header.flags, &header.extra, encoding).text)
+            .filter(|name| matcher.is_match(name))
+            .map(|name| format!("{}!/{}", path.display(), name))
+            .collect(),
+        skipped: vec![],
+    })
+}
+
+/// Search the lines of one entry, skipping binary data unless `text` is set
+fn grep_entry<R: Read>(
+    entry: R,
+    location: &str,
+    regex: &BytesRegex,
+    text: bool,
+) -> io::Result<Vec<String>> {
+    let mut reader = BufReader::with_capacity(BINARY_SNIFF_SIZE, entry);
+
+    if !text && reader.fill_buf()?.contains(&0) {
+        return Ok(vec![]);
+    }
+
+    let mut lines = vec![];
+    let mut line = vec![];
+    let mut number = 0;
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        number += 1;
+
+        let content = line.strip_suffix(b"\n").unwrap_or(&line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+
+        if regex.is_match(content) {
+            lines.push(format!(
+                "{}:{}:{}",
+                location,
+                number,
+                String::from_utf8_lossy(content)
+            ));
+        }
+
+        line.clear();
+    }
+
+    Ok(lines)
+}
+
+fn grep_archive(
+    path: &Path,
+    names: &[NameMatcher],
+    regex: &BytesRegex,
+    text: bool,
+    encoding: NameEncoding,
+) -> io::Result<Found> {
+    let mut archive = open_archive(path)?;
+    let mut found = Found::default();
+    let mut deferred = vec![];
+
+    let is_wanted = |name: &str| names.is_empty() || names.iter().any(|m| m.is_match(name));
+
+    for i in 0..archive.len() {
+        match archive.by_index(i) {
+            Ok(entry) => {
+                let name = zip_file_name(&entry, encoding);
+
+                if !entry.is_dir() && is_wanted(&name) {
+                    let location = format!("{}!/{}", path.display(), name);
+
+                    found
+                        .lines
+                        .extend(grep_entry(entry, &location, regex, text)?);
+                }
+
+                continue;
+            }
+            // Encrypted, or in a compression method `ZipArchive` doesn't decode
+            Err(zip::result::ZipError::UnsupportedArchive(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let entry = archive.by_index_raw(i)?;
+        let name = zip_file_name(&entry, encoding);
+
+        if !entry.is_dir() && is_wanted(&name) {
+            deferred.push(DeferredEntry {
+                location: format!("{}!/{}", path.display(), name),
+                central_header_start: entry.central_header_start(),
+                data_start: entry.data_start(),
+                compressed_size: entry.compressed_size(),
+                size: entry.size(),
+            });
+        }
+    }
+
+    let mut reader = archive.into_inner();
+
+    for entry in deferred {
+        let header = central_header_at(&mut reader, entry.central_header_start)?;
+
+        if let Some(scheme) = Encryption::detect(header.flags, header.method, &header.extra) {
+            found.skipped.push(format!(
+                "{}: encrypted with {}",
+                entry.location,
+                scheme.name()
+            ));
+        } else if let Some(decoder) =
+            Decoder::for_method(header.method, header.flags, Some(entry.size))
+        {
+            // The decoders only write their output, so the entry is held in memory
+            let mut data = vec![];
+
+            reader.seek(SeekFrom::Start(entry.data_start))?;
+            decoder.decompress(
+                BufReader::new((&mut reader).take(entry.compressed_size)),
+                &mut data,
+                Some(entry.size),
+            )?;
+
+            found
+                .lines
+                .extend(grep_entry(data.as_slice(), &entry.location, regex, text)?);
+        } else {
+            found.skipped.push(format!(
+                "{}: {} compression is not supported",
+                entry.location,
+                method_name(header.method)
+            ));
+        }
+    }
+
+    Ok(found)
+}
+
+/// Run the `find` subcommand and return its exit code
+pub(crate) fn find(args: FindArgs, encoding: NameEncoding) -> u8 {
+    let matcher = if args.regex {
+        RegexBuilder::new(&args.pattern)
+            .case_insensitive(args.ignore_case)
+            .build()
+            .map(NameMatcher::Regex)
+            .map_err(|e| e.to_string())
+    } else {
+        NameMatcher::glob(&args.pattern, args.ignore_case).map_err(|e| e.to_string())
+    };
+
+    let matcher = match matcher {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            e_red!("❌ Invalid pattern: {}\n", e);
+            return exit::USAGE;
+        }
+    };
+
+    search_archives(args.paths, default_jobs(args.jobs), move |path| {
+        entry_names(path, &matcher, encoding)
+    })
+}
+
+/// Run the `grep` subcommand and return its exit code
+pub(crate) fn grep(args: GrepArgs, encoding: NameEncoding) -> u8 {
+    let pattern = if args.fixed_strings {
+        regex::escape(&args.pattern)
+    } else {
+        args.pattern.clone()
+    };
+
+    let regex = match regex::bytes::RegexBuilder::new(&pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+    {
+        Ok(regex) => regex,
+        Err(e) => {
+            e_red!("❌ Invalid pattern: {}\n", e);
+            return exit::USAGE;
+        }
+    };
+
+    let names: Result<Vec<NameMatcher>, _> = args
+        .names
+        .iter()
+        .map(|name| NameMatcher::glob(name, args.ignore_case))
+        .collect();
+
+    let names = match names {
+        Ok(names) => names,
+        Err(e) => {
+            e_red!("❌ Invalid --name glob: {}\n", e);
+            return exit::USAGE;
+        }
+    };
+
+    let text = args.text;
+
+    search_archives(args.paths, default_jobs(args.jobs), move |path| {
+        grep_archive(path, &names, &regex, text, encoding)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use zip::unstable::write::FileOptionsExt;
+    use zip::write::SimpleFileOptions;
+
+    fn create_archive(path: &Path) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("config/prod.yaml", options).unwrap();
+        zip.write_all(b"host: db\r\npassword: secret\n").unwrap();
+        zip.start_file("config/dev.yaml", options).unwrap();
+        zip.write_all(b"password: dev\n").unwrap();
+        zip.start_file("bin/tool", options).unwrap();
+        zip.write_all(b"\0\0password\0").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_glob_matches_name_or_path() {
+        let name = NameMatcher::glob("*.yaml", false).unwrap();
+        let path = NameMatcher::glob("config/prod.yaml", false).unwrap();
+        let deep = NameMatcher::glob("**/PROD.yaml", true).unwrap();
+
+        assert!(name.is_match("config/prod.yaml"));
+        assert!(!name.is_match("config/prod.yml"));
+        assert!(path.is_match("config/prod.yaml"));
+        assert!(!path.is_match("old/config/prod.yaml"));
+        assert!(deep.is_match("old/config/prod.yaml"));
+    }
+
+    #[test]
+    fn test_entry_names() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = temp_dir.path().join("a.zip");
+        create_archive(&archive);
+
+        let matcher = NameMatcher::glob("/config/prod.yaml", false).unwrap();
+
+        assert_eq!(
+            entry_names(&archive, &matcher, NameEncoding::Auto)
+                .unwrap()
+                .lines,
+            vec![format!("{}!/config/prod.yaml", archive.display())]
+        );
+    }
+
+    #[test]
+    fn test_grep_archive() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = temp_dir.path().join("a.zip");
+        create_archive(&archive);
+
+        let regex = BytesRegex::new("password").unwrap();
+        let prod = vec![NameMatcher::glob("prod.yaml", false).unwrap()];
+
+        assert_eq!(
+            grep_archive(&archive, &prod, &regex, false, NameEncoding::Auto)
+                .unwrap()
+                .lines,
+            vec![format!(
+                "{}!/config/prod.yaml:2:password: secret",
+                archive.display()
+            )]
+        );
+        // The binary entry is only searched with --text
+        assert_eq!(
+            grep_archive(&archive, &[], &regex, false, NameEncoding::Auto)
+                .unwrap()
+                .lines
+                .len(),
+            2
+        );
+        assert_eq!(
+            grep_archive(&archive, &[], &regex, true, NameEncoding::Auto)
+                .unwrap()
+                .lines
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_grep_archive_decodes_or_reports_unreadable_entries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let archive = temp_dir.path().join("a.zip");
+
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = SimpleFileOptions::default();
+
+        zip.start_file(
+            "notes.txt",
+            options.compression_method(zip::CompressionMethod::Zstd),
+        )
+        .unwrap();
+        zip.write_all(b"password: zstd\n").unwrap();
+        zip.start_file("secret.txt", options.with_deprecated_encryption(b"pw"))
+            .unwrap();
+        zip.write_all(b"password: hidden\n").unwrap();
+        zip.finish().unwrap();
+
+        // Zstandard under its deprecated method ID, which only our decoder reads
+        let mut data = fs::read(&archive).unwrap();
+        for (signature, offset) in [(b"PK\x03\x04", 8), (b"PK\x01\x02", 10)] {
+            let at = data.windows(4).position(|w| w == signature).unwrap();
+            data[at + offset..at + offset + 2].copy_from_slice(&20u16.to_le_bytes());
+        }
+        fs::write(&archive, data).unwrap();
+
+        let regex = BytesRegex::new("password").unwrap();
+        let found = grep_archive(&archive, &[], &regex, false, NameEncoding::Auto).unwrap();
+
+        assert_eq!(
+            found.lines,
+            vec![format!("{}!/notes.txt:1:password: zstd", archive.display())]
+        );
+        assert_eq!(
+            found.skipped,
+            vec![format!(
+                "{}!/secret.txt: encrypted with ZipCrypto",
+                archive.display()
+            )]
+        );
+    }
+}
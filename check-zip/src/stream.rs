@@ -0,0 +1,835 @@
+//! Validation of ZIP archives read sequentially, such as from a pipe.
+//!
+//! Entries are verified in the order their local file headers appear, without
+//! seeking. Once the central directory at the end of the stream is reached, it
+//! is cross-checked against the entries that were actually seen.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+
+use crate::decode::{Checksum, Decoder};
+use crate::error::{CheckError, ErrorKind};
+use crate::format::{
+    CENTRAL_DIRECTORY_HEADER_SIGNATURE, CentralDirectoryHeader, DATA_DESCRIPTOR_SIGNATURE,
+    DIGITAL_SIGNATURE_SIGNATURE, END_OF_CENTRAL_DIRECTORY_SIGNATURE, Encryption,
+    EndOfCentralDirectory, FLAG_DATA_DESCRIPTOR, LOCAL_FILE_HEADER_SIGNATURE, LocalFileHeader,
+    METHOD_STORED, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE,
+    ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE, read_u16, read_u32, read_u64,
+};
+use crate::names::{NameEncoding, decode_name};
+use crate::{Corruption, EncryptedEntries, UnsupportedEntries, ZipFileStatus};
+
+/// Reader that tracks how many bytes have been consumed
+struct CountingReader<R> {
+    inner: R,
+    position: u64,
+    /// Bytes read past the end of an entry, returned before those of `inner`
+    unread: Vec<u8>,
+}
+
+impl<R> CountingReader<R> {
+    /// Return bytes that were read too far, to be read again next
+    fn unread(&mut self, bytes: &[u8]) {
+        self.unread.splice(..0, bytes.iter().copied());
+        self.position -= bytes.len() as u64;
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = if self.unread.is_empty() {
+            self.inner.read(buf)?
+        } else {
+            let n = buf.len().min(self.unread.len());
+            buf[..n].copy_from_slice(&self.unread[..n]);
+            self.unread.drain(..n);
+            n
+        };
+
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.unread.is_empty() {
+            self.inner.fill_buf()
+        } else {
+            Ok(&self.unread)
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.unread.is_empty() {
+            self.inner.consume(amt);
+        } else {
+            self.unread.drain(..amt);
+        }
+
+        self.position += amt as u64;
+    }
+}
+
+/// Why walking the stream stopped early
+enum StreamError {
+    Corrupted(CheckError),
+    /// An encrypted entry of unknown length, after which nothing can be read
+    Encrypted,
+    /// An entry of unknown length in a compression method that can't be decoded here
+    Unsupported,
+}
+
+impl StreamError {
+    fn corrupted(kind: ErrorKind, message: String) -> Self {
+        StreamError::Corrupted(CheckError::new(kind, message))
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> Self {
+        let message = if e.kind() == io::ErrorKind::UnexpectedEof {
+            String::from("Unexpected end of stream")
+        } else {
+            format!("Cannot read stream: {}", e)
+        };
+
+        StreamError::corrupted(ErrorKind::of_io(&e), message)
+    }
+}
+
+/// Findings that don't stop the walk
+#[derive(Default)]
+struct Findings {
+    encrypted: EncryptedEntries,
+    unsupported: UnsupportedEntries,
+}
+
+/// An entry as it appeared in the stream, for the central directory cross-check
+struct LocalEntry {
+    name: Vec<u8>,
+    method: u16,
+    crc32: u32,
+    compressed_size: u64,
+    uncompressed_size: u64,
+}
+
+/// Validate a ZIP archive read from start to end without seeking
+pub(crate) fn check_zip_stream<R: BufRead>(reader: R, encoding: NameEncoding) -> ZipFileStatus {
+    let mut reader = CountingReader {
+        inner: reader,
+        position: 0,
+        unread: Vec::new(),
+    };
+
+    let mut findings = Findings::default();
+
+    match walk_stream(&mut reader, &mut findings, encoding) {
+        Ok(()) if !findings.encrypted.entries.is_empty() => {
+            ZipFileStatus::PasswordProtected(findings.encrypted)
+        }
+        Ok(()) if !findings.unsupported.entries.is_empty() => {
+            ZipFileStatus::Unsupported(findings.unsupported)
+        }
+        Ok(()) => ZipFileStatus::Valid(None),
+        Err(StreamError::Corrupted(error)) => ZipFileStatus::Corrupted(Corruption {
+            error,
+            entries: vec![],
+        }),
+        // Entries after it can't be found, so only those seen so far are counted
+        Err(StreamError::Encrypted) => ZipFileStatus::PasswordProtected(findings.encrypted),
+        Err(StreamError::Unsupported) => ZipFileStatus::Unsupported(findings.unsupported),
+    }
+}
+
+fn walk_stream<R: BufRead>(
+    reader: &mut CountingReader<R>,
+    findings: &mut Findings,
+    encoding: NameEncoding,
+) -> Result<(), StreamError> {
+    let mut entries = HashMap::<u64, LocalEntry>::new();
+
+    let mut offset = reader.position;
+    let mut signature = read_u32(reader)?;
+
+    while signature == LOCAL_FILE_HEADER_SIGNATURE {
+        let header = LocalFileHeader::read(reader)?;
+
+        findings.encrypted.total += 1;
+        findings.unsupported.total += 1;
+
+        let entry = check_entry(reader, &header, findings, encoding)?;
+
+        entries.insert(offset, entry);
+
+        offset = reader.position;
+        signature = read_u32(reader)?;
+    }
+
+    let central_directory_offset = offset;
+    let mut central_entries = 0u64;
+
+    while signature == CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+        let header = CentralDirectoryHeader::read(reader)?;
+
+        cross_check(&header, &entries, encoding)?;
+        central_entries += 1;
+
+        offset = reader.position;
+        signature = read_u32(reader)?;
+    }
+
+    if signature == DIGITAL_SIGNATURE_SIGNATURE {
+        let len = read_u16(reader)? as u64;
+        io::copy(&mut reader.take(len), &mut io::sink())?;
+
+        offset = reader.position;
+        signature = read_u32(reader)?;
+    }
+
+    let central_directory_size = offset - central_directory_offset;
+
+    let end = match signature {
+        ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE => {
+            let end = EndOfCentralDirectory::read_zip64(reader)?;
+
+            signature = read_u32(reader)?;
+
+            if signature == ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE {
+                let _disk = read_u32(reader)?;
+                let _zip64_end_offset = read_u64(reader)?;
+                let _disks = read_u32(reader)?;
+
+                signature = read_u32(reader)?;
+            }
+
+            if signature != END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+                return Err(unexpected_signature(signature, reader.position - 4));
+            }
+
+            // The classic record only holds placeholders once a Zip64 record exists
+            EndOfCentralDirectory::read(reader)?;
+
+            end
+        }
+        END_OF_CENTRAL_DIRECTORY_SIGNATURE => EndOfCentralDirectory::read(reader)?,
+        _ => return Err(unexpected_signature(signature, offset)),
+    };
+
+    if end.entries != central_entries {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadEocd,
+            format!(
+                "End of central directory lists {} entries, but the central directory has {}",
+                end.entries, central_entries
+            ),
+        ));
+    }
+
+    if central_entries != entries.len() as u64 {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadCentralHeader,
+            format!(
+                "Central directory lists {} entries, but the stream has {}",
+                central_entries,
+                entries.len()
+            ),
+        ));
+    }
+
+    if end.central_directory_offset != central_directory_offset
+        || end.central_directory_size != central_directory_size
+    {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadEocd,
+            format!(
+                "End of central directory places the central directory at {} ({} bytes), but it is at {} ({} bytes)",
+                end.central_directory_offset,
+                end.central_directory_size,
+                central_directory_offset,
+                central_directory_size
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn unexpected_signature(signature: u32, offset: u64) -> StreamError {
+    // Nothing at the start looks like an archive
+    let kind = if offset == 0 {
+        ErrorKind::NotAZip
+    } else {
+        ErrorKind::UnexpectedSignature
+    };
+
+    StreamError::corrupted(
+        kind,
+        format!(
+            "Unexpected signature 0x{:08x} at offset {}",
+            signature, offset
+        ),
+    )
+}
+
+/// Read the data of one entry and its data descriptor, verifying CRC and sizes
+fn check_entry<R: BufRead>(
+    reader: &mut CountingReader<R>,
+    header: &LocalFileHeader,
+    findings: &mut Findings,
+    encoding: NameEncoding,
+) -> Result<LocalEntry, StreamError> {
+    let name = decode_name(&header.name, header.flags, &header.extra, encoding).text;
+    let has_descriptor = header.flags & FLAG_DATA_DESCRIPTOR != 0;
+    // With a data descriptor the local header sizes are normally zero
+    let size_known = !has_descriptor || header.compressed_size != 0;
+
+    let start = reader.position;
+    let mut checksum = Checksum::default();
+    let mut verified = true;
+    let mut descriptor_read = false;
+    let mut entry = LocalEntry {
+        name: header.name.clone(),
+        method: header.method,
+        crc32: header.crc32,
+        compressed_size: header.compressed_size,
+        uncompressed_size: header.uncompressed_size,
+    };
+
+    if let Some(scheme) = Encryption::detect(header.flags, header.method, &header.extra) {
+        findings.encrypted.entries.push((name.to_string(), scheme));
+
+        if !size_known {
+            return Err(StreamError::Encrypted);
+        }
+
+        io::copy(&mut reader.take(header.compressed_size), &mut io::sink())?;
+        verified = false;
+    } else if header.method == METHOD_STORED && !size_known {
+        entry = read_stored_until_descriptor(reader, header, &mut checksum)?;
+        descriptor_read = true;
+    } else if let Some(decoder) = Decoder::for_method(
+        header.method,
+        header.flags,
+        size_known.then_some(header.uncompressed_size),
+    ) {
+        let result = if size_known {
+            decoder.decompress(
+                (&mut *reader).take(header.compressed_size),
+                &mut checksum,
+                Some(header.uncompressed_size),
+            )
+        } else {
+            decoder.decompress(&mut *reader, &mut checksum, None)
+        };
+
+        if let Err(e) = result {
+            return Err(StreamError::corrupted(
+                match ErrorKind::of_io(&e) {
+                    ErrorKind::TimedOut => ErrorKind::TimedOut,
+                    _ => ErrorKind::BadData,
+                },
+                format!("Cannot decompress {}: {}", name, e),
+            ));
+        }
+    } else {
+        findings
+            .unsupported
+            .entries
+            .push((name.to_string(), header.method));
+
+        if !size_known {
+            return Err(StreamError::Unsupported);
+        }
+
+        io::copy(&mut reader.take(header.compressed_size), &mut io::sink())?;
+        verified = false;
+    }
+
+    // The scan for a stored entry's descriptor has already consumed the descriptor
+    let compressed_size = if descriptor_read {
+        checksum.len
+    } else {
+        reader.position - start
+    };
+
+    if has_descriptor && !descriptor_read {
+        let mut crc32 = read_u32(reader)?;
+
+        // The descriptor signature is optional
+        if crc32 == DATA_DESCRIPTOR_SIGNATURE {
+            crc32 = read_u32(reader)?;
+        }
+
+        entry.crc32 = crc32;
+
+        if header.is_zip64() {
+            entry.compressed_size = read_u64(reader)?;
+            entry.uncompressed_size = read_u64(reader)?;
+        } else {
+            entry.compressed_size = read_u32(reader)? as u64;
+            entry.uncompressed_size = read_u32(reader)? as u64;
+        }
+    }
+
+    if compressed_size != entry.compressed_size {
+        return Err(StreamError::corrupted(
+            ErrorKind::SizeMismatch,
+            format!(
+                "Compressed size mismatch in {}: expected {}, read {}",
+                name, entry.compressed_size, compressed_size
+            ),
+        ));
+    }
+
+    if verified {
+        let crc32 = checksum.hasher.finalize();
+
+        if crc32 != entry.crc32 {
+            return Err(StreamError::corrupted(
+                ErrorKind::CrcMismatch,
+                format!(
+                    "CRC mismatch in {}: expected {:08x}, computed {:08x}",
+                    name, entry.crc32, crc32
+                ),
+            ));
+        }
+
+        if checksum.len != entry.uncompressed_size {
+            return Err(StreamError::corrupted(
+                ErrorKind::SizeMismatch,
+                format!(
+                    "Size mismatch in {}: expected {}, decompressed {}",
+                    name, entry.uncompressed_size, checksum.len
+                ),
+            ));
+        }
+    }
+
+    Ok(entry)
+}
+
+/// Read a stored entry of unknown length.
+///
+/// Stored data has no end marker, so the data is scanned for a data descriptor,
+/// with or without its signature, whose sizes match the number of bytes read
+/// so far and whose CRC matches the data.
+fn read_stored_until_descriptor<R: BufRead>(
+    reader: &mut CountingReader<R>,
+    header: &LocalFileHeader,
+    checksum: &mut Checksum,
+) -> Result<LocalEntry, StreamError> {
+    let size_len = if header.is_zip64() { 8 } else { 4 };
+    // An unsigned descriptor is followed by at least the next signature, so
+    // this much data decides whether a descriptor starts at a position
+    let window_len = 8 + 2 * size_len;
+    // Bytes read but not yet hashed, the tail of the previous chunk first
+    let mut data = Vec::new();
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let carried = data.len();
+        let read = buf.len();
+        data.extend_from_slice(buf);
+
+        let decidable = (data.len() + 1).saturating_sub(window_len);
+
+        match find_descriptor(&data, decidable, size_len, checksum) {
+            Some((start, descriptor)) => {
+                let end = start + descriptor.len;
+
+                // An unsigned descriptor can end before the bytes kept from
+                // the previous chunk, which then belong to the next record
+                if end < carried {
+                    reader.unread(&data[end..carried]);
+                } else {
+                    reader.consume(end - carried);
+                }
+
+                return Ok(LocalEntry {
+                    name: header.name.clone(),
+                    method: header.method,
+                    crc32: descriptor.crc32,
+                    compressed_size: checksum.len,
+                    uncompressed_size: checksum.len,
+                });
+            }
+            None => {
+                reader.consume(read);
+                data.drain(..decidable);
+            }
+        }
+    }
+}
+
+/// A data descriptor found after stored data
+struct Descriptor {
+    crc32: u32,
+    len: usize,
+}
+
+/// Find the first data descriptor starting before `end`, hashing the data in
+/// front of it. Without one, everything before `end` is hashed.
+fn find_descriptor(
+    data: &[u8],
+    end: usize,
+    size_len: usize,
+    checksum: &mut Checksum,
+) -> Option<(usize, Descriptor)> {
+    let base = checksum.len;
+    let mut hashed = 0;
+
+    let field = |at: usize| {
+        let mut bytes = [0u8; 8];
+        bytes[..size_len].copy_from_slice(&data[at..at + size_len]);
+        u64::from_le_bytes(bytes)
+    };
+    let sizes_match = |at: usize, len: u64| field(at) == len && field(at + size_len) == len;
+
+    for start in 0..end {
+        let len = base + start as u64;
+        let signed = data[start..start + 4] == DATA_DESCRIPTOR_SIGNATURE.to_le_bytes();
+
+        // The signature is optional, so the CRC comes first without it
+        let crc_at = if signed && sizes_match(start + 8, len) {
+            start + 4
+        } else if sizes_match(start + 4, len) {
+            start
+        } else {
+            continue;
+        };
+
+        checksum.hasher.update(&data[hashed..start]);
+        checksum.len = len;
+        hashed = start;
+
+        let crc32 = u32::from_le_bytes([
+            data[crc_at],
+            data[crc_at + 1],
+            data[crc_at + 2],
+            data[crc_at + 3],
+        ]);
+
+        if crc32 == checksum.hasher.clone().finalize() {
+            return Some((
+                start,
+                Descriptor {
+                    crc32,
+                    len: crc_at - start + 4 + 2 * size_len,
+                },
+            ));
+        }
+    }
+
+    checksum.hasher.update(&data[hashed..end]);
+    checksum.len = base + end as u64;
+
+    None
+}
+
+/// Compare a central directory header with the local entry it points to
+fn cross_check(
+    header: &CentralDirectoryHeader,
+    entries: &HashMap<u64, LocalEntry>,
+    encoding: NameEncoding,
+) -> Result<(), StreamError> {
+    let name = decode_name(&header.name, header.flags, &header.extra, encoding).text;
+
+    let Some(entry) = entries.get(&header.local_header_offset) else {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadCentralHeader,
+            format!(
+                "Central directory entry {} points to offset {}, where no local header was found",
+                name, header.local_header_offset
+            ),
+        ));
+    };
+
+    if entry.name != header.name {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadCentralHeader,
+            format!(
+                "Central directory entry {} points to local entry {}",
+                name,
+                decode_name(&entry.name, header.flags, &[], encoding).text
+            ),
+        ));
+    }
+
+    if entry.method != header.method {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadCentralHeader,
+            format!(
+                "Central directory disagrees with the local compression method of {}",
+                name
+            ),
+        ));
+    }
+
+    if entry.crc32 != header.crc32
+        || entry.compressed_size != header.compressed_size
+        || entry.uncompressed_size != header.uncompressed_size
+    {
+        return Err(StreamError::corrupted(
+            ErrorKind::BadCentralHeader,
+            format!(
+                "Central directory disagrees with the local CRC or sizes of {}",
+                name
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::METHOD_DEFLATED;
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use std::io::{BufReader, Cursor, Write};
+    use zip::CompressionMethod;
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    fn create_zip(method: CompressionMethod) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(method);
+
+        zip.start_file("test.txt", options).unwrap();
+        zip.write_all(b"Hello, World!").unwrap();
+        zip.add_directory("folder/", options).unwrap();
+        zip.start_file("folder/nested.txt", options).unwrap();
+        zip.write_all(&b"Nested content ".repeat(100)).unwrap();
+
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Archive written without seeking, so every entry has a data descriptor
+    fn create_streamed_zip(method: u16, signed: bool) -> Vec<u8> {
+        let files: [(&str, Vec<u8>); 3] = [
+            ("test.txt", b"Hello, World!".to_vec()),
+            ("empty.txt", Vec::new()),
+            ("folder/nested.txt", b"Nested content ".repeat(100)),
+        ];
+
+        let mut buf = Vec::new();
+        let mut central_directory = Vec::new();
+
+        for (name, content) in files {
+            let offset = buf.len() as u32;
+            let crc32 = crc32fast::hash(&content);
+            let data = if method == METHOD_DEFLATED {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&content).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                content.clone()
+            };
+
+            buf.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+            buf.extend_from_slice(&[20, 0]);
+            buf.extend_from_slice(&FLAG_DATA_DESCRIPTOR.to_le_bytes());
+            buf.extend_from_slice(&method.to_le_bytes());
+            buf.extend_from_slice(&[0, 0, 0x21, 0]);
+            buf.extend_from_slice(&[0; 12]);
+            buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&[0, 0]);
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(&data);
+            if signed {
+                buf.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+            }
+            buf.extend_from_slice(&crc32.to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(content.len() as u32).to_le_bytes());
+
+            central_directory.extend_from_slice(&CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes());
+            central_directory.extend_from_slice(&[20, 0, 20, 0]);
+            central_directory.extend_from_slice(&FLAG_DATA_DESCRIPTOR.to_le_bytes());
+            central_directory.extend_from_slice(&method.to_le_bytes());
+            central_directory.extend_from_slice(&[0, 0, 0x21, 0]);
+            central_directory.extend_from_slice(&crc32.to_le_bytes());
+            central_directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            central_directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central_directory.extend_from_slice(&[0; 12]);
+            central_directory.extend_from_slice(&offset.to_le_bytes());
+            central_directory.extend_from_slice(name.as_bytes());
+        }
+
+        let central_directory_offset = buf.len() as u32;
+
+        buf.extend_from_slice(&central_directory);
+        buf.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0, 3, 0, 3, 0]);
+        buf.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&central_directory_offset.to_le_bytes());
+        buf.extend_from_slice(&[0, 0]);
+
+        buf
+    }
+
+    #[test]
+    fn test_stream_valid_zip() {
+        for method in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let data = create_zip(method);
+
+            assert!(matches!(
+                check_zip_stream(Cursor::new(data), NameEncoding::Auto),
+                ZipFileStatus::Valid(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_stream_other_methods() {
+        for method in [
+            CompressionMethod::Bzip2,
+            CompressionMethod::Zstd,
+            CompressionMethod::Xz,
+        ] {
+            let data = create_zip(method);
+
+            assert!(matches!(
+                check_zip_stream(Cursor::new(data), NameEncoding::Auto),
+                ZipFileStatus::Valid(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_stream_with_data_descriptors() {
+        for method in [METHOD_STORED, METHOD_DEFLATED] {
+            for signed in [true, false] {
+                let data = create_streamed_zip(method, signed);
+
+                assert!(matches!(
+                    check_zip_stream(Cursor::new(data), NameEncoding::Auto),
+                    ZipFileStatus::Valid(_)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_descriptor_across_reads() {
+        // Small buffers split the descriptors of stored entries between reads
+        for capacity in [1, 5, 16, 23] {
+            for signed in [true, false] {
+                let data = create_streamed_zip(METHOD_STORED, signed);
+                let reader = BufReader::with_capacity(capacity, Cursor::new(data));
+
+                assert!(matches!(
+                    check_zip_stream(reader, NameEncoding::Auto),
+                    ZipFileStatus::Valid(_)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_stored_without_descriptor() {
+        let mut data = create_streamed_zip(METHOD_STORED, true);
+        // Truncate inside the data of the first entry
+        data.truncate(30 + "test.txt".len() + 5);
+
+        match check_zip_stream(Cursor::new(data), NameEncoding::Auto) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert_eq!(corruption.error.kind, ErrorKind::Truncated)
+            }
+            _ => panic!("Expected Corrupted status for a truncated stored entry"),
+        }
+    }
+
+    #[test]
+    fn test_stream_empty_zip() {
+        let data = ZipWriter::new(Cursor::new(Vec::new()))
+            .finish()
+            .unwrap()
+            .into_inner();
+
+        assert!(matches!(
+            check_zip_stream(Cursor::new(data), NameEncoding::Auto),
+            ZipFileStatus::Valid(_)
+        ));
+    }
+
+    #[test]
+    fn test_stream_crc_mismatch() {
+        let mut data = create_zip(CompressionMethod::Stored);
+        // First byte of "Hello, World!" after the 30 byte header and the name
+        data[30 + "test.txt".len()] ^= 0xFF;
+
+        match check_zip_stream(Cursor::new(data), NameEncoding::Auto) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("CRC mismatch"))
+            }
+            _ => panic!("Expected Corrupted status for CRC mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_stream_truncated() {
+        let mut data = create_zip(CompressionMethod::Deflated);
+        data.truncate(data.len() - 30);
+
+        match check_zip_stream(Cursor::new(data), NameEncoding::Auto) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(
+                    corruption
+                        .error
+                        .message
+                        .contains("Unexpected end of stream")
+                )
+            }
+            _ => panic!("Expected Corrupted status for truncated stream"),
+        }
+    }
+
+    #[test]
+    fn test_stream_reports_encryption_per_entry() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        zip.start_file("plain.txt", options).unwrap();
+        zip.write_all(b"Hello, World!").unwrap();
+        zip.start_file(
+            "secret.txt",
+            options.with_aes_encryption(zip::AesMode::Aes256, "pw"),
+        )
+        .unwrap();
+        zip.write_all(b"Secret").unwrap();
+
+        let data = zip.finish().unwrap().into_inner();
+
+        match check_zip_stream(Cursor::new(data), NameEncoding::Auto) {
+            ZipFileStatus::PasswordProtected(encrypted) => {
+                assert_eq!(encrypted.total, 2);
+                assert_eq!(
+                    encrypted.entries,
+                    vec![(String::from("secret.txt"), Encryption::Aes256)]
+                );
+            }
+            _ => panic!("Expected PasswordProtected status for an encrypted entry"),
+        }
+    }
+
+    #[test]
+    fn test_stream_not_a_zip() {
+        let data = b"This is not a ZIP file".to_vec();
+
+        match check_zip_stream(Cursor::new(data), NameEncoding::Auto) {
+            ZipFileStatus::Corrupted(corruption) => {
+                assert!(corruption.error.message.contains("Unexpected signature"));
+                assert_eq!(corruption.error.kind, ErrorKind::NotAZip);
+            }
+            _ => panic!("Expected Corrupted status for non-ZIP stream"),
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Read bandwidth shared by all workers.
+///
+/// Reads are paced on a virtual clock: every read pushes the clock forward by
+/// the time it should take at the configured rate, and readers sleep until the
+/// clock catches up with real time.
+pub(crate) struct Bandwidth {
+    bytes_per_sec: u64,
+    next: Mutex<Instant>,
+}
+
+impl Bandwidth {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn consume(&self, bytes: usize) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = (*next).max(now);
+
+            *next = start + cost;
+
+            start - now
+        }; // lock is released before sleeping
+
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Counting semaphore limiting how many workers read from disk at once
+pub(crate) struct IoLimiter {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+pub(crate) struct IoPermit<'a> {
+    limiter: &'a IoLimiter,
+}
+
+impl IoLimiter {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn acquire(&self) -> IoPermit<'_> {
+        let mut available = self.available.lock().unwrap();
+
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+
+        *available -= 1;
+
+        IoPermit { limiter: self }
+    }
+}
+
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.available.lock().unwrap() += 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+/// Reader that charges every read against an optional shared bandwidth
+pub(crate) struct ThrottledReader<R> {
+    inner: R,
+    bandwidth: Option<Arc<Bandwidth>>,
+}
+
+impl<R> ThrottledReader<R> {
+    pub(crate) fn new(inner: R, bandwidth: Option<Arc<Bandwidth>>) -> Self {
+        Self { inner, bandwidth }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        if let Some(bandwidth) = &self.bandwidth
+            && n > 0
+        {
+            bandwidth.consume(n);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for ThrottledReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+
+        if let Some(bandwidth) = &self.bandwidth
+            && amt > 0
+        {
+            bandwidth.consume(amt);
+        }
+    }
+}
+
+impl<R: Seek> Seek for ThrottledReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Reader that fails every read once a deadline has passed, so a check gives
+/// up between two reads instead of stalling its worker
+pub(crate) struct DeadlineReader<R> {
+    inner: R,
+    deadline: Option<Instant>,
+}
+
+impl<R> DeadlineReader<R> {
+    pub(crate) fn new(inner: R, deadline: Option<Instant>) -> Self {
+        Self { inner, deadline }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "per-file timeout exceeded",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<R: BufRead> BufRead for DeadlineReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: Seek> Seek for DeadlineReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.check()?;
+        self.inner.seek(pos)
+    }
+}
+
+/// Parse a bandwidth such as `500K`, `20M`, `1.5GiB` or `10MB/s` into bytes per second.
+///
+/// Suffixes are binary multiples, matching the units shown by the progress bar.
+pub(crate) fn parse_bandwidth(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+
+    parse_size(value.strip_suffix("/s").unwrap_or(value))
+        .map_err(|e| e.replace("size", "bandwidth"))
+}
+
+/// Parse a size such as `4096`, `500K`, `20M` or `1.5GiB` into bytes, with binary multiples.
+pub(crate) fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("iB")
+        .or_else(|| value.strip_suffix('B'))
+        .unwrap_or(value);
+
+    let (number, multiplier) = match value.chars().last() {
+        Some('K' | 'k') => (&value[..value.len() - 1], 1u64 << 10),
+        Some('M' | 'm') => (&value[..value.len() - 1], 1 << 20),
+        Some('G' | 'g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size: {}", value))?;
+
+    if !number.is_finite() || number <= 0.0 {
+        return Err(String::from("size must be greater than zero"));
+    }
+
+    Ok(((number * multiplier as f64) as u64).max(1))
+}
+
+/// Parse a timeout such as `90`, `30s`, `5m` or `1.5h` into a duration; plain numbers are seconds.
+pub(crate) fn parse_timeout(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1.0),
+        Some('m') => (&value[..value.len() - 1], 60.0),
+        Some('h') => (&value[..value.len() - 1], 3600.0),
+        _ => (value, 1.0),
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid timeout: {}", value))?;
+
+    if !number.is_finite() || number <= 0.0 {
+        return Err(String::from("timeout must be greater than zero"));
+    }
+
+    Ok(Duration::from_secs_f64(number * multiplier))
+}
+
+/// Identifier of the filesystem a file lives on
+#[cfg(unix)]
+fn filesystem_id(path: &Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    path.metadata().map(|m| m.dev()).unwrap_or(0)
+}
+
+/// Identifier of the filesystem a file lives on
+#[cfg(not(unix))]
+fn filesystem_id(path: &Path) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::path::Component;
+
+    // Without a portable device ID, fall back to the drive prefix
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => {
+            let mut hasher = DefaultHasher::new();
+            prefix.as_os_str().to_ascii_uppercase().hash(&mut hasher);
+            hasher.finish()
+        }
+        _ => 0,
+    }
+}
+
+/// Identifier of the disk holding a filesystem: partitions, device mapper
+/// volumes (LVM, LUKS) and btrfs subvolumes resolve to the disk beneath them
+#[cfg(target_os = "linux")]
+fn disk_id(filesystem: u64) -> u64 {
+    // Filesystems without a block device of their own, such as btrfs
+    // subvolumes, are found through the source of their mount
+    let device = if major(filesystem) == 0 {
+        std::fs::read_to_string("/proc/self/mountinfo")
+            .ok()
+            .and_then(|mountinfo| mount_source(&mountinfo, filesystem).map(str::to_string))
+            .and_then(|source| block_device(Path::new(&source)))
+            .unwrap_or(filesystem)
+    } else {
+        filesystem
+    };
+
+    parent_disk(Path::new("/sys/dev/block"), device).unwrap_or(device)
+}
+
+/// Identifier of the disk holding a filesystem, where disks can't be told apart
+#[cfg(not(target_os = "linux"))]
+fn disk_id(filesystem: u64) -> u64 {
+    filesystem
+}
+
+#[cfg(target_os = "linux")]
+fn major(device: u64) -> u64 {
+    ((device >> 8) & 0xfff) | ((device >> 32) & !0xfff)
+}
+
+#[cfg(target_os = "linux")]
+fn minor(device: u64) -> u64 {
+    (device & 0xff) | ((device >> 12) & !0xff)
+}
+
+#[cfg(target_os = "linux")]
+fn make_device(major: u64, minor: u64) -> u64 {
+    ((major & 0xfff) << 8) | ((major & !0xfff) << 32) | (minor & 0xff) | ((minor & !0xff) << 12)
+}
+
+/// Source of the mount of `device` in `/proc/self/mountinfo`, such as `/dev/sda2`
+#[cfg(target_os = "linux")]
+fn mount_source(mountinfo: &str, device: u64) -> Option<&str> {
+    let id = format!("{}:{}", major(device), minor(device));
+
+    mountinfo.lines().find_map(|line| {
+        let mut fields = line.split(' ');
+
+        if fields.nth(2)? != id {
+            return None;
+        }
+
+        // Optional fields end with a lone `-`, followed by the type and source
+        fields.find(|field| *field == "-")?;
+        fields.nth(1)
+    })
+}
+
+/// Device number of a block device file
+#[cfg(target_os = "linux")]
+fn block_device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let metadata = path.metadata().ok()?;
+
+    metadata
+        .file_type()
+        .is_block_device()
+        .then(|| metadata.rdev())
+}
+
+/// The whole disk beneath a block device, found in sysfs (`sys_block` is
+/// `/sys/dev/block`): a partition's parent, or the first device a device
+/// mapper or RAID volume is built on
+#[cfg(target_os = "linux")]
+fn parent_disk(sys_block: &Path, device: u64) -> Option<u64> {
+    let mut dir =
+        std::fs::canonicalize(sys_block.join(format!("{}:{}", major(device), minor(device))))
+            .ok()?;
+
+    // Stacked volumes are only a few levels deep; the bound guards against loops
+    for _ in 0..16 {
+        let mut slaves: Vec<PathBuf> = std::fs::read_dir(dir.join("slaves"))
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        slaves.sort();
+
+        if let Some(slave) = slaves.first() {
+            dir = std::fs::canonicalize(slave).ok()?;
+        } else if dir.join("partition").exists() {
+            dir = dir.parent()?.to_path_buf();
+        } else {
+            break;
+        }
+    }
+
+    let dev = std::fs::read_to_string(dir.join("dev")).ok()?;
+    let (major, minor) = dev.trim().split_once(':')?;
+
+    Some(make_device(major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Split paths into one queue per disk, so each disk gets its own worker
+pub(crate) fn group_by_device(paths: Vec<PathBuf>) -> Vec<Vec<PathBuf>> {
+    let mut disks = HashMap::<u64, u64>::new();
+    let mut groups = BTreeMap::<u64, Vec<PathBuf>>::new();
+
+    for path in paths {
+        let filesystem = filesystem_id(&path);
+        let disk = *disks
+            .entry(filesystem)
+            .or_insert_with(|| disk_id(filesystem));
+
+        groups.entry(disk).or_default().push(path);
+    }
+
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bandwidth() {
+        assert_eq!(parse_bandwidth("1024"), Ok(1024));
+        assert_eq!(parse_bandwidth("500K"), Ok(500 * 1024));
+        assert_eq!(parse_bandwidth("20M"), Ok(20 * 1024 * 1024));
+        assert_eq!(parse_bandwidth("10MB/s"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_bandwidth("1.5GiB"), Ok(3 * 512 * 1024 * 1024));
+        assert!(parse_bandwidth("fast").is_err());
+        assert!(parse_bandwidth("0").is_err());
+    }
+
+    #[test]
+    fn test_throttled_reader_paces_reads() {
+        let data = vec![0u8; 2048];
+        let bandwidth = Arc::new(Bandwidth::new(8192));
+        let mut reader = ThrottledReader::new(io::Cursor::new(data), Some(bandwidth));
+
+        let start = Instant::now();
+        let mut buf = [0u8; 1024];
+        reader.read_exact(&mut buf).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+
+        // The second KiB has to wait for the first one at 8 KiB/s
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_timeout("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_timeout("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_timeout("1.5h"), Ok(Duration::from_secs(5400)));
+        assert!(parse_timeout("soon").is_err());
+        assert!(parse_timeout("0").is_err());
+    }
+
+    #[test]
+    fn test_deadline_reader_fails_after_deadline() {
+        let mut reader = DeadlineReader::new(io::Cursor::new(vec![0u8; 16]), Some(Instant::now()));
+        let mut buf = [0u8; 8];
+
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let mut reader = DeadlineReader::new(io::Cursor::new(vec![0u8; 16]), None);
+        assert_eq!(reader.read(&mut buf).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_io_limiter_releases_permits() {
+        let limiter = IoLimiter::new(1);
+
+        drop(limiter.acquire());
+
+        let _permit = limiter.acquire();
+        assert_eq!(*limiter.available.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_group_by_device_keeps_all_paths() {
+        let paths = vec![PathBuf::from("a.zip"), PathBuf::from("b.zip")];
+
+        let groups = group_by_device(paths);
+
+        assert_eq!(groups.iter().map(Vec::len).sum::<usize>(), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_mount_source() {
+        let mountinfo = "\
+            29 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw\n\
+            41 29 0:45 /@home /home rw,relatime shared:5 master:2 - btrfs /dev/sdb1 rw\n";
+
+        assert_eq!(
+            mount_source(mountinfo, make_device(0, 45)),
+            Some("/dev/sdb1")
+        );
+        assert_eq!(
+            mount_source(mountinfo, make_device(8, 2)),
+            Some("/dev/sda2")
+        );
+        assert_eq!(mount_source(mountinfo, make_device(0, 46)), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parent_disk() {
+        use std::os::unix::fs::symlink;
+
+        // A sysfs tree with disk sda, its partitions and an LVM volume on sda2
+        let sys = tempfile::tempdir().unwrap();
+        let sda = sys.path().join("devices/sda");
+        let dm = sys.path().join("devices/dm-0");
+        let sys_block = sys.path().join("dev/block");
+
+        for (dir, dev) in [
+            (sda.clone(), "8:0"),
+            (sda.join("sda1"), "8:1"),
+            (sda.join("sda2"), "8:2"),
+            (dm.clone(), "253:0"),
+        ] {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("dev"), dev).unwrap();
+        }
+
+        std::fs::write(sda.join("sda1/partition"), "1").unwrap();
+        std::fs::write(sda.join("sda2/partition"), "2").unwrap();
+        std::fs::create_dir_all(dm.join("slaves")).unwrap();
+        symlink(sda.join("sda2"), dm.join("slaves/sda2")).unwrap();
+        std::fs::create_dir_all(&sys_block).unwrap();
+
+        for (link, dir) in [("8:0", &sda), ("8:1", &sda.join("sda1")), ("253:0", &dm)] {
+            symlink(dir, sys_block.join(link)).unwrap();
+        }
+
+        let disk = Some(make_device(8, 0));
+
+        assert_eq!(parent_disk(&sys_block, make_device(8, 0)), disk);
+        assert_eq!(parent_disk(&sys_block, make_device(8, 1)), disk);
+        assert_eq!(parent_disk(&sys_block, make_device(253, 0)), disk);
+        assert_eq!(parent_disk(&sys_block, make_device(9, 0)), None);
+        assert_eq!(major(make_device(259, 70000)), 259);
+        assert_eq!(minor(make_device(259, 70000)), 70000);
+    }
+}
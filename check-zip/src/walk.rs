@@ -0,0 +1,152 @@
+//! Finding archives below the given paths, and handing them to a pool of workers.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use colour::red;
+
+use crate::ZIP_EXTENSIONS;
+use crate::throttle::group_by_device;
+
+/// Archives found by `walk`
+pub(crate) struct Walk {
+    pub paths: Vec<PathBuf>,
+    pub total_bytes: u64,
+    /// Directories that could not be read
+    pub walk_errors: usize,
+}
+
+/// Collect the given files, and recursively find ZIP files in the given folders.
+///
+/// Files named explicitly are taken whatever their extension.
+pub(crate) fn walk(roots: Vec<PathBuf>) -> Walk {
+    let mut stack = vec![];
+    let mut walk = Walk {
+        paths: vec![],
+        total_bytes: 0,
+        walk_errors: 0,
+    };
+
+    for path in roots {
+        if path.is_dir() {
+            stack.push(path);
+        } else {
+            walk.total_bytes += path.metadata().map(|m| m.len()).unwrap_or(0);
+            walk.paths.push(path);
+        }
+    }
+
+    while let Some(current_path) = stack.pop() {
+        let ext = if let Some(ext) = current_path.extension() {
+            ext.to_str().unwrap_or("")
+        } else {
+            ""
+        };
+
+        if current_path.is_dir() {
+            match current_path.read_dir() {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        stack.push(entry.path());
+                    }
+                }
+                Err(e) => {
+                    walk.walk_errors += 1;
+                    red!("🚫 [UNREADABLE DIR] {} - {}\n", current_path.display(), e);
+                }
+            }
+        } else if ZIP_EXTENSIONS.contains(&ext) {
+            walk.total_bytes += current_path.metadata().map(|m| m.len()).unwrap_or(0);
+            walk.paths.push(current_path);
+        }
+    }
+
+    walk
+}
+
+/// Run `work` on every path, on `jobs` workers sharing one queue, or with
+/// `queue_per_device` on one worker per device, and wait for them to finish
+pub(crate) fn run_workers<F>(paths: Vec<PathBuf>, queue_per_device: bool, jobs: usize, work: F)
+where
+    F: Fn(PathBuf) + Send + Sync + 'static,
+{
+    // Workers sharing a queue share its Arc; with --per-device every device
+    // gets a queue and a worker of its own
+    let queues: Vec<Arc<Mutex<Vec<PathBuf>>>> = if queue_per_device {
+        group_by_device(paths)
+            .into_iter()
+            .map(|group| Arc::new(Mutex::new(group)))
+            .collect()
+    } else {
+        let queue = Arc::new(Mutex::new(paths));
+
+        (0..jobs).map(|_| queue.clone()).collect()
+    };
+
+    let work = Arc::new(work);
+    let mut children: Vec<JoinHandle<()>> = vec![];
+
+    for path_lock in queues {
+        let work = work.clone();
+
+        children.push(thread::spawn(move || {
+            loop {
+                let path_option = {
+                    let mut vec = path_lock.lock().unwrap();
+                    vec.pop()
+                };
+
+                match path_option {
+                    Some(path) => work(path),
+                    None => break,
+                }
+            }
+        }))
+    }
+
+    for child in children {
+        let id = child.thread().id();
+
+        child
+            .join()
+            .unwrap_or_else(|_| panic!("Failed to join thread: {:?}", id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_walk_finds_archives_and_explicit_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.zip"), b"zip").unwrap();
+        fs::write(dir.join("notes.txt"), b"text").unwrap();
+        fs::write(dir.join("b.bin"), b"bin").unwrap();
+
+        let mut walk = walk(vec![dir.to_path_buf(), dir.join("b.bin")]);
+        walk.paths.sort();
+
+        assert_eq!(walk.paths, vec![dir.join("b.bin"), dir.join("sub/a.zip")]);
+        assert_eq!(walk.total_bytes, 6);
+        assert_eq!(walk.walk_errors, 0);
+    }
+
+    #[test]
+    fn test_run_workers_visits_every_path() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let paths = (0..20)
+            .map(|i| PathBuf::from(format!("{}.zip", i)))
+            .collect();
+
+        let sink = seen.clone();
+        run_workers(paths, false, 4, move |path| sink.lock().unwrap().push(path));
+
+        assert_eq!(seen.lock().unwrap().len(), 20);
+    }
+}
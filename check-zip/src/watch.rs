@@ -0,0 +1,358 @@
+//! Watch mode: check archives as they land in a folder.
+//!
+//! Files are only checked once their writer is done with them: either the
+//! file was closed after writing, or its size stopped changing for the settle
+//! time. Platforms without close events rely on the settle time alone.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use colour::{red, yellow};
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::actions::handle_corrupted_now;
+use crate::cli::OnCorrupted;
+use crate::metrics::MetricsExport;
+use crate::{
+    CheckOptions, FileRecord, RunState, ZIP_EXTENSIONS, ZipFileStatus, check_zip_file,
+    print_status, record_status,
+};
+
+/// How often pending files are re-examined
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Pending {
+    size: Option<u64>,
+    changed: Instant,
+    closed: bool,
+}
+
+/// Archives that changed recently, waiting until their writer is done
+struct PendingFiles {
+    files: HashMap<PathBuf, Pending>,
+    settle: Duration,
+}
+
+impl PendingFiles {
+    fn new(settle: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            settle,
+        }
+    }
+
+    fn touch(&mut self, path: PathBuf, now: Instant) -> &mut Pending {
+        let pending = self.files.entry(path).or_insert(Pending {
+            size: None,
+            changed: now,
+            closed: false,
+        });
+
+        pending.changed = now;
+        pending.closed = false;
+
+        pending
+    }
+
+    fn close(&mut self, path: PathBuf, now: Instant) {
+        self.touch(path, now).closed = true;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Take the files that were closed after writing, or whose size has not
+    /// changed for the settle time. Files that disappeared are dropped.
+    fn take_ready(&mut self, now: Instant, size_of: impl Fn(&Path) -> Option<u64>) -> Vec<PathBuf> {
+        let settle = self.settle;
+        let mut ready = vec![];
+
+        self.files.retain(|path, pending| {
+            let size = size_of(path);
+
+            if size.is_none() {
+                return false;
+            }
+
+            if pending.closed {
+                ready.push(path.clone());
+                return false;
+            }
+
+            if size != pending.size {
+                pending.size = size;
+                pending.changed = now;
+                return true;
+            }
+
+            if now.duration_since(pending.changed) >= settle {
+                ready.push(path.clone());
+                return false;
+            }
+
+            true
+        });
+
+        ready
+    }
+}
+
+/// Resolve `path` to an absolute path without `.`, `..` or symlinks, through
+/// its parent folder when it no longer exists
+pub(crate) fn resolve(path: &Path) -> PathBuf {
+    path.canonicalize()
+        .or_else(|_| match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize().map(|parent| parent.join(name)),
+            _ => std::path::absolute(path),
+        })
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether `path` is an archive to check. Archives moved into the quarantine
+/// folder, given resolved, must not be checked again.
+fn is_watched_archive(path: &Path, quarantine_dir: Option<&Path>) -> bool {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+    ZIP_EXTENSIONS.contains(&ext)
+        && !quarantine_dir.is_some_and(|dir| resolve(path).starts_with(dir))
+}
+
+/// Settings of the watch loop
+pub(crate) struct WatchOptions {
+    pub settle: Duration,
+    pub jobs: usize,
+    pub on_corrupted: OnCorrupted,
+    pub quarantine_dir: Option<PathBuf>,
+    /// Metrics are rewritten whenever more archives have been checked
+    pub metrics: Option<MetricsExport>,
+}
+
+/// Watch `paths` recursively and check archives as they are written, until interrupted
+pub(crate) fn watch(
+    paths: &[PathBuf],
+    watch_options: WatchOptions,
+    options: &CheckOptions,
+    state: &RunState,
+) -> notify::Result<()> {
+    let (event_tx, event_rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(event_tx)?;
+
+    for path in paths {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+
+    let (work_tx, work_rx) = mpsc::channel::<PathBuf>();
+    let work_rx = Arc::new(Mutex::new(work_rx));
+    let quarantine_dir = watch_options.quarantine_dir.clone();
+    // Resolved once, as event paths are resolved before comparing with it
+    let resolved_quarantine_dir = quarantine_dir.as_deref().map(resolve);
+
+    for _ in 0..watch_options.jobs {
+        let work_rx = work_rx.clone();
+        let options = options.clone();
+        let state = state.clone();
+        let on_corrupted = watch_options.on_corrupted;
+        let quarantine_dir = quarantine_dir.clone();
+        let roots = paths.to_vec();
+
+        thread::spawn(move || {
+            loop {
+                // The lock is released as soon as a path is received
+                let path = match work_rx.lock().unwrap().recv() {
+                    Ok(path) => path,
+                    Err(_) => break,
+                };
+
+                let status = check_zip_file(&path, &options);
+
+                let log_line = {
+                    let mut result = state.result.lock().unwrap();
+                    result.total += 1;
+
+                    record_status(&mut result, &status, &path)
+                };
+
+                print_status(&status, &log_line);
+
+                state.log(&log_line);
+
+                if let ZipFileStatus::Corrupted(_) = status {
+                    handle_corrupted_now(&path, on_corrupted, quarantine_dir.as_deref());
+                }
+
+                let root = roots.iter().find(|root| path.starts_with(root));
+                let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+
+                state.records.lock().unwrap().push(FileRecord {
+                    root: root.unwrap_or(&path).clone(),
+                    path,
+                    size,
+                    status,
+                });
+            }
+        });
+    }
+
+    yellow!(
+        "👀 Watching {} for new and modified ZIP files (Ctrl-C to stop)...\n",
+        paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let is_archive = |path: &Path| is_watched_archive(path, resolved_quarantine_dir.as_deref());
+
+    let mut pending = PendingFiles::new(watch_options.settle);
+    let mut exported = None;
+
+    loop {
+        match event_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                let now = Instant::now();
+                let paths = event.paths.into_iter().filter(|p| is_archive(p));
+
+                match event.kind {
+                    EventKind::Create(_)
+                    | EventKind::Modify(
+                        ModifyKind::Any | ModifyKind::Data(_) | ModifyKind::Name(RenameMode::To),
+                    ) => paths.for_each(|path| {
+                        pending.touch(path, now);
+                    }),
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                        // Paths are [from, to]; either may have been filtered out
+                        for path in paths {
+                            if path.exists() {
+                                pending.touch(path, now);
+                            } else {
+                                pending.remove(&path);
+                            }
+                        }
+                    }
+                    EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(_) => {
+                        paths.for_each(|path| pending.remove(&path))
+                    }
+                    EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                        paths.for_each(|path| pending.close(path, now))
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Err(e)) => red!("❌ Watch error: {}\n", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let size_of = |path: &Path| {
+            path.metadata()
+                .ok()
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+        };
+
+        for path in pending.take_ready(Instant::now(), size_of) {
+            // Workers only stop once this sender is dropped
+            work_tx.send(path).expect("Watch workers stopped");
+        }
+
+        if let Some(metrics) = &watch_options.metrics {
+            let checked = state.records.lock().unwrap().len();
+
+            if exported != Some(checked) {
+                if let Err(e) = metrics.write(state) {
+                    red!("❌ Failed to write metrics file: {}\n", e);
+                }
+
+                exported = Some(checked);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_waits_for_size_to_settle() {
+        let start = Instant::now();
+        let mut pending = PendingFiles::new(Duration::from_secs(2));
+        let path = PathBuf::from("upload.zip");
+
+        pending.touch(path.clone(), start);
+
+        // First sighting records the size
+        assert!(pending.take_ready(start, |_| Some(100)).is_empty());
+        // Still growing
+        let later = start + Duration::from_secs(3);
+        assert!(pending.take_ready(later, |_| Some(200)).is_empty());
+        // Unchanged, but not for long enough
+        let soon = later + Duration::from_secs(1);
+        assert!(pending.take_ready(soon, |_| Some(200)).is_empty());
+        // Unchanged for the settle time
+        let settled = later + Duration::from_secs(2);
+        assert_eq!(pending.take_ready(settled, |_| Some(200)), vec![path]);
+        assert!(pending.files.is_empty());
+    }
+
+    #[test]
+    fn test_pending_closed_files_are_ready() {
+        let now = Instant::now();
+        let mut pending = PendingFiles::new(Duration::from_secs(60));
+        let path = PathBuf::from("upload.zip");
+
+        pending.touch(path.clone(), now);
+        pending.close(path.clone(), now);
+
+        assert_eq!(pending.take_ready(now, |_| Some(100)), vec![path]);
+    }
+
+    #[test]
+    fn test_quarantined_archives_are_not_watched() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let quarantine_dir = temp_dir.path().join("quarantine");
+        std::fs::create_dir(&quarantine_dir).unwrap();
+
+        // The same folders, relative to the working directory and through `.`
+        let up = std::env::current_dir().unwrap().components().count() - 1;
+        let relative = Path::new(&"../".repeat(up))
+            .join(temp_dir.path().strip_prefix("/").unwrap())
+            .join(".");
+        let resolved = resolve(&relative.join("quarantine"));
+
+        assert_eq!(resolved, quarantine_dir.canonicalize().unwrap());
+        assert!(!is_watched_archive(
+            &relative.join("quarantine/broken.zip"),
+            Some(&resolved)
+        ));
+        assert!(!is_watched_archive(
+            &quarantine_dir.join("other.jar"),
+            Some(&resolved)
+        ));
+        assert!(is_watched_archive(
+            &relative.join("upload.zip"),
+            Some(&resolved)
+        ));
+        assert!(!is_watched_archive(&relative.join("notes.txt"), None));
+    }
+
+    #[test]
+    fn test_pending_drops_removed_files() {
+        let now = Instant::now();
+        let mut pending = PendingFiles::new(Duration::ZERO);
+
+        pending.touch(PathBuf::from("gone.zip"), now);
+
+        assert!(pending.take_ready(now, |_| None).is_empty());
+        assert!(pending.files.is_empty());
+    }
+}